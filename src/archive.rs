@@ -0,0 +1,667 @@
+//! The archive module packages the outputs of a Job into a downloadable zip \
+//! archive, so the frontend can serve a ready-made file instead of zipping \
+//! `paths.frames` on the fly for each download.
+//!
+//! The [Archive](struct.Archive.html) is a zip file without compression \
+//! (rendered images are already compressed or huge EXRs) that can be \
+//! extended in place: new files are appended and the central directory is \
+//! rewritten after them. This allows the [Archiver](trait.Archiver.html) to \
+//! build the archive incrementally as frames arrive. Finalizing the archive \
+//! adds the [manifest](../manifest/index.html) and a short render report and \
+//! records the size and hash of the archive in the data of the Job:
+//! - `archive.path`: the path of the archive
+//! - `archive.files`: the number of files in the archive
+//! - `archive.size`: the size of the archive in bytes
+//! - `archive.hash`: the Blake2b hash of the archive (only once finalized)
+//! - `archive.complete`: `"true"` once finalized
+//!
+//! The archive is a plain zip without ZIP64 extensions. It therefore holds at \
+//! most 65535 files and can't grow beyond 4 GiB, adding more files returns \
+//! a Error and leaves the archive as it was.
+//!
+//! ```no_run
+//! # extern crate bender_job;
+//! # use bender_job::{Job, Archiver};
+//! let mut job = Job::from_datajson("/data/blendfiles/5873c0033e78b222bec2cb2a221487cf/data.json").unwrap();
+//! // Whenever a frame arrived
+//! job.update_archive().unwrap();
+//! // When the Job finished
+//! job.finalize_archive().unwrap();
+//! if let Some(path) = job.ready_archive(){
+//!     println!("Serve {:?}", path);
+//! }
+//! ```
+
+use ::*;
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::path::Path;
+use completeness::Completeness;
+use manifest::{hash_file, Manifest, MANIFEST_FILENAME};
+
+
+/// The filename of the render report within the archive
+pub const REPORT_FILENAME: &str = "report.txt";
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const LOCAL_HEADER_SIZE: u64 = 30;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const END_OF_CENTRAL_DIRECTORY_SIZE: u64 = 22;
+/// Version 2.0 (needed for directories in names)
+const ZIP_VERSION: u16 = 20;
+/// General purpose flag: names are UTF-8
+const FLAG_UTF8: u16 = 0x0800;
+/// The number of entries and the length of names are 16 bit fields
+const MAX_ENTRIES: usize = u16::MAX as usize;
+
+
+
+
+// ===========================================================================
+//                                 Archive
+// ===========================================================================
+
+/// A file stored in the Archive
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveEntry{
+    pub name: String,
+    pub crc32: u32,
+    pub size: u32,
+    offset: u32,
+    time: u16,
+    date: u16
+}
+
+impl ArchiveEntry{
+    /// Return true if the file still has the archived content. Files of the \
+    /// same size are only read (to compare the CRC) if they were modified \
+    /// after they were archived
+    pub fn matches_file<P>(&self, path: P) -> bool where P: AsRef<Path>{
+        let metadata = match fs::metadata(path.as_ref()){
+            Ok(metadata) => metadata,
+            Err(_) => return false
+        };
+        if metadata.len() != u64::from(self.size){
+            return false;
+        }
+        if let Ok(modified) = metadata.modified(){
+            let (time, date) = dos_timestamp(DateTime::<Local>::from(modified));
+            if (date, time) < (self.date, self.time){
+                return true;
+            }
+        }
+        file_crc32(path.as_ref()).ok() == Some(self.crc32)
+    }
+}
+
+
+/// An uncompressed zip archive on disk that can be extended in place
+#[derive(Debug, Clone, PartialEq)]
+pub struct Archive{
+    path: PathBuf,
+    entries: Vec<ArchiveEntry>,
+    /// Where the data ends and the central directory starts
+    data_end: u32
+}
+
+impl Archive{
+    /// Create a new empty archive at path, overwriting existing files
+    pub fn create<P>(path: P) -> GenResult<Self> where P: AsRef<Path>{
+        let path = path.as_ref();
+        if let Some(parent) = path.parent(){
+            fs::create_dir_all(parent)?;
+        }
+        let archive = Archive{
+            path: path.to_path_buf(),
+            entries: Vec::new(),
+            data_end: 0
+        };
+        let mut file = fs::File::create(path)?;
+        archive.write_central_directory(&mut file)?;
+        Ok(archive)
+    }
+
+    /// Open an existing archive written by Archive
+    pub fn open<P>(path: P) -> GenResult<Self> where P: AsRef<Path>{
+        let path = path.as_ref();
+        let mut file = fs::File::open(path)?;
+        let length = file.metadata()?.len();
+        if length < END_OF_CENTRAL_DIRECTORY_SIZE{
+            return Err(From::from(format!("Error: {} is too short to be a zip archive", path.display())));
+        }
+        let mut eocd = [0; END_OF_CENTRAL_DIRECTORY_SIZE as usize];
+        file.seek(SeekFrom::Start(length - END_OF_CENTRAL_DIRECTORY_SIZE))?;
+        file.read_exact(&mut eocd)?;
+        if read_u32(&eocd, 0) != END_OF_CENTRAL_DIRECTORY{
+            return Err(From::from(format!("Error: {} has no end of central directory (not written by bender?)", path.display())));
+        }
+        let count = read_u16(&eocd, 10) as usize;
+        let directory_size = read_u32(&eocd, 12);
+        let directory_offset = read_u32(&eocd, 16);
+        if u64::from(directory_offset) + u64::from(directory_size) + END_OF_CENTRAL_DIRECTORY_SIZE != length{
+            return Err(From::from(format!("Error: the central directory of {} is corrupt", path.display())));
+        }
+
+        let mut directory = vec![0; directory_size as usize];
+        file.seek(SeekFrom::Start(u64::from(directory_offset)))?;
+        file.read_exact(&mut directory)?;
+        let mut entries = Vec::with_capacity(count);
+        let mut position = 0;
+        for _ in 0..count{
+            if position + CENTRAL_HEADER_SIZE > directory.len() || read_u32(&directory, position) != CENTRAL_HEADER{
+                return Err(From::from(format!("Error: the central directory of {} is corrupt", path.display())));
+            }
+            let header = &directory[position..];
+            let name_length = read_u16(header, 28) as usize;
+            let extra_length = read_u16(header, 30) as usize + read_u16(header, 32) as usize;
+            let name = header.get(CENTRAL_HEADER_SIZE..CENTRAL_HEADER_SIZE + name_length)
+                             .ok_or_else(|| format!("Error: the central directory of {} is corrupt", path.display()))?;
+            entries.push(ArchiveEntry{
+                name: String::from_utf8_lossy(name).to_string(),
+                crc32: read_u32(header, 16),
+                size: read_u32(header, 24),
+                offset: read_u32(header, 42),
+                time: read_u16(header, 12),
+                date: read_u16(header, 14)
+            });
+            position += CENTRAL_HEADER_SIZE + name_length + extra_length;
+        }
+        Ok(Archive{
+            path: path.to_path_buf(),
+            entries,
+            data_end: directory_offset
+        })
+    }
+
+    /// Open the archive at path. Create a new one if it doesn't exist or \
+    /// can't be read (e.g. because writing it was interrupted)
+    pub fn open_or_create<P>(path: P) -> GenResult<Self> where P: AsRef<Path>{
+        match Self::open(path.as_ref()){
+            Ok(archive) => Ok(archive),
+            Err(_) => Self::create(path)
+        }
+    }
+
+    /// The path of the archive
+    pub fn path(&self) -> &Path{
+        &self.path
+    }
+
+    /// The files in the archive, in the order they were added
+    pub fn entries(&self) -> &[ArchiveEntry]{
+        &self.entries
+    }
+
+    /// Return true if a file with that name is in the archive
+    pub fn contains(&self, name: &str) -> bool{
+        self.entries.iter().any(|entry| entry.name == name)
+    }
+
+    /// Add a file from disk under the given name
+    pub fn add_file<S, P>(&mut self, name: S, path: P) -> GenResult<()> where S: Into<String>, P: AsRef<Path>{
+        let file = fs::File::open(path)?;
+        self.add(name, file)
+    }
+
+    /// Add bytes as a file with the given name
+    pub fn add_bytes<S>(&mut self, name: S, bytes: &[u8]) -> GenResult<()> where S: Into<String>{
+        self.add(name, bytes)
+    }
+
+    /// Add everything read from the reader as a file with the given name
+    pub fn add<S, R>(&mut self, name: S, mut reader: R) -> GenResult<()> where S: Into<String>, R: Read{
+        let name = name.into();
+        if self.contains(&name){
+            return Err(From::from(format!("Error: {} is already in the archive", name)));
+        }
+        if self.entries.len() >= MAX_ENTRIES{
+            return Err(From::from(format!("Error: adding {} would exceed the maximum number of files in a zip archive ({})", name, MAX_ENTRIES)));
+        }
+        if name.len() > usize::from(u16::MAX){
+            return Err(From::from(format!("Error: the name of {}... is too long for a zip archive", name.chars().take(32).collect::<String>())));
+        }
+        let (time, date) = dos_timestamp(Local::now());
+        let mut file = fs::OpenOptions::new().read(true).write(true).open(&self.path)?;
+        let offset = self.data_end;
+
+        // Write the header with a placeholder for crc and size, which are
+        // known only after the data has been written
+        file.seek(SeekFrom::Start(u64::from(offset)))?;
+        let mut entry = ArchiveEntry{ name, crc32: 0, size: 0, offset, time, date };
+        file.write_all(&local_header(&entry))?;
+
+        let table = crc32_table();
+        let mut crc = !0u32;
+        let mut size: u64 = 0;
+        let mut buffer = vec![0; 64 * 1024];
+        loop{
+            let n = reader.read(&mut buffer)?;
+            if n == 0{ break; }
+            crc = crc32_update(&table, crc, &buffer[..n]);
+            size += n as u64;
+            file.write_all(&buffer[..n])?;
+        }
+        let data_end = u64::from(offset) + LOCAL_HEADER_SIZE + entry.name.len() as u64 + size;
+        if data_end > u64::from(u32::MAX) - (1 << 24){
+            // Leave the archive as it was
+            self.write_central_directory(&mut file)?;
+            return Err(From::from(format!("Error: adding {} would exceed the maximum size of a zip archive (4 GiB)", entry.name)));
+        }
+        entry.crc32 = !crc;
+        entry.size = size as u32;
+        file.seek(SeekFrom::Start(u64::from(offset)))?;
+        file.write_all(&local_header(&entry))?;
+
+        self.entries.push(entry);
+        self.data_end = data_end as u32;
+        self.write_central_directory(&mut file)
+    }
+
+    /// Remove every file after the first `len` files
+    pub fn truncate(&mut self, len: usize) -> GenResult<()>{
+        if len >= self.entries.len(){
+            return Ok(());
+        }
+        self.data_end = self.entries[len].offset;
+        self.entries.truncate(len);
+        let mut file = fs::OpenOptions::new().write(true).open(&self.path)?;
+        self.write_central_directory(&mut file)
+    }
+
+    /// The size of the archive in bytes
+    pub fn size(&self) -> u64{
+        u64::from(self.data_end)
+        + self.entries.iter().map(|e| (CENTRAL_HEADER_SIZE + e.name.len()) as u64).sum::<u64>()
+        + END_OF_CENTRAL_DIRECTORY_SIZE
+    }
+
+    /// Write central directory and end of central directory after the data \
+    /// and cut off anything behind it
+    fn write_central_directory(&self, file: &mut fs::File) -> GenResult<()>{
+        let mut directory = Vec::new();
+        for entry in self.entries.iter(){
+            directory.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+            directory.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+            directory.extend_from_slice(&entry_fields(entry));
+            directory.extend_from_slice(&0u16.to_le_bytes()); // comment length
+            directory.extend_from_slice(&0u16.to_le_bytes()); // disk number
+            directory.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+            directory.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+            directory.extend_from_slice(&entry.offset.to_le_bytes());
+            directory.extend_from_slice(entry.name.as_bytes());
+        }
+        let count = self.entries.len() as u16;
+        let mut eocd = Vec::with_capacity(END_OF_CENTRAL_DIRECTORY_SIZE as usize);
+        eocd.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        eocd.extend_from_slice(&0u16.to_le_bytes()); // this disk
+        eocd.extend_from_slice(&0u16.to_le_bytes()); // disk with the directory
+        eocd.extend_from_slice(&count.to_le_bytes());
+        eocd.extend_from_slice(&count.to_le_bytes());
+        eocd.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        eocd.extend_from_slice(&self.data_end.to_le_bytes());
+        eocd.extend_from_slice(&0u16.to_le_bytes()); // comment length
+
+        file.seek(SeekFrom::Start(u64::from(self.data_end)))?;
+        file.write_all(&directory)?;
+        file.write_all(&eocd)?;
+        file.set_len(self.size())?;
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+
+/// The local file header of an entry
+fn local_header(entry: &ArchiveEntry) -> Vec<u8>{
+    let mut header = Vec::with_capacity(LOCAL_HEADER_SIZE as usize + entry.name.len());
+    header.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+    header.extend_from_slice(&entry_fields(entry));
+    header.extend_from_slice(entry.name.as_bytes());
+    header
+}
+
+
+/// The fields local and central header share: version needed, flags, \
+/// method, time, date, crc, sizes, name length and extra length
+fn entry_fields(entry: &ArchiveEntry) -> Vec<u8>{
+    let mut fields = Vec::with_capacity(26);
+    fields.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+    fields.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+    fields.extend_from_slice(&0u16.to_le_bytes()); // stored, no compression
+    fields.extend_from_slice(&entry.time.to_le_bytes());
+    fields.extend_from_slice(&entry.date.to_le_bytes());
+    fields.extend_from_slice(&entry.crc32.to_le_bytes());
+    fields.extend_from_slice(&entry.size.to_le_bytes()); // compressed
+    fields.extend_from_slice(&entry.size.to_le_bytes()); // uncompressed
+    fields.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+    fields.extend_from_slice(&0u16.to_le_bytes()); // extra length
+    fields
+}
+
+
+fn read_u16(bytes: &[u8], position: usize) -> u16{
+    u16::from(bytes[position]) | u16::from(bytes[position + 1]) << 8
+}
+
+
+fn read_u32(bytes: &[u8], position: usize) -> u32{
+    u32::from(read_u16(bytes, position)) | u32::from(read_u16(bytes, position + 2)) << 16
+}
+
+
+/// Compute the CRC-32 of a file
+fn file_crc32(path: &Path) -> GenResult<u32>{
+    let mut file = fs::File::open(path)?;
+    let table = crc32_table();
+    let mut crc = !0u32;
+    let mut buffer = vec![0; 64 * 1024];
+    loop{
+        let n = file.read(&mut buffer)?;
+        if n == 0{ break; }
+        crc = crc32_update(&table, crc, &buffer[..n]);
+    }
+    Ok(!crc)
+}
+
+
+/// The local time as MS-DOS time and date
+fn dos_timestamp(now: DateTime<Local>) -> (u16, u16){
+    let time = ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16;
+    let year = (now.year() - 1980).max(0) as u32;
+    let date = ((year << 9) | (now.month() << 5) | now.day()) as u16;
+    (time, date)
+}
+
+
+/// The lookup table for the CRC-32 used by zip (polynomial 0xEDB88320)
+fn crc32_table() -> [u32; 256]{
+    let mut table = [0; 256];
+    for (i, value) in table.iter_mut().enumerate(){
+        let mut c = i as u32;
+        for _ in 0..8{
+            c = if c & 1 == 1 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+        }
+        *value = c;
+    }
+    table
+}
+
+
+/// Update a running (inverted) CRC-32 with the bytes
+fn crc32_update(table: &[u32; 256], crc: u32, bytes: &[u8]) -> u32{
+    bytes.iter().fold(crc, |crc, byte| table[((crc ^ u32::from(*byte)) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+
+/// Return the CRC-32 of the bytes
+pub fn crc32(bytes: &[u8]) -> u32{
+    !crc32_update(&crc32_table(), !0, bytes)
+}
+
+
+
+
+// ===========================================================================
+//                                 Archiver
+// ===========================================================================
+
+/// This Trait is implemented by a [Job](struct.Job.html) and builds the \
+/// downloadable archive of its frames.
+pub trait Archiver{
+    fn archive_path(&self) -> PathBuf;
+    fn update_archive(&mut self) -> GenResult<usize>;
+    fn finalize_archive(&mut self) -> GenResult<()>;
+    fn ready_archive(&self) -> Option<PathBuf>;
+    fn render_report(&self) -> String;
+}
+
+impl Archiver for Job{
+    /// The archive is stored next to the frames directory as `<id>.zip`
+    fn archive_path(&self) -> PathBuf{
+        PathBuf::from(format!("{}.zip", self.paths.frames.trim_end_matches('/')))
+    }
+
+    /// Add every file of the expected frames that exists in `paths.frames` \
+    /// and isn't archived yet. Files that changed since they were archived \
+    /// (e.g. re-rendered corrupt frames) are written again together with all \
+    /// files after them. Return the number of added files. Adding files to a \
+    /// finalized archive removes manifest and report again.
+    fn update_archive(&mut self) -> GenResult<usize>{
+        let frames = PathBuf::from(&self.paths.frames);
+        let mut files = Vec::new();
+        for framenumber in self.expected_frames(){
+            for path in self.frame_paths(framenumber).into_iter().filter(|path| path.is_file()){
+                let name = path.strip_prefix(&frames).unwrap_or(&path).to_string_lossy().to_string();
+                files.push((name, path));
+            }
+        }
+
+        let mut archive = Archive::open_or_create(self.archive_path())?;
+        let stale = archive.entries().iter().position(|entry|{
+            files.iter().any(|(name, path)| *name == entry.name && !entry.matches_file(path))
+        });
+        if let Some(position) = stale{
+            archive.truncate(position)?;
+        }
+        let new_files: Vec<_> = files.into_iter().filter(|(name, _)| !archive.contains(name)).collect();
+        if !new_files.is_empty(){
+            if let Some(position) = archive.entries().iter().position(|e| e.name == MANIFEST_FILENAME){
+                archive.truncate(position)?;
+            }
+            for (name, path) in new_files.iter(){
+                archive.add_file(name.as_str(), path)?;
+            }
+        }
+        self.record_archive(&archive, false);
+        Ok(new_files.len())
+    }
+
+    /// Add all remaining frames, the manifest and the render report, then \
+    /// record size and hash of the archive
+    fn finalize_archive(&mut self) -> GenResult<()>{
+        self.update_archive()?;
+        let mut archive = Archive::open(self.archive_path())?;
+        if let Some(position) = archive.entries().iter().position(|e| e.name == MANIFEST_FILENAME){
+            archive.truncate(position)?;
+        }
+        let manifest = Manifest::from_job(self);
+        manifest.write_to(&self.paths.frames)?;
+        archive.add_bytes(MANIFEST_FILENAME, serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+        archive.add_bytes(REPORT_FILENAME, self.render_report().as_bytes())?;
+        self.record_archive(&archive, true);
+        let message = format!("Packaged {} files into {}", archive.entries().len(), archive.path().display());
+        self.add_history(message.as_str());
+        Ok(())
+    }
+
+    /// Return the path of the archive if it has been finalized and is still \
+    /// the file that was recorded
+    fn ready_archive(&self) -> Option<PathBuf>{
+        if self.data.get("archive.complete").map(|s| s.as_str()) != Some("true"){
+            return None;
+        }
+        let path = self.archive_path();
+        let size = fs::metadata(&path).ok()?.len();
+        match self.data.get("archive.size").and_then(|s| s.parse::<u64>().ok()){
+            Some(recorded) if recorded == size => Some(path),
+            _ => None
+        }
+    }
+
+    /// The [JobReport](../report/struct.JobReport.html) as text, extended \
+    /// by the frames actually found on disk
+    fn render_report(&self) -> String{
+        let check = self.check_frames();
+        let size: u64 = check.rendered.iter()
+                              .flat_map(|framenumber| self.frame_paths(*framenumber))
+                              .filter_map(|path| fs::metadata(path).ok())
+                              .map(|metadata| metadata.len())
+                              .sum();
+        let mut report = self.report().to_text();
+        report.push_str(&format!("On disk:      {} ({} bytes)\n", check, size));
+        if !check.missing.is_empty(){
+            report.push_str(&format!("Missing:      {:?}\n", check.missing));
+        }
+        report.push_str(&format!("Packaged:     {}\n", Utc::now().to_rfc3339()));
+        report
+    }
+}
+
+
+impl Job{
+    /// Record path, size, number of files and (if complete) the hash of the \
+    /// archive in the data of the Job
+    fn record_archive(&mut self, archive: &Archive, complete: bool){
+        self.add_data("archive.path".to_string(), archive.path().to_string_lossy().to_string());
+        self.add_data("archive.files".to_string(), archive.entries().len().to_string());
+        self.add_data("archive.size".to_string(), archive.size().to_string());
+        self.add_data("archive.complete".to_string(), complete.to_string());
+        let hash = match complete{
+            true => hash_file(archive.path()).ok().map(|(_, hash)| hash),
+            false => None
+        };
+        match hash{
+            Some(hash) => self.add_data("archive.hash".to_string(), hash),
+            None => { self.data.remove("archive.hash"); }
+        }
+    }
+}
+
+
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atomizer::Atomizer;
+    use common::tempfile::TempDir;
+    use common::JobBuilder;
+
+    #[test]
+    fn checksum() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn incremental() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("archive.zip");
+        let mut archive = Archive::create(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 22);
+        archive.add_bytes("000001.png", b"first").unwrap();
+        archive.add_bytes("Out/beauty0001.png", b"second").unwrap();
+        assert!(archive.add_bytes("000001.png", b"again").is_err());
+        assert_eq!(fs::metadata(&path).unwrap().len(), archive.size());
+
+        let mut reopened = Archive::open(&path).unwrap();
+        assert_eq!(reopened, archive);
+        assert_eq!(reopened.entries()[1].crc32, crc32(b"second"));
+        reopened.add_bytes("report.txt", b"third").unwrap();
+        reopened.truncate(2).unwrap();
+        assert_eq!(Archive::open(&path).unwrap(), archive);
+
+        // The data of every entry follows its local header
+        let bytes = fs::read(&path).unwrap();
+        let entry = &archive.entries()[1];
+        let start = entry.offset as usize + 30 + entry.name.len();
+        assert_eq!(&bytes[entry.offset as usize..entry.offset as usize + 4], &[0x50, 0x4b, 0x03, 0x04]);
+        assert_eq!(&bytes[start..start + 6], b"second");
+
+        // The zip format has no room for more entries or longer names
+        assert!(archive.add_bytes("x".repeat(70_000), b"long").is_err());
+        let entry = archive.entries()[0].clone();
+        archive.entries = vec![entry; MAX_ENTRIES];
+        assert!(archive.add_bytes("000003.png", b"too many").is_err());
+
+        fs::write(&path, b"garbage").unwrap();
+        assert!(Archive::open(&path).is_err());
+        assert!(Archive::open_or_create(&path).unwrap().entries().is_empty());
+    }
+
+    #[test]
+    fn archiver() {
+        let dir = TempDir::new().unwrap();
+        let mut job = JobBuilder::new().in_directory(dir.path().join("blendfiles"))
+                                       .with_image_format("PNG")
+                                       .with_frames(1, 3)
+                                       .build();
+        job.tasks = job.generate_commands(1);
+        let frames = PathBuf::from(&job.paths.frames);
+        fs::create_dir_all(&frames).unwrap();
+        assert_eq!(job.archive_path(), dir.path().join("blendfiles/frames/5873c0033e78b222bec2cb2a221487cf.zip"));
+
+        fs::write(frames.join("000001.png"), "one").unwrap();
+        assert_eq!(job.update_archive().unwrap(), 1);
+        assert_eq!(job.update_archive().unwrap(), 0);
+        assert_eq!(job.data["archive.files"], "1");
+        assert_eq!(job.ready_archive(), None);
+
+        fs::write(frames.join("000002.png"), "two").unwrap();
+        job.finalize_archive().unwrap();
+        let names: Vec<String> = Archive::open(job.archive_path()).unwrap().entries().iter().map(|e| e.name.clone()).collect();
+        assert_eq!(names, vec!["000001.png", "000002.png", "manifest.json", "report.txt"]);
+        assert_eq!(job.data["archive.complete"], "true");
+        assert_eq!(job.data["archive.hash"].len(), 128);
+        assert_eq!(job.ready_archive(), Some(job.archive_path()));
+        assert!(frames.join(MANIFEST_FILENAME).exists());
+
+        // A late frame reopens the archive
+        fs::write(frames.join("000003.png"), "three").unwrap();
+        assert_eq!(job.update_archive().unwrap(), 1);
+        assert_eq!(job.ready_archive(), None);
+        assert!(!job.data.contains_key("archive.hash"));
+        job.finalize_archive().unwrap();
+        let names: Vec<String> = Archive::open(job.archive_path()).unwrap().entries().iter().map(|e| e.name.clone()).collect();
+        assert_eq!(names, vec!["000001.png", "000002.png", "000003.png", "manifest.json", "report.txt"]);
+    }
+
+    #[test]
+    fn changed_files() {
+        let dir = TempDir::new().unwrap();
+        let mut job = JobBuilder::new().in_directory(dir.path().join("blendfiles"))
+                                       .with_image_format("PNG")
+                                       .with_frames(1, 3)
+                                       .build();
+        job.tasks = job.generate_commands(1);
+        let frames = PathBuf::from(&job.paths.frames);
+        fs::create_dir_all(&frames).unwrap();
+        for (name, content) in [("000001.png", "one"), ("000002.png", "two"), ("000003.png", "three")].iter(){
+            fs::write(frames.join(name), content).unwrap();
+        }
+        job.finalize_archive().unwrap();
+        let archived = Archive::open(job.archive_path()).unwrap();
+        assert!(archived.entries()[1].matches_file(frames.join("000002.png")));
+        assert!(!archived.entries()[1].matches_file(frames.join("000003.png")));
+
+        // A re-rendered frame of the same size replaces the stale one and
+        // everything after it
+        fs::write(frames.join("000002.png"), "TWO").unwrap();
+        assert_eq!(job.update_archive().unwrap(), 2);
+        assert_eq!(job.ready_archive(), None);
+        let archive = Archive::open(job.archive_path()).unwrap();
+        let names: Vec<String> = archive.entries().iter().map(|e| e.name.clone()).collect();
+        assert_eq!(names, vec!["000001.png", "000002.png", "000003.png"]);
+        assert_eq!(archive.entries()[0], archived.entries()[0]);
+        assert_eq!(archive.entries()[1].crc32, crc32(b"TWO"));
+        assert_eq!(job.update_archive().unwrap(), 0);
+
+        // A finalized archive holds the files as they are on disk
+        fs::write(frames.join("000003.png"), "re-rendered").unwrap();
+        job.finalize_archive().unwrap();
+        let archive = Archive::open(job.archive_path()).unwrap();
+        assert_eq!(archive.entries().len(), 5);
+        for entry in archive.entries().iter().take(3){
+            assert_eq!(entry.crc32, crc32(&fs::read(frames.join(&entry.name)).unwrap()));
+        }
+    }
+}
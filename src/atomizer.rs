@@ -11,32 +11,61 @@ use std::iter::FromIterator;
 pub trait Atomizer{
     fn atomize_to_tasks(&mut self);
     fn generate_commands(&self, chunk_size: usize) -> VecDeque<Task>;
+    fn generate_commands_for(&self, frames: &[usize], chunk_size: usize) -> VecDeque<Task>;
 }
 
 impl Atomizer for Job{
     /// Genenerate Tasks for the command. The chunk size controls how many \
-    /// Frames are grouped together if `job::animation == true`.
+    /// Frames are grouped together if `job::animation == true`. An invalid \
+    /// frame override errors the Job.
     fn atomize_to_tasks(&mut self){
         let chunk_size = 1;
+        if !self.frame_override.is_default(){
+            match self.effective_frames(){
+                Ok(frames) => {
+                    let message = format!("Frame override applied: scanned {} ({} frames), effective {} ({} frames)",
+                        FrameSpec::from(&self.frames), self.frames.count(), frames, frames.len());
+                    self.add_history(message.as_str());
+                },
+                Err(err) => {
+                    self.error(format!("Invalid frame override: {}", err));
+                    return;
+                }
+            }
+        }
         self.tasks = self.generate_commands(chunk_size);
         self.set_atomize();
     }
 
     /// Generate a list of commands for a Job
     fn generate_commands(&self, chunk_size: usize) -> VecDeque<Task>{
-        let mut frames = Vec::new();
-        let iformat = &self.render.image_format;
-        // Return the frame/frames depending on the split settings
-        if self.animation { 
+        // Return the frame/frames depending on the split settings and the
+        // frame override
+        let frames = if !self.frame_override.is_default() {
+            self.effective_frames().map(|frames| frames.as_vec()).unwrap_or_default()
+        } else if self.animation { 
             self.frames.as_vec()
-                       .iter()
-                       .for_each(|frame| frames.push(*frame)) 
         } else { 
-            frames.push(self.frames.current) 
-        }
+            vec![self.frames.current]
+        };
+        self.generate_commands_for(&frames, chunk_size)
+    }
+
+    /// Generate a list of commands for the given frames of a Job (e.g. to \
+    /// render missing frames again)
+    fn generate_commands_for(&self, frames: &[usize], chunk_size: usize) -> VecDeque<Task>{
+        let iformat = &self.render.image_format;
+        let template = self.resolved_output_template();
+        let with_template = |mut task: Task| {
+            if let Command::Blender(ref mut blender_command) = task.command{
+                blender_command.template = Some(template.clone());
+                blender_command.set_outputs(self.render.outputs.clone());
+            }
+            task
+        };
         if chunk_size == 1{
             // Run construct_command on every frame and return as a VecDeque<Task>
-            VecDeque::from_iter(frames.iter().map(|frame| Task::new_blender_single(*frame, iformat.clone(), self.id.clone())))
+            VecDeque::from_iter(frames.iter().map(|frame| with_template(Task::new_blender_single(*frame, iformat.clone(), self.id.clone()))))
         } else {
             // Get a chunk of frames (a Vec<usize>) and map it to the construct_range_command
             VecDeque::from_iter(frames.chunks(chunk_size as usize)
@@ -46,8 +75,54 @@ impl Atomizer for Job{
                         let step = self.frames.step;
                         debug_assert!(self.frames.start <= self.frames.end);
                         debug_assert!(step > 0);
-                        Task::new_blender_range(*start, *end, step, iformat.clone(), self.id.clone())
+                        with_template(Task::new_blender_range(*start, *end, step, iformat.clone(), self.id.clone()))
                     }))
         }
     }
-}
\ No newline at end of file
+}
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::JobBuilder;
+
+    fn job() -> Job{
+        let mut job = JobBuilder::new().with_image_format("PNG").with_frames(1, 250).build();
+        job.status.validate().unwrap();
+        job.status.scan().unwrap();
+        job
+    }
+
+    #[test]
+    fn frame_override() {
+        let mut job = job();
+        job.set_frame_override(FrameOverride::range(Some(101), None, Some(50))).unwrap();
+        job.atomize();
+        let frames: Vec<usize> = job.tasks.iter().filter_map(|task| match task.command{
+            Command::Blender(ref command) => Some(command.frame.start()),
+            _ => None
+        }).collect();
+        assert_eq!(frames, vec![101, 151, 201]);
+        assert!(job.history.texts().any(|event| event == "Frame override applied: scanned 1-250 (250 frames), effective 101-201x50 (3 frames)"));
+        assert_eq!(job.last_event_message(), "Atomization finished: created 3 atomic tasks (for 3 frames)");
+    }
+
+    #[test]
+    fn invalid_frame_override() {
+        let mut job = job();
+        assert!(job.set_frame_override(FrameOverride::range(None, Some(300), None)).is_err());
+        assert!(job.frame_override.is_default());
+        job.frame_override = FrameOverride::current(300);
+        job.atomize();
+        assert!(job.is_errored());
+        assert!(job.tasks.is_empty());
+    }
+}
@@ -1,7 +1,13 @@
 //! The command module defines the Command enum, which is either a BasicCommand \
 //! or a BlenderCommand. For details check the Command Enum documentation.
+//!
+//! Both variants can be turned into a [CommandLine](struct.CommandLine.html), \
+//! a program plus a vector of arguments that can be executed without going \
+//! through a shell.
 
 use ::*;
+use template::TemplateContext;
+use upload::{Uploader, UploadItem, UploadReport};
 use reqwest::{header::USER_AGENT, multipart};
 use std::thread;
 use std::time::Duration;
@@ -59,6 +65,11 @@ impl Command{
         Command::Basic(BasicCommand::new(command.into()))
     }
 
+    /// Return a new Basic Command from a structured CommandLine
+    pub fn from_line(line: CommandLine) -> Self{
+        Command::Basic(BasicCommand::from_line(line))
+    }
+
     /// Return a new Blender Command for a single Frame
     pub fn new_blender_single<S>(f: usize, image_format: S) -> Self where S: Into<String>{
         Command::Blender(BlenderCommand::new_single(f, image_format.into()))
@@ -88,6 +99,15 @@ impl Command{
         }
     }
 
+    /// Return the Command as a program with a argument vector. Return a Error \
+    /// if the Command is a BlenderCommand that hasn't been constructed yet
+    pub fn command_line(&self) -> GenResult<CommandLine>{
+        match self{
+            Command::Basic(c) => c.command_line(),
+            Command::Blender(c) => c.command_line()
+        }
+    }
+
     /// Return a string that represents the Command in essence
     pub fn short(&self) -> String{
         match self{
@@ -149,7 +169,31 @@ impl Command{
         }
     }
 
-    /// Post the frame in self to flaskbender via http
+    /// Verify the rendered files of the underlying BlenderCommand and return \
+    /// the framenumbers of the corrupt frames. If the command is _not_ a \
+    /// BlenderCommand, return Error. See `BlenderCommand::verify_frames()`
+    pub fn verify_frames(&mut self, resolution: Option<&Resolution>) -> GenResult<Vec<usize>>{
+        if let Command::Blender(blender_command) = self{
+            blender_command.verify_frames(resolution)
+        }else{
+            Err(From::from("Couldn't verify the frames, because the Command was not a BlenderCommand"))
+        }
+    }
+
+    /// Upload the frames of the underlying BlenderCommand that haven't been \
+    /// uploaded yet with the Uploader (see the [upload](../upload/index.html) \
+    /// module). If the command is _not_ a BlenderCommand, return Error.
+    pub fn upload_frames(&mut self, uploader: &Uploader) -> GenResult<UploadReport>{
+        match self{
+            Command::Blender(ref mut blender_command) => uploader.upload(blender_command),
+            _ => Err(From::from("The Command was not a blender command"))
+        }
+    }
+
+    /// Post the frame in self to flaskbender via http. This posts every \
+    /// frame once, without retries, and doesn't mark frames as uploaded. \
+    /// Use `upload_frames()` with a [Uploader](../upload/struct.Uploader.html) instead
+    #[deprecated(note = "use `upload_frames()` with a `Uploader`")]
     pub fn post_frames<S>(&self, bender_url: S) -> GenResult<Vec<reqwest::Response>> where S: Into<String>{
         let bender_url = bender_url.into();
         let mut v = Vec::new();
@@ -166,7 +210,7 @@ impl Command{
 
                     let client = reqwest::Client::new();
                     let url    = reqwest::Url::parse(bender_url.as_str())?;
-                    
+
                     let res = client.post(url)
                                     .header(USER_AGENT, "bender-worker")
                                     .multipart(form)
@@ -178,8 +222,6 @@ impl Command{
             },
             _ => Err(From::from("The Command was not a blender command"))
         }
-
-
     }
 
 }
@@ -208,20 +250,38 @@ impl fmt::Display for Command {
 // ===========================================================================
 
 
-/// A basic command, that is basically just a command-line executable string
+/// A basic command, that is basically just a command-line executable string. \
+/// Besides the string it stores the structured [CommandLine](struct.CommandLine.html) \
+/// (program, args, env and working dir). Basic commands deserialized from \
+/// older data only carry the string, their CommandLine is parsed on demand.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct BasicCommand{
-    pub command: String
+    pub command: String,
+    #[serde(flatten)]
+    pub line: CommandLine
 }
 
 
 
 impl BasicCommand{
 
-    /// Create a new basic command
+    /// Create a new basic command. The string is split into program and \
+    /// arguments following the usual shell quoting rules
     pub fn new<S>(command: S) -> Self where S: Into<String>{
+        let command = command.into();
+        let line = CommandLine::parse(command.as_str()).unwrap_or_default();
+        BasicCommand{
+            command,
+            line
+        }
+    }
+
+    /// Create a new basic command from a structured CommandLine. The string \
+    /// representation is the shell-quoted rendering of the CommandLine
+    pub fn from_line(line: CommandLine) -> Self{
         BasicCommand{
-            command: command.into()
+            command: line.to_shell_string(),
+            line
         }
     }
 
@@ -231,6 +291,16 @@ impl BasicCommand{
         Ok(c)
     }
 
+    /// Return the structured CommandLine. If there is none stored, parse it \
+    /// from the command string
+    pub fn command_line(&self) -> GenResult<CommandLine>{
+        if self.line.is_empty(){
+            CommandLine::parse(self.command.as_str())
+        }else{
+            Ok(self.line.clone())
+        }
+    }
+
 }
 
 
@@ -262,8 +332,13 @@ pub struct BlenderCommand{
     pub image_format: String,
     pub blendfile: Option<String>,
     pub outpath: Option<String>,
-    pub command: Option<String>
-    
+    pub command: Option<String>,
+    #[serde(default)]
+    pub line: Option<CommandLine>,
+    #[serde(default)]
+    pub template: Option<OutputTemplate>,
+    #[serde(default)]
+    pub outputs: Vec<OutputSpec>
 }
 
 
@@ -277,7 +352,10 @@ impl BlenderCommand{
             image_format: image_format.into(),
             blendfile: None,
             outpath: None,
-            command: None
+            command: None,
+            line: None,
+            template: None,
+            outputs: Vec::new()
         }
     }
 
@@ -288,8 +366,44 @@ impl BlenderCommand{
             image_format: image_format.into(),
             blendfile: None,
             outpath: None,
-            command: None
+            command: None,
+            line: None,
+            template: None,
+            outputs: Vec::new()
+        }
+    }
+
+    /// Use the given OutputTemplate to name the frames (instead of the \
+    /// default template). This has to be set before construct() is called.
+    pub fn with_template(mut self, template: OutputTemplate) -> Self{
+        self.template = Some(template);
+        self
+    }
+
+    /// Expect the given outputs for every frame (see [OutputSpec](../outputs/struct.OutputSpec.html)) \
+    /// instead of a single file per frame. This has to be set before \
+    /// construct() is called.
+    pub fn with_outputs(mut self, outputs: Vec<OutputSpec>) -> Self{
+        self.set_outputs(outputs);
+        self
+    }
+
+    /// Set the outputs expected for every frame and track each of them in \
+    /// the frames. An empty Vector means a single file per frame.
+    pub fn set_outputs(&mut self, outputs: Vec<OutputSpec>){
+        for frame in self.frame.values_mut(){
+            outputs.iter().for_each(|output| frame.add_output(output.name.as_str()));
         }
+        self.outputs = outputs;
+    }
+
+    /// Return the OutputTemplate for the frames. Placeholders that are still \
+    /// unresolved get resolved with the name of the constructed blendfile
+    pub fn output_template(&self) -> OutputTemplate{
+        let template = self.template.clone().unwrap_or_default();
+        let blend = self.blendfile.as_ref()
+                                  .and_then(|b| PathBuf::from(b).file_stem().map(|s| s.to_string_lossy().to_string()));
+        template.resolve(&TemplateContext{ blend, ..Default::default() })
     }
 
     /// Convert the command to String, return Error if Self::construct() hasn't been called before
@@ -304,13 +418,44 @@ impl BlenderCommand{
     pub fn construct<S>(&mut self, blendfile: S, outpath: S) where S: Into<String>{
         self.blendfile = Some(blendfile.into());
         self.outpath = Some(outpath.into());
-        let framestring = self.frame.to_flags();
-        let out = self.outpath.clone().unwrap()+"/######."+&self.image_format.to_lowercase();
-        self.command = Some(format!("blender -b --disable-autoexec {blendfile} -o {out} -F {format} {f}", 
-            blendfile=self.blendfile.clone().unwrap(), 
-            out=out, 
-            format=self.image_format,
-            f=framestring));
+        let line = self.build_line();
+        self.command = Some(line.to_shell_string());
+        self.line = Some(line);
+    }
+
+    /// Build the CommandLine for the blendfile and outpath stored in self. \
+    /// This expects both to be set.
+    fn build_line(&self) -> CommandLine{
+        let outpath = self.outpath.clone().unwrap();
+        let out = outpath.clone()+"/"+&self.output_template().blender_pattern()+"."+&self.extension();
+        let mut line = CommandLine::new("blender")
+            .arg("-b")
+            .arg("--disable-autoexec")
+            .arg(self.blendfile.clone().unwrap());
+        // Point the File Output nodes into the outpath
+        if let Some(expr) = outputs::python_expr(&outpath, &self.outputs){
+            line = line.arg("--python-expr").arg(expr);
+        }
+        line.arg("-o")
+            .arg(out)
+            .arg("-F")
+            .arg(self.image_format.clone())
+            .args(self.frame.to_args())
+    }
+
+    /// Return the constructed CommandLine, return Error if Self::construct() \
+    /// hasn't been called before
+    pub fn command_line(&self) -> GenResult<CommandLine>{
+        match self.line{
+            Some(ref line) => Ok(line.clone()),
+            None => {
+                if self.blendfile.is_some() && self.outpath.is_some(){
+                    Ok(self.build_line())
+                }else{
+                    Err(From::from("Error: Couldn't get the CommandLine of the Blender Command. Forgot to call construct() first?"))
+                }
+            }
+        }
     }
 
     /// Merge one BlenderCommand into another based on its values
@@ -330,6 +475,18 @@ impl BlenderCommand{
         if self.command.is_none() && other.command.is_some(){
             self.command = other.command.clone();
         }
+
+        if self.line.is_none() && other.line.is_some(){
+            self.line = other.line.clone();
+        }
+
+        if self.template.is_none() && other.template.is_some(){
+            self.template = other.template.clone();
+        }
+
+        if self.outputs.is_empty() && !other.outputs.is_empty(){
+            self.outputs = other.outputs.clone();
+        }
     }
 
     /// Return true if the blendfile has been constructed
@@ -337,89 +494,197 @@ impl BlenderCommand{
         self.blendfile.is_some()
     }
 
-    /// Return a Vector of PathBuf where each PathBuf is the Path of one frame generated by the command
+    /// Return a Vector of PathBuf where each PathBuf is the Path of one file generated by the command \
+    /// (one per frame, or one per frame and output)
     pub fn renderpaths(&self) -> Vec<PathBuf>{
-        self.frame.iter()
-            .map(|(framenumber, _)| {
-                self.path_for_frame(*framenumber)
+        self.frame.keys()
+            .flat_map(|framenumber| {
+                self.paths_for_frame(*framenumber)
+                    .into_iter()
+                    .map(|(_, path)| path)
             })
             .collect()
     }
 
-    /// Return the path for a constructed frame
+    /// Return the path for a constructed frame. If the command has outputs, \
+    /// this is the path of the main render without view suffix.
     pub fn path_for_frame(&self, framenumber: usize) -> PathBuf{
-        let s = self.outpath.clone().unwrap()+"/"+&format!("{:06}", framenumber)+"."+&self.image_format.to_lowercase();
+        let s = self.outpath.clone().unwrap()+"/"+&self.output_template().file_stem(framenumber)+"."+&self.extension();
         PathBuf::from(s.clone())
     }
 
-    /// Read and set the filesizes for all rendered frames
+    /// Return the name and path of every file of a constructed frame. \
+    /// Without outputs this is the single file at `path_for_frame()`, named \
+    /// "render".
+    pub fn paths_for_frame(&self, framenumber: usize) -> Vec<(String, PathBuf)>{
+        self.frame_files(framenumber)
+            .into_iter()
+            .map(|file| (file.output.unwrap_or_else(|| outputs::MAIN.to_string()), file.path))
+            .collect()
+    }
+
+    /// Return the files of a constructed frame
+    fn frame_files(&self, framenumber: usize) -> Vec<FrameFile>{
+        if self.outputs.is_empty(){
+            return vec![FrameFile{
+                framenumber,
+                output: None,
+                path: self.path_for_frame(framenumber),
+                image_format: self.image_format.clone()
+            }];
+        }
+        let outpath = self.outpath.clone().unwrap();
+        let stem = self.output_template().file_stem(framenumber);
+        self.outputs.iter()
+            .map(|output| FrameFile{
+                framenumber,
+                output: Some(output.name.clone()),
+                path: PathBuf::from(format!("{}/{}", outpath, output.file_name(&stem, framenumber, &self.image_format))),
+                image_format: output.image_format(&self.image_format)
+            })
+            .collect()
+    }
+
+    /// Return the files of all constructed frames
+    fn all_frame_files(&self) -> Vec<FrameFile>{
+        self.frame.keys()
+            .flat_map(|framenumber| self.frame_files(*framenumber))
+            .collect()
+    }
+
+    /// Return the ImageFormat of the command, or None if the image_format \
+    /// is not one of the allowed formats
+    pub fn format(&self) -> Option<ImageFormat>{
+        ImageFormat::from_blender(&self.image_format)
+    }
+
+    /// Return the extension blender writes for the image_format. Unknown \
+    /// formats fall back to the lowercase format name
+    pub fn extension(&self) -> String{
+        match self.format(){
+            Some(format) => format.extension().to_string(),
+            None => self.image_format.to_lowercase()
+        }
+    }
+
+    /// Read and set the filesizes for all rendered frames (and outputs). \
+    /// Files that don't exist are skipped
     pub fn get_frame_filesizes(&mut self) -> GenResult<()>{
-        // Collect the paths where the frame should be rendered first
-        let mut framepaths = HashMap::new(); 
-        self.frame.iter()
-                  .for_each(|(i, _)|{
-                    framepaths.insert(*i, self.path_for_frame(*i));
-                  });
-
-        let _v: GenResult<Vec<usize>> = 
-        self.frame.iter_mut()
-                  .map(|(i, frame)|{
-                    match framepaths.get(i){
-                        Some(path) => {
-                            if path.exists(){
-                                let file = std::fs::File::open(&path)?;
-                                Ok(frame.filesize_from_file(file)?)
-                            }else{
-                                let message = format!("Couldn't filesize Frame {}, because the file doesn't exist: {}", 
-                                    i, &path.to_string_lossy());
-                                Err(From::from(message))
-                            }
-                        },
-                        None => {
-                            let message = format!("Couldn't filesize Frame {} because the index is out of bounds", 
-                                    i);
-                                Err(From::from(message))
-                        }
-                    }
-                  })
-                  .collect();
+        for file in self.all_frame_files(){
+            if !file.path.exists(){ continue; }
+            let reader = std::fs::File::open(&file.path)?;
+            if let Some(frame) = self.frame.get_mut(&file.framenumber){
+                match file.output{
+                    Some(name) => frame.output_entry(name).filesize_from_file(reader)?,
+                    None => frame.filesize_from_file(reader)?
+                };
+            }
+        }
         Ok(())
     }
 
-    /// Generate and set the hashes for all rendered frames
+    /// Generate and set the hashes for all rendered frames (and outputs). \
+    /// Files that don't exist are skipped
     pub fn get_frame_hashes(&mut self) -> GenResult<()>{
-        // Collect the paths where the frame should be rendered first
-        let mut framepaths = HashMap::new(); 
-        self.frame.iter()
-                  .for_each(|(i, _)|{
-                    framepaths.insert(*i, self.path_for_frame(*i));
-                  });
-
-        let _v: GenResult<Vec<String>> = 
-        self.frame.iter_mut()
-                  .map(|(i, frame)|{
-                    match framepaths.get(i){
-                        Some(path) => {
-                            if path.exists(){
-                                let file = std::fs::File::open(&path)?;
-                                Ok(frame.hash_from_file(file)?)
-                            }else{
-                                let message = format!("Couldn't hash Frame {}, because the file doesn't exist: {}", 
-                                    i, &path.to_string_lossy());
-                                Err(From::from(message))
-                            }
-                        },
-                        None => {
-                            let message = format!("Couldn't hash Frame {} because the index is out of bounds", 
-                                    i);
-                                Err(From::from(message))
-                        }
-                    }
-                  })
-                  .collect();
+        for file in self.all_frame_files(){
+            if !file.path.exists(){ continue; }
+            let reader = std::fs::File::open(&file.path)?;
+            if let Some(frame) = self.frame.get_mut(&file.framenumber){
+                match file.output{
+                    Some(name) => frame.output_entry(name).hash_from_file(reader)?,
+                    None => frame.hash_from_file(reader)?
+                };
+            }
+        }
         Ok(())
     }
 
+    /// Verify the rendered file of every frame (and output, see the \
+    /// [verify](../verify/index.html) module) and compare its dimensions with \
+    /// the Resolution if one is given. \
+    /// Formats the verify module doesn't understand (JPEG2000, CINEON, DPX) \
+    /// are only checked for a non-empty file. Frames that fail verification \
+    /// are marked as corrupt, frames that pass are unmarked. Return the framenumbers of the corrupt frames, or Error if \
+    /// the command hasn't been constructed yet.
+    pub fn verify_frames(&mut self, resolution: Option<&Resolution>) -> GenResult<Vec<usize>>{
+        if self.outpath.is_none(){
+            return Err(From::from("Error: Couldn't verify the frames of the Blender Command. Forgot to call construct() first?"));
+        }
+        self.frame.values_mut().for_each(|frame| frame.clear_corrupt());
+        let mut corrupt = Vec::new();
+        for file in self.all_frame_files(){
+            if let Err(reason) = verify_frame_file(&file, resolution){
+                if let Some(frame) = self.frame.get_mut(&file.framenumber){
+                    match file.output{
+                        Some(name) => frame.output_entry(name).set_corrupt(reason),
+                        None => frame.set_corrupt(reason)
+                    }
+                }
+                if !corrupt.contains(&file.framenumber){
+                    corrupt.push(file.framenumber);
+                }
+            }
+        }
+        Ok(corrupt)
+    }
+
+    /// Return the files that haven't been uploaded yet and the number of \
+    /// files that have. Files marked as corrupt by `verify_frames()` are \
+    /// neither uploaded nor counted. Return Error if a file that has to be \
+    /// uploaded has no filesize or hash yet
+    pub fn pending_uploads(&self) -> GenResult<(Vec<UploadItem>, usize)>{
+        let mut items = Vec::new();
+        let mut skipped = 0;
+        for file in self.all_frame_files(){
+            let frame = &self.frame[&file.framenumber];
+            let (uploaded, corrupt, filesize, hash) = match file.output{
+                Some(ref name) => match frame.get_output(name){
+                    Some(output) => (output.is_uploaded(), output.is_corrupt(), output.get_filesize(), output.get_hash()),
+                    None => (false, false, None, None)
+                },
+                None => (frame.is_uploaded(), frame.is_corrupt(), frame.get_filesize(), frame.get_hash())
+            };
+            if corrupt{
+                continue;
+            }
+            if uploaded || frame.is_uploaded(){
+                skipped += 1;
+                continue;
+            }
+            let item = match (filesize, hash){
+                (Some(filesize), Some(hash)) => UploadItem{
+                    framenumber: file.framenumber,
+                    output: file.output,
+                    path: file.path,
+                    filesize,
+                    hash
+                },
+                _ => {
+                    let message = format!("Error: Couldn't upload {}, because its filesize or hash is not set", file.path.to_string_lossy());
+                    return Err(From::from(message));
+                }
+            };
+            items.push(item);
+        }
+        Ok((items, skipped))
+    }
+
+    /// Mark the frame (or frame output) of a confirmed upload as uploaded
+    pub fn confirm_upload(&mut self, item: &UploadItem) -> GenResult<()>{
+        match item.output{
+            Some(ref name) => {
+                match self.frame.get_mut(&item.framenumber){
+                    Some(frame) => {
+                        frame.output_entry(name.as_str()).set_uploaded();
+                        Ok(())
+                    },
+                    None => Err(From::from(format!("Error: Couldn't confirm the upload of {}. Frame not contained in this Task.", item)))
+                }
+            },
+            None => self.frame.set_uploaded(item.framenumber)
+        }
+    }
+
     /// Set a rendered Frame's uploaded flag
     pub fn set_uploaded(&mut self, framenumber: usize) -> GenResult<()>{
         self.frame.set_uploaded(framenumber)
@@ -443,7 +708,259 @@ impl fmt::Display for BlenderCommand {
     }
 }
 
-    
+
+/// A single file rendered for a frame, with the name of the output it \
+/// belongs to (None if the command renders a single file per frame)
+struct FrameFile{
+    framenumber: usize,
+    output: Option<String>,
+    path: PathBuf,
+    image_format: String
+}
+
+
+/// Verify a rendered file and return the reason if it is corrupt
+fn verify_frame_file(file: &FrameFile, resolution: Option<&Resolution>) -> Result<(), String>{
+    let format = ImageFormat::from_blender(&file.image_format);
+    let expected_kind = format.and_then(|format| format.image_kind());
+    let verifiable = expected_kind.is_some() || format.is_none();
+    let path = &file.path;
+    if verifiable{
+        match verify::verify_file(path, resolution){
+            Ok(ref info) if expected_kind.is_some() && expected_kind != Some(info.kind) => {
+                Err(format!("Expected a {} file, but found a {} file", expected_kind.unwrap(), info.kind))
+            },
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string())
+        }
+    }else{
+        match fs::metadata(path){
+            Ok(ref metadata) if metadata.len() > 0 => Ok(()),
+            Ok(_) => Err("The file is empty".to_string()),
+            Err(err) => Err(VerifyError::Io(format!("{}: {}", path.to_string_lossy(), err)).to_string())
+        }
+    }
+}
+
+
+
+
+
+
+
+// ===========================================================================
+//                               CommandLine
+// ===========================================================================
+
+
+/// A CommandLine is the structured form of a command: a program, a vector of \
+/// arguments, additional environment variables and an optional working \
+/// directory. Because every argument is stored separately, blendfiles or \
+/// output folders containing spaces are passed to the program unchanged and \
+/// workers don't have to split a string themselves. The shell-quoted string \
+/// rendering is only meant for display and logging.
+/// ```
+/// # extern crate bender_job;
+/// # use bender_job::command::CommandLine;
+/// let c = CommandLine::new("blender")
+///                     .arg("-b")
+///                     .arg("my project/shot 1.blend");
+///
+/// assert_eq!(c.argv(), vec!["blender", "-b", "my project/shot 1.blend"]);
+/// assert_eq!(c.to_shell_string(), "blender -b 'my project/shot 1.blend'");
+///
+/// // Strings can be parsed back into a CommandLine
+/// let parsed = CommandLine::parse(c.to_shell_string()).unwrap();
+/// assert_eq!(parsed, c);
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CommandLine{
+    #[serde(default)]
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub working_dir: Option<String>
+}
+
+
+
+impl CommandLine{
+
+    /// Create a new CommandLine for the given program without any arguments
+    pub fn new<S>(program: S) -> Self where S: Into<String>{
+        CommandLine{
+            program: program.into(),
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            working_dir: None
+        }
+    }
+
+    /// Parse a CommandLine from a string, splitting it into program and \
+    /// arguments using the quoting rules of a POSIX shell (single quotes, \
+    /// double quotes and backslash escapes). Return a Error if the string is \
+    /// empty or contains unterminated quotes
+    pub fn parse<S>(command: S) -> GenResult<Self> where S: Into<String>{
+        let command = command.into();
+        let mut argv = shell_split(command.as_str())?;
+        if argv.is_empty(){
+            return Err(From::from("Error: Couldn't parse CommandLine from a empty string"));
+        }
+        let program = argv.remove(0);
+        Ok(CommandLine::new(program).args(argv))
+    }
+
+    /// Append a single argument
+    pub fn arg<S>(mut self, arg: S) -> Self where S: Into<String>{
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Append multiple arguments
+    pub fn args<I, S>(mut self, args: I) -> Self where I: IntoIterator<Item=S>, S: Into<String>{
+        self.args.extend(args.into_iter().map(|a| a.into()));
+        self
+    }
+
+    /// Set a environment variable for the program
+    pub fn env<S>(mut self, key: S, value: S) -> Self where S: Into<String>{
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set the directory the program should be run in
+    pub fn current_dir<S>(mut self, dir: S) -> Self where S: Into<String>{
+        self.working_dir = Some(dir.into());
+        self
+    }
+
+    /// Return true if there is no program set
+    pub fn is_empty(&self) -> bool{
+        self.program.is_empty()
+    }
+
+    /// Return the program followed by all arguments
+    pub fn argv(&self) -> Vec<String>{
+        let mut v = vec![self.program.clone()];
+        v.extend(self.args.iter().cloned());
+        v
+    }
+
+    /// Render the CommandLine as a shell-quoted string for display. \
+    /// Environment variables are prepended as `KEY=value` pairs, the working \
+    /// directory is not part of the rendering.
+    pub fn to_shell_string(&self) -> String{
+        self.env.iter()
+                .map(|(key, value)| format!("{}={}", key, shell_quote(value.as_str())))
+                .chain(self.argv().iter().map(|a| shell_quote(a.as_str())))
+                .collect::<Vec<String>>()
+                .join(" ")
+    }
+
+    /// Return a `std::process::Command` ready to be spawned
+    pub fn to_process(&self) -> std::process::Command{
+        let mut process = std::process::Command::new(&self.program);
+        process.args(&self.args);
+        process.envs(&self.env);
+        if let Some(ref dir) = self.working_dir{
+            process.current_dir(dir);
+        }
+        process
+    }
+}
+
+
+
+/// Implement Formating for CommandLine (shell-quoted)
+impl fmt::Display for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_shell_string())
+    }
+}
+
+
+
+/// Quote a single argument so a POSIX shell would read it back unchanged. \
+/// Arguments consisting only of safe characters are returned as they are, \
+/// everything else gets wrapped in single quotes.
+pub fn shell_quote(arg: &str) -> String{
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-.,/:=+@%#".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) && !arg.starts_with('#'){
+        arg.to_string()
+    }else{
+        format!("'{}'", arg.replace("'", "'\\''"))
+    }
+}
+
+
+
+/// Split a string into arguments following the quoting rules of a POSIX \
+/// shell. Return a Error on unterminated quotes or a trailing backslash
+pub fn shell_split(s: &str) -> GenResult<Vec<String>>{
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next(){
+        match c{
+            '\'' => {
+                in_word = true;
+                loop{
+                    match chars.next(){
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err(From::from(format!("Error: Unterminated single quote in \"{}\"", s)))
+                    }
+                }
+            },
+            '"' => {
+                in_word = true;
+                loop{
+                    match chars.next(){
+                        Some('"') => break,
+                        Some('\\') => {
+                            match chars.next(){
+                                Some(c) if "\"\\$`".contains(c) => current.push(c),
+                                Some(c) => { current.push('\\'); current.push(c); },
+                                None => return Err(From::from(format!("Error: Unterminated double quote in \"{}\"", s)))
+                            }
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err(From::from(format!("Error: Unterminated double quote in \"{}\"", s)))
+                    }
+                }
+            },
+            '\\' => {
+                in_word = true;
+                match chars.next(){
+                    Some(c) => current.push(c),
+                    None => return Err(From::from(format!("Error: Trailing backslash in \"{}\"", s)))
+                }
+            },
+            c if c.is_whitespace() => {
+                if in_word{
+                    args.push(current.clone());
+                    current.clear();
+                    in_word = false;
+                }
+            },
+            c => {
+                in_word = true;
+                current.push(c);
+            }
+        }
+    }
+
+    if in_word{
+        args.push(current);
+    }
+    Ok(args)
+}
+
 
 
 
@@ -467,4 +984,188 @@ mod command {
         let r = Command::new("ls -a");
         assert_eq!(r.to_string().unwrap(), "ls -a".to_string());
     }
+
+    #[test]
+    fn basic_command_line() {
+        let r = Command::new("ls -a 'some folder'");
+        let line = r.command_line().unwrap();
+        assert_eq!(line.program, "ls");
+        assert_eq!(line.args, vec!["-a".to_string(), "some folder".to_string()]);
+    }
+
+    #[test]
+    fn basic_legacy_deserialize() {
+        let data = r#"{"Basic": {"command": "ls -a"}}"#;
+        let c: Command = serde_json::from_str(data).expect("Deserialization of legacy basic command failed");
+        assert_eq!(c.to_string().unwrap(), "ls -a");
+        assert_eq!(c.command_line().unwrap().argv(), vec!["ls", "-a"]);
+    }
+
+    #[test]
+    fn basic_from_line() {
+        let line = CommandLine::new("echo").arg("hello world").env("FOO", "bar baz");
+        let c = Command::from_line(line.clone());
+        assert_eq!(c.to_string().unwrap(), "FOO='bar baz' echo 'hello world'");
+        assert_eq!(c.command_line().unwrap(), line);
+    }
+
+    #[test]
+    fn blender_paths_with_spaces() {
+        let mut c = Command::new_blender_single(121, "PNG");
+        assert!(c.command_line().is_err());
+        c.construct("my blends/shot 1.blend", "/data/render here");
+        let line = c.command_line().unwrap();
+        assert_eq!(line.program, "blender");
+        assert_eq!(line.args, vec!["-b", "--disable-autoexec", "my blends/shot 1.blend", "-o", "/data/render here/######.png", "-F", "PNG", "-f", "121"]);
+        assert_eq!(c.to_string().unwrap(), "blender -b --disable-autoexec 'my blends/shot 1.blend' -o '/data/render here/######.png' -F PNG -f 121");
+    }
+
+    #[test]
+    fn blender_range_args() {
+        let mut c = Command::new_blender_range(1, 250, 10, "PNG");
+        c.construct("a.blend", "out");
+        let line = c.command_line().unwrap();
+        assert_eq!(&line.args[7..], &["-s", "1", "-e", "241", "-j", "10"]);
+    }
+
+    #[test]
+    fn split_quotes() {
+        let v = shell_split(r#"a 'b c' "d \"e\"" f\ g '' h"#).unwrap();
+        assert_eq!(v, vec!["a", "b c", "d \"e\"", "f g", "", "h"]);
+    }
+
+    #[test]
+    fn split_unterminated() {
+        assert!(shell_split("a 'b").is_err());
+        assert!(shell_split("a \"b").is_err());
+        assert!(shell_split("a b\\").is_err());
+    }
+
+    #[test]
+    fn quote_roundtrip() {
+        let args = vec!["plain", "with space", "it's", "", "#hash", "a#b", "$HOME", "tab\there"];
+        let joined = args.iter().map(|a| shell_quote(a)).collect::<Vec<String>>().join(" ");
+        assert_eq!(shell_split(&joined).unwrap(), args);
+    }
+
+    #[test]
+    fn verify_frames() {
+        use common::tempfile::TempDir;
+        let dir = TempDir::new().unwrap();
+        let outpath = dir.path().to_string_lossy().to_string();
+        let mut c = BlenderCommand::new_range(1, 3, 1, "PNG");
+        assert!(c.verify_frames(None).is_err());
+        c.construct("shot.blend".to_string(), outpath);
+
+        let png = verify::tests::png(64, 32);
+        fs::write(c.path_for_frame(1), &png).unwrap();
+        fs::write(c.path_for_frame(2), &png[..png.len()-4]).unwrap();
+
+        let resolution = Resolution{ x: 64, y: 32, scale: 100 };
+        assert_eq!(c.verify_frames(Some(&resolution)).unwrap(), vec![2, 3]);
+        assert!(!c.frame[&1].is_corrupt());
+        assert_eq!(c.frame[&2].get_corrupt(), Some("The PNG file is truncated".to_string()));
+        assert!(c.frame[&3].is_corrupt());
+
+        // Corrupt frames are not uploaded, even if filesize and hash are set
+        for frame in c.frame.values_mut(){
+            frame.set_filesize(png.len());
+            frame.set_hash("f5560c3296de4e0e");
+        }
+        let (items, skipped) = c.pending_uploads().unwrap();
+        assert_eq!(items.iter().map(|item| item.framenumber).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(skipped, 0);
+
+        fs::write(c.path_for_frame(2), &png).unwrap();
+        fs::write(c.path_for_frame(3), &png).unwrap();
+        assert!(c.verify_frames(Some(&resolution)).unwrap().is_empty());
+        assert!(!c.frame.any_corrupt());
+    }
+
+    #[test]
+    fn real_extensions() {
+        let formats = [("PNG", "png"), ("OPEN_EXR", "exr"), ("OPEN_EXR_MULTILAYER", "exr"), ("JPEG", "jpg"),
+                       ("TIFF", "tif"), ("TARGA_RAW", "tga"), ("FOO_BAR", "foo_bar")];
+        for (format, extension) in formats.iter(){
+            let mut c = BlenderCommand::new_single(1, *format);
+            c.construct("shot.blend", "/out");
+            assert_eq!(c.path_for_frame(1), PathBuf::from(format!("/out/000001.{}", extension)));
+            assert!(c.command_line().unwrap().args.contains(&format!("/out/######.{}", extension)));
+        }
+    }
+
+    #[test]
+    fn verify_unverifiable_format() {
+        use common::tempfile::TempDir;
+        let dir = TempDir::new().unwrap();
+        let mut c = BlenderCommand::new_range(1, 2, 1, "DPX");
+        c.construct("shot.blend".to_string(), dir.path().to_string_lossy().to_string());
+        fs::write(c.path_for_frame(1), b"SDPX").unwrap();
+        fs::write(c.path_for_frame(2), b"").unwrap();
+        assert_eq!(c.verify_frames(None).unwrap(), vec![2]);
+
+        // A PNG written where a JPEG is expected is corrupt
+        let mut c = BlenderCommand::new_single(1, "JPEG");
+        c.construct("shot.blend".to_string(), dir.path().to_string_lossy().to_string());
+        fs::write(c.path_for_frame(1), verify::tests::png(8, 8)).unwrap();
+        assert_eq!(c.verify_frames(None).unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn output_template() {
+        let template = OutputTemplate::new("{blend}_{camera}_{frame:4}").unwrap()
+                           .resolve(&TemplateContext{ camera: Some("cam2".to_string()), ..Default::default() });
+        let mut c = BlenderCommand::new_single(42, "PNG").with_template(template);
+        c.construct("/tmp/blends/shot010.blend", "/out");
+        assert_eq!(c.path_for_frame(42), PathBuf::from("/out/shot010_cam2_0042.png"));
+        assert!(c.command_line().unwrap().args.contains(&"/out/shot010_cam2_####.png".to_string()));
+
+        let json = serde_json::to_string(&c).unwrap();
+        let d: BlenderCommand = serde_json::from_str(&json).unwrap();
+        assert_eq!(d.path_for_frame(42), c.path_for_frame(42));
+    }
+
+    #[test]
+    fn multiple_outputs() {
+        use common::tempfile::TempDir;
+        let dir = TempDir::new().unwrap();
+        let outpath = dir.path().to_string_lossy().to_string();
+        let outputs = vec![OutputSpec::view("_L"),
+                           OutputSpec::view("_R"),
+                           OutputSpec::file_output("Depth", "depth_", "TIFF")];
+        let mut c = BlenderCommand::new_range(1, 2, 1, "PNG").with_outputs(outputs);
+        c.construct("shot.blend".to_string(), outpath.clone());
+
+        let names: Vec<String> = c.paths_for_frame(1).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["render_L", "render_R", "Depth/depth_"]);
+        assert_eq!(c.paths_for_frame(2)[2].1, PathBuf::from(format!("{}/Depth/depth_0002.tif", outpath)));
+        assert_eq!(c.renderpaths().len(), 6);
+        let args = c.command_line().unwrap().args;
+        assert_eq!(args[3], "--python-expr");
+        assert!(args[4].contains(&format!("\"Depth\":\"{}/Depth/\"", outpath)));
+
+        fs::create_dir(dir.path().join("Depth")).unwrap();
+        let png = verify::tests::png(8, 8);
+        for (_, path) in c.paths_for_frame(1).into_iter().chain(c.paths_for_frame(2)){
+            fs::write(path, &png).unwrap();
+        }
+        fs::write(&c.paths_for_frame(2)[1].1, &png[..10]).unwrap();
+        c.get_frame_filesizes().unwrap();
+        c.get_frame_hashes().unwrap();
+        assert!(c.frame.all_filesize());
+        assert!(c.frame.all_hash());
+        assert_eq!(c.frame.get_filesize(2), Some(png.len() * 2 + 10));
+
+        // The depth pass is a PNG where a TIFF is expected
+        assert_eq!(c.verify_frames(None).unwrap(), vec![1, 2]);
+        assert!(c.frame[&1].get_corrupt().unwrap().starts_with("Depth/depth_: Expected a TIFF file"));
+        assert!(c.frame[&2].get_output("render_R").unwrap().is_corrupt());
+        assert!(!c.frame[&2].get_output("render_L").unwrap().is_corrupt());
+        let (items, _) = c.pending_uploads().unwrap();
+        let pending: Vec<(usize, String)> = items.into_iter().map(|item| (item.framenumber, item.output.unwrap())).collect();
+        assert_eq!(pending, vec![(1, "render_L".to_string()), (1, "render_R".to_string()), (2, "render_L".to_string())]);
+
+        c.set_all_uploaded().unwrap();
+        assert!(c.frame[&1].get_output("render_R").unwrap().is_uploaded());
+    }
 }
\ No newline at end of file
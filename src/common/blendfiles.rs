@@ -206,7 +206,7 @@ pub mod permanent{
         use status::Status;
         use std::path::PathBuf;
         use std::fs;
-        use std::collections::{HashMap, BTreeMap};
+        use std::collections::HashMap;
 
         // ================== PERMANENT::DETERMINISTIC::SINGLE =================
         /// Creation of permanent deterministic jobs
@@ -373,11 +373,15 @@ pub mod permanent{
                 time: JobTime::new_deterministic_for_test(),
                 status: Status::new(),
                 data: HashMap::new(),
-                history: BTreeMap::new(),
+                history: Default::default(),
                 resolution: Default::default(),
                 render: Default::default(),
                 frames: Default::default(),
-                tasks: Default::default()
+                tasks: Default::default(),
+                output_template: Default::default(),
+                frame_override: Default::default(),
+                webhooks: Default::default(),
+                observers: Default::default()
             };
 
             // Write the "data.json" to the temporary folder
@@ -398,7 +402,7 @@ pub mod permanent{
         use status::Status;
         use std::path::PathBuf;
         use std::fs;
-        use std::collections::{HashMap, BTreeMap};
+        use std::collections::HashMap;
 
         // ===================== PERMANENT::RANDOM::SINGLE =====================
         /// Creation of permanent random jobs
@@ -543,11 +547,15 @@ pub mod permanent{
                 time:       JobTime::new(),
                 status:     Status::new(),
                 data:       HashMap::new(),
-                history:    BTreeMap::new(),
+                history:    Default::default(),
                 resolution: Default::default(),
                 render:     Default::default(),
                 frames:     Default::default(),
-                tasks:      Default::default()
+                tasks:      Default::default(),
+                output_template: Default::default(),
+                frame_override: Default::default(),
+                webhooks: Default::default(),
+                observers: Default::default()
             };
 
             // Write the "data.json" to the temporary folder
@@ -871,11 +879,15 @@ pub mod temporary{
             time: JobTime::new_deterministic_for_test(),
             status: Status::new(),
             data: HashMap::new(),
-            history: BTreeMap::new(),
+            history: Default::default(),
             resolution: Default::default(),
             render: Default::default(),
             frames: Default::default(),
-            tasks: Default::default()
+            tasks: Default::default(),
+            output_template: Default::default(),
+            frame_override: Default::default(),
+            webhooks: Default::default(),
+            observers: Default::default()
         };
 
         // Write the "data.json" to the temporary folder
@@ -0,0 +1,166 @@
+//! The httpstub module provides a minimal local HTTP server for tests. It \
+//! answers every request with the next status code of a script and records \
+//! the requests it received.
+
+
+use ::*;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+
+
+
+// ===========================================================================
+//                            common::httpstub
+// ===========================================================================
+
+/// A request received by the HttpStub
+#[derive(Debug, Clone)]
+pub struct StubRequest{
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>
+}
+
+impl StubRequest{
+    /// Return the value of the first header with the given name
+    pub fn header(&self, name: &str) -> Option<String>{
+        self.headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Return the body as (lossy) String
+    pub fn body_string(&self) -> String{
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+}
+
+
+/// A local HTTP server listening on a random port of 127.0.0.1. Every \
+/// request is answered with the next status code of the script (200 once \
+/// the script is exhausted). A status code of 0 closes the connection \
+/// without a response, redirects (3xx) point at `/redirected`. The server \
+/// stops when the HttpStub is dropped.
+pub struct HttpStub{
+    pub addr: SocketAddr,
+    requests: Arc<Mutex<Vec<StubRequest>>>,
+    stop: Arc<AtomicBool>
+}
+
+impl HttpStub{
+    /// Start a server that answers with the given status codes
+    #[allow(dead_code)]
+    pub fn start(script: Vec<u16>) -> Self{
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Couldn't bind the HttpStub");
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let script = Arc::new(Mutex::new(VecDeque::from(script)));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread_requests = requests.clone();
+        let thread_stop = stop.clone();
+        thread::spawn(move ||{
+            for stream in listener.incoming(){
+                if thread_stop.load(Ordering::SeqCst){ break; }
+                if let Ok(stream) = stream{
+                    let requests = thread_requests.clone();
+                    let script = script.clone();
+                    thread::spawn(move || handle(stream, &requests, &script));
+                }
+            }
+        });
+        HttpStub{ addr, requests, stop }
+    }
+
+    /// The url of the server for the given path
+    #[allow(dead_code)]
+    pub fn url(&self, path: &str) -> String{
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// Return all requests received so far
+    #[allow(dead_code)]
+    pub fn requests(&self) -> Vec<StubRequest>{
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for HttpStub{
+    fn drop(&mut self){
+        self.stop.store(true, Ordering::SeqCst);
+        // Wake up the listener
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+
+/// Read one request from the stream, record it and answer it
+fn handle(stream: TcpStream, requests: &Mutex<Vec<StubRequest>>, script: &Mutex<VecDeque<u16>>){
+    let mut reader = BufReader::new(match stream.try_clone(){
+        Ok(s) => s,
+        Err(_) => return
+    });
+    let request = match read_request(&mut reader){
+        Some(request) => request,
+        None => return
+    };
+    let status = script.lock().unwrap().pop_front().unwrap_or(200);
+    requests.lock().unwrap().push(request);
+    if status == 0{
+        return;
+    }
+    let body = format!("{}", status);
+    let location = match status{
+        300..=399 => "Location: /redirected\r\n",
+        _ => ""
+    };
+    let response = format!("HTTP/1.1 {} Stub\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}", status, location, body.len(), body);
+    let mut stream = stream;
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.flush();
+}
+
+
+/// Read the request line, headers and body (Content-Length or chunked)
+fn read_request<R: BufRead>(reader: &mut R) -> Option<StubRequest>{
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut headers = Vec::new();
+    loop{
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty(){ break; }
+        let mut header = line.splitn(2, ':');
+        let key = header.next()?.trim().to_string();
+        let value = header.next().unwrap_or("").trim().to_string();
+        headers.push((key, value));
+    }
+
+    let mut request = StubRequest{ method, path, headers, body: Vec::new() };
+    if let Some(length) = request.header("content-length"){
+        let mut body = vec![0; length.parse().ok()?];
+        reader.read_exact(&mut body).ok()?;
+        request.body = body;
+    }else if request.header("transfer-encoding").filter(|e| e.eq_ignore_ascii_case("chunked")).is_some(){
+        loop{
+            let mut size = String::new();
+            reader.read_line(&mut size).ok()?;
+            let size = usize::from_str_radix(size.trim(), 16).ok()?;
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).ok()?;
+            if size == 0{ break; }
+            request.body.extend_from_slice(&chunk[..size]);
+        }
+    }
+    Some(request)
+}
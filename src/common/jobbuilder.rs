@@ -0,0 +1,148 @@
+//! The jobbuilder module provides a builder for Jobs used by unit tests that \
+//! don't need a real blendfile. Nothing is created on disk, the Job only \
+//! points at `<directory>/<id>/shot.blend` (with its data.json next to it).
+
+
+use ::*;
+use std::path::Path;
+
+
+
+
+// ===========================================================================
+//                            common::jobbuilder
+// ===========================================================================
+
+/// The id of the Jobs built by default
+pub const TEST_JOB_ID: &str = "5873c0033e78b222bec2cb2a221487cf";
+
+
+/// Builds an animation Job for tests
+#[derive(Debug, Clone)]
+pub struct JobBuilder{
+    directory: PathBuf,
+    id: String,
+    email: String,
+    renderer: Option<String>,
+    device: Option<String>,
+    image_format: Option<String>,
+    resolution: Option<Resolution>,
+    frames: Option<(usize, usize)>,
+    status: Option<Status>,
+    tasks: Vec<Task>
+}
+
+impl Default for JobBuilder{
+    fn default() -> Self{
+        JobBuilder{
+            directory: PathBuf::from("/data/blendfiles"),
+            id: TEST_JOB_ID.to_string(),
+            email: "dh@atoav.com".to_string(),
+            renderer: None,
+            device: None,
+            image_format: None,
+            resolution: None,
+            frames: None,
+            status: None,
+            tasks: Vec::new()
+        }
+    }
+}
+
+impl JobBuilder{
+    /// A Job with the id `TEST_JOB_ID` in `/data/blendfiles` for dh@atoav.com
+    #[allow(dead_code)]
+    pub fn new() -> Self{
+        Self::default()
+    }
+
+    /// Place the Job in the directory (e.g. a TempDir)
+    #[allow(dead_code)]
+    pub fn in_directory<P>(mut self, directory: P) -> Self where P: AsRef<Path>{
+        self.directory = directory.as_ref().to_path_buf();
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_id<S>(mut self, id: S) -> Self where S: Into<String>{
+        self.id = id.into();
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_email<S>(mut self, email: S) -> Self where S: Into<String>{
+        self.email = email.into();
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_renderer<S>(mut self, renderer: S) -> Self where S: Into<String>{
+        self.renderer = Some(renderer.into());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_device<S>(mut self, device: S) -> Self where S: Into<String>{
+        self.device = Some(device.into());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_image_format<S>(mut self, image_format: S) -> Self where S: Into<String>{
+        self.image_format = Some(image_format.into());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_resolution(mut self, x: usize, y: usize, scale: usize) -> Self{
+        self.resolution = Some(Resolution{ x, y, scale });
+        self
+    }
+
+    /// Render the frames from start to end (step 1, 25 fps)
+    #[allow(dead_code)]
+    pub fn with_frames(mut self, start: usize, end: usize) -> Self{
+        self.frames = Some((start, end));
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_status(mut self, status: Status) -> Self{
+        self.status = Some(status);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn with_task(mut self, task: Task) -> Self{
+        self.tasks.push(task);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn build(self) -> Job{
+        let directory = self.directory.join(&self.id);
+        let blendpath = directory.join("shot.blend");
+        let mut job = Job::new(blendpath.to_string_lossy().to_string(), self.email, true);
+        job.paths.data = directory.join("data.json").to_string_lossy().to_string();
+        if let Some(renderer) = self.renderer{
+            job.render.renderer = renderer;
+        }
+        if let Some(device) = self.device{
+            job.render.device = device;
+        }
+        if let Some(image_format) = self.image_format{
+            job.render.image_format = image_format;
+        }
+        if let Some(resolution) = self.resolution{
+            job.resolution = resolution;
+        }
+        if let Some((start, end)) = self.frames{
+            job.frames = data::Frames{ start, end, current: start, step: 1, fps: 25 };
+        }
+        if let Some(status) = self.status{
+            job.status = status;
+        }
+        job.tasks.extend(self.tasks);
+        job
+    }
+}
@@ -9,7 +9,7 @@ pub extern crate tempfile;
 
 /// Commonly used functions
 use std::path::PathBuf;
-use std::collections::{HashMap, BTreeMap};
+use std::collections::HashMap;
 use self::rand::{thread_rng, prelude::SliceRandom};
 use std::fs;
 use self::tempfile::{Builder, TempDir};
@@ -20,6 +20,12 @@ pub use self::path::*;
 pub mod blendfiles;
 pub use self::blendfiles::*;
 
+pub mod httpstub;
+pub use self::httpstub::*;
+
+pub mod jobbuilder;
+pub use self::jobbuilder::*;
+
 
 
 
@@ -55,11 +61,15 @@ pub fn get_job() -> Job {
         time: JobTime::new_deterministic_for_test(),
         status: Status::new(),
         data: HashMap::new(),
-        history: BTreeMap::new(),
+        history: History::new(),
         resolution: Default::default(),
         render: Default::default(),
         frames: Default::default(),
-        tasks: Default::default()
+        tasks: Default::default(),
+        output_template: Default::default(),
+        frame_override: Default::default(),
+        webhooks: Default::default(),
+        observers: Default::default()
     } 
 }
 
@@ -76,11 +86,15 @@ pub fn get_other_job() -> Job {
         time: JobTime::new_deterministic_for_test(),
         status: Status::new(),
         data: HashMap::new(),
-        history: BTreeMap::new(),
+        history: History::new(),
         resolution: Default::default(),
         render: Default::default(),
         frames: Default::default(),
-        tasks: Default::default()
+        tasks: Default::default(),
+        output_template: Default::default(),
+        frame_override: Default::default(),
+        webhooks: Default::default(),
+        observers: Default::default()
     } 
 }
 
@@ -98,11 +112,15 @@ pub fn get_invalid_job() -> Job {
         time: JobTime::new_deterministic_for_test(),
         status: Status::new(),
         data: HashMap::new(),
-        history: BTreeMap::new(),
+        history: History::new(),
         resolution: Default::default(),
         render: Default::default(),
         frames: Default::default(),
-        tasks: Default::default()
+        tasks: Default::default(),
+        output_template: Default::default(),
+        frame_override: Default::default(),
+        webhooks: Default::default(),
+        observers: Default::default()
     } 
 }
 
@@ -174,11 +192,15 @@ pub fn get_random_job_from<S>(source_id: S, source_filename: S) -> (Job, TempDir
         time: JobTime::new_deterministic_for_test(),
         status: Status::new(),
         data: HashMap::new(),
-        history: BTreeMap::new(),
+        history: History::new(),
         resolution: Default::default(),
         render: Default::default(),
         frames: Default::default(),
-        tasks: Default::default()
+        tasks: Default::default(),
+        output_template: Default::default(),
+        frame_override: Default::default(),
+        webhooks: Default::default(),
+        observers: Default::default()
     };
 
     // Create data.json
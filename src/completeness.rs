@@ -0,0 +1,403 @@
+//! The completeness module extends Job with the functionality to compare the \
+//! frames that exist in its frames directory with the frames it is expected \
+//! to produce (`data::Frames::as_vec()` and the frames of each Task's \
+//! BlenderCommand). A frame counts as rendered once every one of its files \
+//! (see [OutputSpec](../outputs/struct.OutputSpec.html)) exists.
+//!
+//! It does so by creating the Completeness trait
+//!
+//! ```no_run
+//! # extern crate bender_job;
+//! # use bender_job::{Job, Completeness};
+//! let mut job = Job::from_datajson("/data/blendfiles/5873c0033e78b222bec2cb2a221487cf/data.json").unwrap();
+//! let check = job.check_frames();
+//! if !check.is_complete(){
+//!     println!("Missing frames: {:?}", check.missing);
+//!     job.regenerate_missing();
+//! }
+//! job.finish_if_complete();
+//! ```
+
+use ::*;
+use std::collections::BTreeSet;
+use std::path::Path;
+use regex::Regex;
+use atomizer::Atomizer;
+use command::BlenderCommand;
+use manifest::MANIFEST_FILENAME;
+
+
+/// A framenumber that doesn't occur in any realistic template. It is used to \
+/// find the position of the frame within a filename
+const SENTINEL: usize = 987_654_321;
+
+
+
+
+// ===========================================================================
+//                                FrameCheck
+// ===========================================================================
+
+/// The result of comparing the frames directory with the expected frames. \
+/// All lists are sorted framenumbers
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FrameCheck{
+    /// Expected frames whose files all exist
+    pub rendered: Vec<usize>,
+    /// Expected frames with at least one missing file
+    pub missing: Vec<usize>,
+    /// Frames that exist in the directory, but are not expected
+    pub unexpected: Vec<usize>
+}
+
+impl FrameCheck{
+    /// Return true if no expected frame is missing (unexpected frames are \
+    /// allowed)
+    pub fn is_complete(&self) -> bool{
+        self.missing.is_empty()
+    }
+}
+
+impl fmt::Display for FrameCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} rendered, {} missing, {} unexpected",
+            self.rendered.len(), self.missing.len(), self.unexpected.len())
+    }
+}
+
+
+
+
+// ===========================================================================
+//                               Completeness
+// ===========================================================================
+
+/// This Trait is implemented by a [Job](struct.Job.html) and compares the \
+/// files in `paths.frames` with the frames the Job is expected to produce.
+pub trait Completeness{
+    fn expected_frames(&self) -> Vec<usize>;
+    fn frame_paths(&self, framenumber: usize) -> Vec<PathBuf>;
+    fn check_frames(&self) -> FrameCheck;
+    fn regenerate_missing(&mut self) -> Vec<usize>;
+    fn finish_if_complete(&mut self) -> bool;
+}
+
+impl Completeness for Job{
+    /// Return the sorted frames the Job is expected to produce: the frames \
+    /// of the blend (or the current frame for stills, or the frames of the \
+    /// frame override) and every frame of the Tasks
+    fn expected_frames(&self) -> Vec<usize>{
+        let mut frames = BTreeSet::new();
+        if !self.frame_override.is_default(){
+            frames.extend(self.effective_frames().map(|frames| frames.as_vec()).unwrap_or_default());
+        }else if self.animation && !self.frames.is_default(){
+            frames.extend(self.frames.as_vec());
+        }else if !self.animation{
+            frames.insert(self.frames.current);
+        }
+        for command in blender_commands(&self.tasks){
+            frames.extend(command.frame.keys().cloned());
+        }
+        frames.into_iter().collect()
+    }
+
+    /// Return the paths of every file the frame produces in `paths.frames`. \
+    /// Frames without a Task are named like a freshly atomized Task would \
+    /// name them
+    fn frame_paths(&self, framenumber: usize) -> Vec<PathBuf>{
+        let command = blender_commands(&self.tasks)
+                        .find(|command| command.frame.has_frame(framenumber))
+                        .cloned()
+                        .unwrap_or_else(|| self.fresh_command(framenumber));
+        self.in_frames_directory(command)
+            .paths_for_frame(framenumber)
+            .into_iter()
+            .map(|(_, path)| path)
+            .collect()
+    }
+
+    /// Scan `paths.frames` and return which of the expected frames have \
+    /// been rendered, which are missing and which frames exist without \
+    /// being expected
+    fn check_frames(&self) -> FrameCheck{
+        let expected = self.expected_frames();
+        let mut check = FrameCheck::default();
+        let mut known = BTreeSet::new();
+        for framenumber in expected.iter(){
+            let paths = self.frame_paths(*framenumber);
+            match paths.iter().all(|path| path.is_file()){
+                true => check.rendered.push(*framenumber),
+                false => check.missing.push(*framenumber)
+            }
+            known.extend(paths);
+        }
+
+        // Every command names its files the same way, so a regex per distinct
+        // command is enough to recognize frames in unknown files
+        let mut patterns: Vec<Regex> = Vec::new();
+        for command in blender_commands(&self.tasks).cloned().chain(Some(self.fresh_command(SENTINEL))){
+            for pattern in self.file_patterns(command){
+                if !patterns.iter().any(|p| p.as_str() == pattern.as_str()){
+                    patterns.push(pattern);
+                }
+            }
+        }
+        let mut unexpected = BTreeSet::new();
+        let mut files = Vec::new();
+        list_files(Path::new(&self.paths.frames), &mut files);
+        for file in files.into_iter().filter(|file| !known.contains(file)){
+            let file = file.to_string_lossy().to_string();
+            let framenumber = patterns.iter()
+                                      .filter_map(|p| p.captures(&file))
+                                      .filter_map(|c| c[1].parse::<usize>().ok())
+                                      .next();
+            if let Some(framenumber) = framenumber{
+                if expected.binary_search(&framenumber).is_err(){
+                    unexpected.insert(framenumber);
+                }
+            }
+        }
+        check.unexpected = unexpected.into_iter().collect();
+        check
+    }
+
+    /// Create new single frame Tasks for every missing frame that no \
+    /// waiting, queued, running or paused Task is going to render and return \
+    /// these frames. Ended Tasks whose frames are all regenerated are dropped, \
+    /// all other Tasks are left untouched.
+    fn regenerate_missing(&mut self) -> Vec<usize>{
+        let live: BTreeSet<usize> = self.tasks.iter()
+                                              .filter(|task| !task.is_ended())
+                                              .filter_map(|task| match task.command{
+                                                  Command::Blender(ref command) => Some(command.frame.keys().cloned()),
+                                                  _ => None
+                                              })
+                                              .flatten()
+                                              .collect();
+        let missing: Vec<usize> = self.check_frames()
+                                      .missing
+                                      .into_iter()
+                                      .filter(|framenumber| !live.contains(framenumber))
+                                      .collect();
+        if missing.is_empty(){
+            return missing;
+        }
+        self.tasks.retain(|task| match task.command{
+            Command::Blender(ref command) if task.is_ended() => {
+                !command.frame.keys().all(|framenumber| missing.binary_search(framenumber).is_ok())
+            },
+            _ => true
+        });
+        let tasks = self.generate_commands_for(&missing, 1);
+        let message = format!("Regenerated {} tasks for missing frames: {:?}", tasks.len(), missing);
+        self.tasks.extend(tasks);
+        self.add_history(message.as_str());
+        missing
+    }
+
+    /// Finish the Job if every expected frame exists in `paths.frames`. \
+    /// Return true if the Job is finished
+    fn finish_if_complete(&mut self) -> bool{
+        let check = self.check_frames();
+        if check.is_complete(){
+            self.finish();
+        }else{
+            let message = format!("Not finishing Job: {} of {} frames missing", check.missing.len(), check.missing.len() + check.rendered.len());
+            self.add_history_debounced(message.as_str());
+        }
+        self.is_finished()
+    }
+}
+
+
+impl Job{
+    /// A BlenderCommand for the frame, as the Atomizer would create it
+    fn fresh_command(&self, framenumber: usize) -> BlenderCommand{
+        match self.generate_commands_for(&[framenumber], 1).pop_front().map(|task| task.command){
+            Some(Command::Blender(command)) => command,
+            _ => unreachable!("The Atomizer only creates blender commands")
+        }
+    }
+
+    /// Point the command at `paths.frames`
+    fn in_frames_directory(&self, mut command: BlenderCommand) -> BlenderCommand{
+        command.outpath = Some(self.paths.frames.clone());
+        if command.blendfile.is_none(){
+            command.blendfile = Some(self.paths.blend.clone());
+        }
+        command
+    }
+
+    /// Return a regex for each file a frame of the command produces, which \
+    /// captures the framenumber
+    fn file_patterns(&self, command: BlenderCommand) -> Vec<Regex>{
+        let command = self.in_frames_directory(command);
+        command.paths_for_frame(SENTINEL)
+               .into_iter()
+               .filter_map(|(_, path)| {
+                   let path = regex::escape(&path.to_string_lossy());
+                   // Allow any padding of the frame
+                   let pattern = path.replacen(&SENTINEL.to_string(), r"(\d+)", 1);
+                   Regex::new(&format!("^{}$", pattern)).ok()
+               })
+               .collect()
+    }
+}
+
+
+/// Iterate over the BlenderCommands of the Tasks
+fn blender_commands(tasks: &Tasks) -> impl Iterator<Item=&BlenderCommand>{
+    tasks.iter().filter_map(|task| match task.command{
+        Command::Blender(ref command) => Some(command),
+        _ => None
+    })
+}
+
+
+/// Collect all files below the directory, skipping hidden entries (like the \
+/// temporary directories of atomic writes) and the manifest
+fn list_files(directory: &Path, files: &mut Vec<PathBuf>){
+    if let Ok(entries) = fs::read_dir(directory){
+        for entry in entries.filter_map(|entry| entry.ok()){
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') || name == MANIFEST_FILENAME{
+                continue;
+            }
+            let path = entry.path();
+            if path.is_dir(){
+                list_files(&path, files);
+            }else{
+                files.push(path);
+            }
+        }
+    }
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::tempfile::TempDir;
+    use common::{JobBuilder, TEST_JOB_ID};
+
+    fn job(dir: &TempDir) -> Job{
+        let mut job = JobBuilder::new().in_directory(dir.path().join("blendfiles"))
+                                       .with_image_format("PNG")
+                                       .with_frames(1, 5)
+                                       .build();
+        job.tasks = job.generate_commands(1);
+        fs::create_dir_all(&job.paths.frames).unwrap();
+        job
+    }
+
+    fn touch(job: &Job, name: &str){
+        fs::write(PathBuf::from(&job.paths.frames).join(name), "png").unwrap();
+    }
+
+    #[test]
+    fn check() {
+        let dir = TempDir::new().unwrap();
+        let job = job(&dir);
+        assert_eq!(job.expected_frames(), vec![1, 2, 3, 4, 5]);
+        touch(&job, "000001.png");
+        touch(&job, "000003.png");
+        touch(&job, "000007.png");
+        touch(&job, "0000012.png");
+        touch(&job, "notes.txt");
+        touch(&job, MANIFEST_FILENAME);
+        let check = job.check_frames();
+        assert_eq!(check.rendered, vec![1, 3]);
+        assert_eq!(check.missing, vec![2, 4, 5]);
+        assert_eq!(check.unexpected, vec![7, 12]);
+        assert!(!check.is_complete());
+        assert_eq!(check.to_string(), "2 rendered, 3 missing, 2 unexpected");
+    }
+
+    #[test]
+    fn outputs() {
+        let dir = TempDir::new().unwrap();
+        let mut job = job(&dir);
+        job.render.outputs = vec![OutputSpec::view("_L"), OutputSpec::view("_R")];
+        job.tasks = job.generate_commands(1);
+        touch(&job, "000001_L.png");
+        touch(&job, "000001_R.png");
+        touch(&job, "000002_L.png");
+        touch(&job, "000009_R.png");
+        let check = job.check_frames();
+        assert_eq!(check.rendered, vec![1]);
+        assert_eq!(check.missing, vec![2, 3, 4, 5]);
+        assert_eq!(check.unexpected, vec![9]);
+    }
+
+    #[test]
+    fn regenerate_and_finish() {
+        let dir = TempDir::new().unwrap();
+        let mut job = job(&dir);
+        let mut task = Task::new_blender_range(1, 5, 1, "PNG", TEST_JOB_ID);
+        task.queue();
+        task.start();
+        task.finish();
+        job.tasks = VecDeque::from(vec![task]);
+        touch(&job, "000001.png");
+        touch(&job, "000002.png");
+        touch(&job, "000004.png");
+        job.status.validate().unwrap();
+        job.status.scan().unwrap();
+        job.status.atomize().unwrap();
+        job.status.queue().unwrap();
+        job.status.run().unwrap();
+
+        assert!(!job.finish_if_complete());
+        assert!(!job.is_finished());
+
+        assert_eq!(job.regenerate_missing(), vec![3, 5]);
+        assert_eq!(job.tasks.len(), 3);
+        let frames: Vec<Vec<usize>> = blender_commands(&job.tasks).map(|c| c.frame.keys().cloned().collect()).collect();
+        assert_eq!(frames, vec![vec![1, 2, 3, 4, 5], vec![3], vec![5]]);
+        assert_eq!(job.expected_frames(), vec![1, 2, 3, 4, 5]);
+
+        touch(&job, "000003.png");
+        touch(&job, "000005.png");
+        assert!(job.regenerate_missing().is_empty());
+        assert!(job.finish_if_complete());
+        assert!(job.is_finished());
+    }
+
+    #[test]
+    fn regenerate_keeps_live_tasks() {
+        let dir = TempDir::new().unwrap();
+        let mut job = job(&dir);
+        let mut finished = Task::new_blender_range(1, 2, 1, "PNG", TEST_JOB_ID);
+        finished.queue();
+        finished.start();
+        finished.finish();
+        let mut running = Task::new_blender_range(3, 4, 1, "PNG", TEST_JOB_ID);
+        running.construct(job.paths.blend.as_str(), "/out");
+        running.queue();
+        running.start();
+        let mut errored = Task::new_blender_single(5, "PNG", TEST_JOB_ID);
+        errored.error();
+        job.tasks = VecDeque::from(vec![finished, running.clone(), errored]);
+
+        // Frames of the running Task are not missing, they are not rendered yet
+        assert_eq!(job.regenerate_missing(), vec![1, 2, 5]);
+        assert_eq!(job.tasks.len(), 4);
+        assert_eq!(job.tasks[0].id, running.id);
+        assert!(job.tasks[0].is_running());
+        assert_eq!(job.tasks[0].command, running.command);
+        let frames: Vec<Vec<usize>> = blender_commands(&job.tasks).map(|c| c.frame.keys().cloned().collect()).collect();
+        assert_eq!(frames, vec![vec![3, 4], vec![1], vec![2], vec![5]]);
+
+        // The regenerated Tasks are waiting, nothing is regenerated twice
+        assert!(job.regenerate_missing().is_empty());
+        assert_eq!(job.tasks.len(), 4);
+    }
+}
@@ -0,0 +1,47 @@
+//! The csv module holds the few helpers shared by everything that writes \
+//! CSV, e.g. the rows of a [JobReport](../report/struct.JobReport.html) and \
+//! the exported [History](../history/struct.History.html).
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::csv::csv_escape;
+//! assert_eq!(csv_escape("1,2"), "\"1,2\"");
+//! ```
+
+
+
+
+// ===========================================================================
+//                                   csv
+// ===========================================================================
+
+/// Quote a CSV field if it contains a comma, a quote or a line break
+pub fn csv_escape(field: &str) -> String{
+    if field.contains(&[',', '"', '\n', '\r'][..]){
+        format!("\"{}\"", field.replace('"', "\"\""))
+    }else{
+        field.to_string()
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape() {
+        assert_eq!(csv_escape("plain"), "plain");
+        assert_eq!(csv_escape(""), "");
+        assert_eq!(csv_escape("a,b"), "\"a,b\"");
+        assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_escape("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_escape("cr\r"), "\"cr\r\"");
+    }
+}
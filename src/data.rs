@@ -1,5 +1,5 @@
 //! The data module defines multiple Structs used by the job to store data. E.g. \
-//! Render, Frames and Resolution
+//! Render, Frames, FrameOverride and Resolution
 
 use ::*;
 
@@ -16,20 +16,33 @@ use ::*;
 /// Typically it is deserialized by the Job with empty default values until \
 /// the information is read via the Jobs [gaffer](trait.Gaffer.html) trait using \
 /// its scan_and_optimize() method.
+///
+/// The outputs list the files blender writes per frame (see [OutputSpec](outputs/struct.OutputSpec.html)). \
+/// It is empty if blender writes a single file per frame.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct Render{
     pub renderer: String,
     pub cuda: bool,
     pub device: String,
     pub image_format: String,
-    pub uses_compositing: bool
+    pub uses_compositing: bool,
+    #[serde(default)]
+    pub scene: String,
+    #[serde(default)]
+    pub camera: String,
+    #[serde(default)]
+    pub outputs: Vec<OutputSpec>
 }
 
 impl Render{
-    /// Check if the Format is valid
+    /// Check if the Format is valid (see [ImageFormat](imageformat/enum.ImageFormat.html))
     pub fn valid_format(&self) -> bool{
-        let valid_formats: [&str; 12] = ["PNG", "BMP", "JPEG", "JPEG2000", "TARGA", "TARGA_RAW", "CINEON", "DPX", "OPEN_EXR_MULTILAYER", "OPEN_EXR", "HDR", "TIFF"];
-        valid_formats.contains(&self.image_format.as_str())
+        self.format().is_some()
+    }
+
+    /// Return the ImageFormat, or None if the image_format is not allowed
+    pub fn format(&self) -> Option<ImageFormat>{
+        ImageFormat::from_blender(&self.image_format)
     }
 
     /// Return true if self has still the default value
@@ -44,6 +57,9 @@ impl Render{
         self.device = other.device.to_string().clone();
         self.image_format = other.image_format.to_string().clone();
         self.uses_compositing = self.uses_compositing && other.uses_compositing;
+        if self.scene.is_empty() { self.scene = other.scene.clone(); }
+        if self.camera.is_empty() { self.camera = other.camera.clone(); }
+        if self.outputs.is_empty() { self.outputs = other.outputs.clone(); }
     }
 }
 
@@ -105,6 +121,139 @@ impl Frames {
 
 
 
+// ===========================================================================
+//                              data::FrameOverride
+// ===========================================================================
+
+/// The FrameOverride struct stores the frames a user requested when \
+/// submitting a [Job](struct.Job.html). It is kept separately from the \
+/// scanned [Frames](struct.Frames.html) and applied by the Jobs \
+/// [atomizer](trait.Atomizer.html). Either parts of the range (start, end, \
+/// step), a list of frames (see [FrameSpec](framespec/struct.FrameSpec.html)) \
+/// or a single current frame can be overridden. Unset values are taken from \
+/// the scanned Frames.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct FrameOverride {
+    #[serde(default)]
+    pub start: Option<usize>,
+    #[serde(default)]
+    pub end: Option<usize>,
+    #[serde(default)]
+    pub step: Option<usize>,
+    #[serde(default)]
+    pub frames: Option<FrameSpec>,
+    #[serde(default)]
+    pub current: Option<usize>
+}
+
+impl FrameOverride {
+    /// Override the range of the scene. Pass None to keep a scanned value
+    pub fn range(start: Option<usize>, end: Option<usize>, step: Option<usize>) -> Self{
+        FrameOverride{ start, end, step, ..Default::default() }
+    }
+
+    /// Render only the given frames
+    pub fn list(frames: FrameSpec) -> Self{
+        FrameOverride{ frames: Some(frames), ..Default::default() }
+    }
+
+    /// Render only a single frame
+    pub fn current(frame: usize) -> Self{
+        FrameOverride{ current: Some(frame), ..Default::default() }
+    }
+
+    /// Return true if nothing is overridden
+    pub fn is_default(&self) -> bool{
+        self == &Self::default()
+    }
+
+    /// Return true if parts of the range are overridden
+    pub fn is_range(&self) -> bool{
+        self.start.is_some() || self.end.is_some() || self.step.is_some()
+    }
+
+    /// Return the frames to render given the scanned frames of the scene. \
+    /// Return an Error if the override mixes range, list and current frame, \
+    /// if the range is invalid, if any frame is outside of the scene or if \
+    /// a range has more than `framespec::MAX_FRAMES` frames
+    pub fn apply(&self, scanned: &Frames, animation: bool) -> GenResult<FrameSpec>{
+        let kinds = [self.is_range(), self.frames.is_some(), self.current.is_some()];
+        if kinds.iter().filter(|k| **k).count() > 1{
+            return Err(From::from(format!("Error: The frame override \"{}\" mixes a range, a frame list and a current frame", self)));
+        }
+        let frames = if let Some(ref frames) = self.frames{
+            frames.clone()
+        }else if let Some(current) = self.current{
+            FrameSpec::from_range(current, current, 1)
+        }else if self.is_range(){
+            let start = self.start.unwrap_or(scanned.start);
+            let end = self.end.unwrap_or(scanned.end);
+            let step = self.step.unwrap_or(scanned.step);
+            if step == 0{
+                return Err(From::from(format!("Error: The frame override \"{}\" has a step of 0", self)));
+            }
+            if start > end{
+                return Err(From::from(format!("Error: The frame override \"{}\" starts after it ends ({} > {})", self, start, end)));
+            }
+            // Check the bounds before the frames are collected, the range
+            // may be arbitrarily large
+            if !scanned.is_default() && (start < scanned.start || end > scanned.end){
+                let message = format!("Error: The frame override \"{}\" selects frames outside of the scene ({}-{}): {}-{}",
+                    self, scanned.start, scanned.end, start, end);
+                return Err(From::from(message));
+            }
+            match (framespec::FrameRun{ start, end, step }).checked_count(){
+                Some(count) if count <= framespec::MAX_FRAMES => FrameSpec::from_range(start, end, step),
+                _ => return Err(From::from(format!("Error: The frame override \"{}\" selects more than {} frames", self, framespec::MAX_FRAMES)))
+            }
+        }else if animation{
+            FrameSpec::from(scanned)
+        }else{
+            FrameSpec::from_range(scanned.current, scanned.current, 1)
+        };
+
+        if frames.is_empty(){
+            return Err(From::from(format!("Error: The frame override \"{}\" selects no frames", self)));
+        }
+        if !self.is_default() && !scanned.is_default(){
+            let outside: FrameSpec = frames.iter().filter(|f| *f < scanned.start || *f > scanned.end).collect();
+            if !outside.is_empty(){
+                let message = format!("Error: The frame override \"{}\" selects frames outside of the scene ({}-{}): {}",
+                    self, scanned.start, scanned.end, outside);
+                return Err(From::from(message));
+            }
+        }
+        Ok(frames)
+    }
+
+    /// Merge two FrameOverride structs, if self is default and the other isn't
+    pub fn merge(&mut self, other: &Self){
+        if self.is_default(){
+            *self = other.clone();
+        }
+    }
+}
+
+impl fmt::Display for FrameOverride {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(start) = self.start { parts.push(format!("start {}", start)); }
+        if let Some(end) = self.end { parts.push(format!("end {}", end)); }
+        if let Some(step) = self.step { parts.push(format!("step {}", step)); }
+        if let Some(ref frames) = self.frames { parts.push(format!("frames {}", frames)); }
+        if let Some(current) = self.current { parts.push(format!("current frame {}", current)); }
+        if parts.is_empty(){
+            write!(f, "none")
+        }else{
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+
+
+
+
 // ===========================================================================
 //                              data::Resolution
 // ===========================================================================
@@ -286,6 +435,60 @@ mod frames {
 
 }
 
+// =========================== TEST FRAME OVERRIDE ===========================
+#[cfg(test)]
+mod frame_override {
+    use ::*;
+    use data::FrameOverride;
+
+    fn scanned() -> data::Frames{
+        data::Frames{ start: 1, end: 100, current: 12, step: 1, fps: 25 }
+    }
+
+    #[test]
+    fn no_override() {
+        let o = FrameOverride::default();
+        assert!(o.is_default());
+        assert_eq!(o.apply(&scanned(), true).unwrap().len(), 100);
+        assert_eq!(o.apply(&scanned(), false).unwrap().as_vec(), vec![12]);
+        assert_eq!(o.to_string(), "none");
+    }
+
+    #[test]
+    fn range() {
+        let o = FrameOverride::range(Some(11), None, Some(10));
+        assert_eq!(o.apply(&scanned(), false).unwrap().to_string(), "11-91x10");
+        assert_eq!(o.to_string(), "start 11, step 10");
+        assert!(FrameOverride::range(None, None, Some(0)).apply(&scanned(), true).is_err());
+        assert!(FrameOverride::range(Some(50), Some(40), None).apply(&scanned(), true).is_err());
+        assert!(FrameOverride::range(None, Some(101), None).apply(&scanned(), true).is_err());
+        assert!(FrameOverride::range(None, Some(usize::MAX), None).apply(&scanned(), true).is_err());
+        assert!(FrameOverride::range(Some(0), Some(1_000_000_000_000), None).apply(&scanned(), true).is_err());
+        let unscanned = data::Frames::default();
+        assert!(FrameOverride::range(Some(1), Some(usize::MAX), Some(1)).apply(&unscanned, true).is_err());
+        assert!(FrameOverride::range(Some(1), Some(2_000_000), Some(1)).apply(&unscanned, true).is_err());
+        assert_eq!(FrameOverride::range(Some(1), Some(2_000_000), Some(4)).apply(&unscanned, true).unwrap().len(), 500_000);
+    }
+
+    #[test]
+    fn list_and_current() {
+        let o = FrameOverride::list(FrameSpec::parse("1-10,50").unwrap());
+        assert_eq!(o.apply(&scanned(), true).unwrap().len(), 11);
+        assert!(FrameOverride::list(FrameSpec::parse("99-101").unwrap()).apply(&scanned(), true).is_err());
+        assert!(FrameOverride::list(FrameSpec::new()).apply(&scanned(), true).is_err());
+        assert_eq!(FrameOverride::current(42).apply(&scanned(), true).unwrap().as_vec(), vec![42]);
+        let mut mixed = FrameOverride::current(42);
+        mixed.start = Some(1);
+        assert!(mixed.apply(&scanned(), true).is_err());
+    }
+
+    #[test]
+    fn deserialize() {
+        let o: FrameOverride = serde_json::from_str("{\"frames\": \"1-3,7\"}").unwrap();
+        assert_eq!(o, FrameOverride::list(FrameSpec::parse("1-3,7").unwrap()));
+    }
+}
+
 // ============================== TEST RESOLUTION ============================
 #[cfg(test)]
 mod resolution {
@@ -0,0 +1,447 @@
+//! The estimate module predicts how long a Job (or a single Task) will take \
+//! to render. The [Estimator](struct.Estimator.html) derives the time per \
+//! frame from (in this order):
+//! 1. the frames of the Job itself that have already been rendered
+//! 2. the finished Tasks of earlier Jobs with a similar [RenderProfile](struct.RenderProfile.html) \
+//!    (same renderer and device, comparable resolution), scaled by the \
+//!    number of pixels
+//! 3. a rough per-megapixel baseline for the renderer and device
+//!
+//! Every [Estimate](struct.Estimate.html) carries a 90% interval and a note \
+//! on how it was derived.
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::{Job, Resolution};
+//! # use bender_job::estimate::{Estimator, EstimateMethod};
+//! let mut job = Job::new("/data/blendfiles/5873c0033e78b222bec2cb2a221487cf/shot.blend", "dh@atoav.com", true);
+//! job.render.renderer = "CYCLES".to_string();
+//! job.resolution = Resolution{ x: 1920, y: 1080, scale: 100 };
+//! job.frames.start = 1;
+//! job.frames.end = 250;
+//! job.frames.step = 1;
+//!
+//! let estimate = Estimator::new().estimate(&job);
+//! assert_eq!(estimate.method, EstimateMethod::Baseline);
+//! assert_eq!(estimate.frames, 250);
+//! assert!(estimate.per_frame_low < estimate.per_frame && estimate.per_frame < estimate.per_frame_high);
+//! println!("{}", estimate);
+//! ```
+
+use ::*;
+use std::time::Duration;
+
+
+/// The z-value of a two-sided 90% interval of a normal distribution
+const Z_90: f64 = 1.645;
+
+/// The minimum number of rendered frames before measured times are trusted
+pub const MIN_SAMPLES: usize = 3;
+
+/// Profiles are similar if their pixel counts differ by at most this factor
+pub const MAX_PIXEL_RATIO: f64 = 4.0;
+
+/// The factor the bounds of a baseline estimate are away from the estimate
+const BASELINE_SPREAD: f64 = 4.0;
+
+
+
+
+// ===========================================================================
+//                               RenderProfile
+// ===========================================================================
+
+/// The properties of a Job that influence its render time the most
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RenderProfile{
+    /// The renderer (e.g. "CYCLES"), uppercase
+    pub renderer: String,
+    /// "GPU" or "CPU"
+    pub device: String,
+    /// The number of rendered pixels per frame (after scaling)
+    pub pixels: i64
+}
+
+impl RenderProfile{
+    /// Read the profile of a Job. Cycles only renders on the GPU if CUDA \
+    /// could be activated
+    pub fn from_job(job: &Job) -> Self{
+        let gpu = job.render.cuda && job.render.device.eq_ignore_ascii_case("GPU");
+        RenderProfile{
+            renderer: job.render.renderer.to_uppercase(),
+            device: if gpu { "GPU".to_string() } else { "CPU".to_string() },
+            pixels: job.resolution.pixels()
+        }
+    }
+
+    /// Return the pixels in megapixels
+    pub fn megapixels(&self) -> f64{
+        self.pixels as f64 / 1_000_000.0
+    }
+
+    /// Return true if renderer and device are the same and the pixel counts \
+    /// differ by at most `MAX_PIXEL_RATIO`
+    pub fn is_similar(&self, other: &Self) -> bool{
+        self.renderer == other.renderer
+        && self.device == other.device
+        && self.pixels > 0 && other.pixels > 0
+        && self.pixel_ratio(other).max(1.0 / self.pixel_ratio(other)) <= MAX_PIXEL_RATIO
+    }
+
+    /// Return the pixels of self relative to the pixels of other
+    pub fn pixel_ratio(&self, other: &Self) -> f64{
+        self.pixels as f64 / other.pixels as f64
+    }
+
+    /// A rough guess of the render time per megapixel in seconds, used if \
+    /// nothing similar has been rendered yet
+    pub fn baseline_seconds_per_megapixel(&self) -> f64{
+        match (self.renderer.as_str(), self.device.as_str()){
+            ("CYCLES", "GPU") => 15.0,
+            ("CYCLES", _) => 60.0,
+            ("BLENDER_EEVEE", _) => 2.0,
+            ("BLENDER_WORKBENCH", _) | ("BLENDER_OPENGL", _) => 0.5,
+            ("BLENDER_RENDER", _) => 10.0,
+            _ => 30.0
+        }
+    }
+}
+
+impl fmt::Display for RenderProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} on {} at {:.1} megapixels", self.renderer, self.device, self.megapixels())
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                 Estimate
+// ===========================================================================
+
+/// Where the numbers of an Estimate come from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum EstimateMethod{
+    /// Frames of the Job itself have been rendered
+    Measured,
+    /// Finished Tasks of similar Jobs
+    Historical,
+    /// A per-megapixel guess for the renderer
+    Baseline
+}
+
+
+/// A predicted render time. All times are in seconds, the bounds form a 90% \
+/// interval
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Estimate{
+    pub frames: usize,
+    pub per_frame: f64,
+    pub per_frame_low: f64,
+    pub per_frame_high: f64,
+    pub total: f64,
+    pub total_low: f64,
+    pub total_high: f64,
+    /// The number of rendered frames the estimate is based on
+    pub samples: usize,
+    pub method: EstimateMethod,
+    pub note: String
+}
+
+impl Estimate{
+    /// Create an Estimate for a number of frames from the time per frame \
+    /// and its bounds
+    fn new(frames: usize, per_frame: (f64, f64, f64), samples: usize, method: EstimateMethod, note: String) -> Self{
+        let (low, mean, high) = per_frame;
+        let n = frames as f64;
+        Estimate{
+            frames,
+            per_frame: mean,
+            per_frame_low: low,
+            per_frame_high: high,
+            total: mean * n,
+            total_low: low * n,
+            total_high: high * n,
+            samples,
+            method,
+            note
+        }
+    }
+
+    /// Return the same estimate for a different number of frames
+    pub fn for_frames(&self, frames: usize) -> Self{
+        Estimate::new(frames, (self.per_frame_low, self.per_frame, self.per_frame_high),
+                      self.samples, self.method, self.note.clone())
+    }
+
+    /// Return the estimated total as Duration
+    pub fn total_duration(&self) -> Duration{
+        Duration::from_millis((self.total * 1000.0).round() as u64)
+    }
+
+    /// Return the estimated time per frame as Duration
+    pub fn per_frame_duration(&self) -> Duration{
+        Duration::from_millis((self.per_frame * 1000.0).round() as u64)
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} frames at {:.1}s ({:.1}s-{:.1}s) per frame: {} ({}-{}). {}",
+            self.frames, self.per_frame, self.per_frame_low, self.per_frame_high,
+            format_seconds(self.total), format_seconds(self.total_low), format_seconds(self.total_high),
+            self.note)
+    }
+}
+
+
+/// Format seconds like `1h 02m`, `4m 05s` or `12s`
+pub fn format_seconds(seconds: f64) -> String{
+    let seconds = seconds.round() as u64;
+    match (seconds / 3600, seconds % 3600 / 60, seconds % 60){
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, _) => format!("{}h {:02}m", h, m)
+    }
+}
+
+
+/// Convert a chrono Duration to seconds
+pub fn to_seconds(duration: chrono::Duration) -> f64{
+    duration.num_milliseconds() as f64 / 1000.0
+}
+
+
+
+
+// ===========================================================================
+//                                 Estimator
+// ===========================================================================
+
+/// The measured render time per frame of a finished Task
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Sample{
+    pub profile: RenderProfile,
+    pub seconds: f64
+}
+
+
+/// Predicts render times from the history of finished Jobs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Estimator{
+    pub samples: Vec<Sample>
+}
+
+impl Estimator{
+    /// Create an Estimator without history
+    pub fn new() -> Self{
+        Self::default()
+    }
+
+    /// Create an Estimator with the history of the given Jobs
+    pub fn from_jobs<'a, I>(jobs: I) -> Self where I: IntoIterator<Item=&'a Job>{
+        let mut estimator = Self::new();
+        jobs.into_iter().for_each(|job| estimator.add_job(job));
+        estimator
+    }
+
+    /// Add the render times of the finished Tasks of the Job to the history
+    pub fn add_job(&mut self, job: &Job){
+        let profile = RenderProfile::from_job(job);
+        for seconds in finished_frame_times(job){
+            self.samples.push(Sample{ profile: profile.clone(), seconds });
+        }
+    }
+
+    /// Estimate the render time of all frames of the Job
+    pub fn estimate(&self, job: &Job) -> Estimate{
+        let frames = match job.effective_frames(){
+            Ok(ref frames) if !frames.is_empty() => frames.len(),
+            _ => job.tasks.iter().map(task_frames).sum()
+        };
+        self.estimate_frames(job, frames)
+    }
+
+    /// Estimate the render time of a single Task of the Job
+    pub fn estimate_task(&self, job: &Job, task: &Task) -> Estimate{
+        self.estimate_frames(job, task_frames(task))
+    }
+
+    /// Estimate the render time of a number of frames of the Job
+    pub fn estimate_frames(&self, job: &Job, frames: usize) -> Estimate{
+        let profile = RenderProfile::from_job(job);
+
+        // 1. Rendered frames of the Job itself
+        let measured = finished_frame_times(job);
+        if measured.len() >= MIN_SAMPLES{
+            let note = format!("Based on {} rendered frames of this job", measured.len());
+            return Estimate::new(frames, interval(&measured), measured.len(), EstimateMethod::Measured, note);
+        }
+
+        // 2. Similar Jobs, scaled to the pixels of this Job
+        let similar: Vec<f64> = self.samples.iter()
+            .filter(|sample| profile.is_similar(&sample.profile))
+            .map(|sample| sample.seconds * profile.pixel_ratio(&sample.profile))
+            .collect();
+        if similar.len() >= MIN_SAMPLES{
+            let note = format!("Based on {} frames of similar renders ({}), scaled by resolution", similar.len(), profile);
+            return Estimate::new(frames, interval(&similar), similar.len(), EstimateMethod::Historical, note);
+        }
+
+        // 3. A guess
+        let per_frame = profile.baseline_seconds_per_megapixel() * profile.megapixels();
+        let note = format!("Rough guess for {}, nothing similar has been rendered yet", profile);
+        Estimate::new(frames, (per_frame / BASELINE_SPREAD, per_frame, per_frame * BASELINE_SPREAD), 0, EstimateMethod::Baseline, note)
+    }
+}
+
+
+/// Return the render time per frame in seconds of every frame of the \
+/// finished Tasks of the Job. Frames without a recorded render time use the \
+/// duration of their Task divided by its frames
+fn finished_frame_times(job: &Job) -> Vec<f64>{
+    let mut times = Vec::new();
+    for task in job.tasks.iter().filter(|task| task.is_finished()){
+        let command = match task.command{
+            Command::Blender(ref command) => command,
+            _ => continue
+        };
+        let count = command.frame.len().max(1);
+        let fallback = task.time.duration()
+                           .and_then(|d| d.to_std().ok())
+                           .map(|d| d.as_secs_f64() / count as f64);
+        for frame in command.frame.values(){
+            match frame.get_render_time().map(|d| d.as_secs_f64()).or(fallback){
+                Some(seconds) => times.push(seconds),
+                None => continue
+            }
+        }
+    }
+    times
+}
+
+
+/// Return the number of frames the Task renders
+fn task_frames(task: &Task) -> usize{
+    match task.command{
+        Command::Blender(ref command) => command.frame.len(),
+        _ => 0
+    }
+}
+
+
+/// Return the lower bound, mean and upper bound of a 90% interval of the \
+/// samples (assuming normally distributed render times)
+fn interval(samples: &[f64]) -> (f64, f64, f64){
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1.0).max(1.0);
+    let spread = Z_90 * variance.sqrt();
+    ((mean - spread).max(0.0), mean, mean + spread)
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{JobBuilder, TEST_JOB_ID};
+
+    fn job(renderer: &str, x: usize, y: usize) -> Job{
+        let mut job = JobBuilder::new().with_renderer(renderer)
+                                       .with_device("GPU")
+                                       .with_resolution(x, y, 100)
+                                       .with_frames(1, 100)
+                                       .build();
+        job.render.cuda = true;
+        job
+    }
+
+    /// Add a finished single frame task with the given render time
+    fn rendered(job: &mut Job, frame: usize, seconds: u64){
+        let mut task = Task::new_blender_single(frame, "PNG", TEST_JOB_ID);
+        if let Command::Blender(ref mut command) = task.command{
+            command.frame.get_mut(&frame).unwrap().set_render_time(Duration::from_secs(seconds));
+        }
+        task.queue();
+        task.start();
+        task.finish();
+        job.tasks.push_back(task);
+    }
+
+    #[test]
+    fn profile() {
+        let mut j = job("cycles", 1920, 1080);
+        let profile = RenderProfile::from_job(&j);
+        assert_eq!(profile.renderer, "CYCLES");
+        assert_eq!(profile.device, "GPU");
+        assert_eq!(profile.to_string(), "CYCLES on GPU at 2.1 megapixels");
+        j.render.cuda = false;
+        assert_eq!(RenderProfile::from_job(&j).device, "CPU");
+        assert!(!RenderProfile::from_job(&j).is_similar(&profile));
+        assert!(RenderProfile::from_job(&job("CYCLES", 960, 540)).is_similar(&profile));
+        assert!(!RenderProfile::from_job(&job("CYCLES", 480, 270)).is_similar(&profile));
+    }
+
+    #[test]
+    fn baseline() {
+        let estimate = Estimator::new().estimate(&job("BLENDER_EEVEE", 1000, 1000));
+        assert_eq!(estimate.method, EstimateMethod::Baseline);
+        assert_eq!(estimate.frames, 100);
+        assert_eq!(estimate.per_frame, 2.0);
+        assert_eq!(estimate.total, 200.0);
+        assert_eq!((estimate.per_frame_low, estimate.per_frame_high), (0.5, 8.0));
+        assert_eq!(estimate.samples, 0);
+    }
+
+    #[test]
+    fn historical() {
+        let mut old = job("CYCLES", 1920, 1080);
+        rendered(&mut old, 1, 10);
+        rendered(&mut old, 2, 12);
+        rendered(&mut old, 3, 14);
+        let mut other = job("BLENDER_EEVEE", 1920, 1080);
+        rendered(&mut other, 1, 1000);
+        let estimator = Estimator::from_jobs(vec![&old, &other]);
+        assert_eq!(estimator.samples.len(), 4);
+
+        // Half the resolution renders in half the time
+        let estimate = estimator.estimate(&job("CYCLES", 1920, 540));
+        assert_eq!(estimate.method, EstimateMethod::Historical);
+        assert_eq!(estimate.samples, 3);
+        assert_eq!(estimate.per_frame, 6.0);
+        assert!((estimate.per_frame_high - 7.645).abs() < 0.001);
+        assert_eq!(estimate.total, 600.0);
+        assert!(estimate.note.starts_with("Based on 3 frames of similar renders"));
+    }
+
+    #[test]
+    fn measured() {
+        let mut j = job("CYCLES", 1920, 1080);
+        rendered(&mut j, 1, 30);
+        rendered(&mut j, 2, 30);
+        rendered(&mut j, 3, 30);
+        let estimate = Estimator::new().estimate(&j);
+        assert_eq!(estimate.method, EstimateMethod::Measured);
+        assert_eq!((estimate.per_frame_low, estimate.per_frame, estimate.per_frame_high), (30.0, 30.0, 30.0));
+        assert_eq!(estimate.total_duration(), Duration::from_secs(3000));
+        assert_eq!(Estimator::new().estimate_task(&j, &j.tasks[0]).total, 30.0);
+        assert_eq!(estimate.for_frames(2).total, 60.0);
+        assert_eq!(estimate.to_string(), "100 frames at 30.0s (30.0s-30.0s) per frame: 50m 00s (50m 00s-50m 00s). Based on 3 rendered frames of this job");
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format_seconds(12.4), "12s");
+        assert_eq!(format_seconds(245.0), "4m 05s");
+        assert_eq!(format_seconds(3720.0), "1h 02m");
+    }
+}
@@ -0,0 +1,447 @@
+//! The executor module runs the [Command](../command/enum.Command.html) of a \
+//! [Task](../task/struct.Task.html) as a local child process. While the \
+//! process runs its stdout and stderr are streamed line by line, and the \
+//! Task's Status and JobTime are updated according to how the process ended.
+//!
+//! ```no_run
+//! # extern crate bender_job;
+//! # use bender_job::Task;
+//! # use bender_job::executor::{Executor, Output};
+//! # use std::time::Duration;
+//! let mut task = Task::new_blender_single(121, "PNG", "55067970443c49eaafdb60541fbde157");
+//! task.construct("my/blend/file.blend", "some/out/folder");
+//!
+//! let executor = Executor::new().with_timeout(Duration::from_secs(3600));
+//! let execution = executor.run_with(&mut task, |output| {
+//!     if let Output::Stdout(line) = output { println!("{}", line) }
+//! }).expect("Couldn't execute the Task");
+//!
+//! println!("Task ended with {:?} ({:?})", execution.outcome, task.status);
+//! ```
+
+use ::*;
+use std::io::{BufRead, BufReader, Read};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+
+
+// ===========================================================================
+//                                 Output
+// ===========================================================================
+
+/// A single line of output produced by the child process
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output{
+    Stdout(String),
+    Stderr(String)
+}
+
+impl Output{
+    /// Return the line without the information about the stream
+    pub fn line(&self) -> &str{
+        match self{
+            Output::Stdout(line) => line.as_str(),
+            Output::Stderr(line) => line.as_str()
+        }
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                Execution
+// ===========================================================================
+
+/// Describes how the execution of a Task ended
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Outcome{
+    /// The process exited successfully
+    Finished,
+    /// The process exited with a non-zero exit code (or was killed by a signal)
+    Failed,
+    /// The execution was canceled via a [CancelHandle](struct.CancelHandle.html)
+    Canceled,
+    /// The process ran longer than the timeout of the Executor
+    TimedOut
+}
+
+/// The result of a execution returned by the [Executor](struct.Executor.html)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Execution{
+    pub outcome: Outcome,
+    pub exit_code: Option<i32>,
+    pub duration: Duration
+}
+
+impl Execution{
+    /// Return true if the process exited successfully
+    pub fn is_success(&self) -> bool{
+        self.outcome == Outcome::Finished
+    }
+}
+
+
+
+
+// ===========================================================================
+//                               CancelHandle
+// ===========================================================================
+
+/// A CancelHandle can be sent to other threads in order to cancel running \
+/// executions of the [Executor](struct.Executor.html) it belongs to. A \
+/// canceled Executor stays canceled until `reset()` is called.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle{
+    /// Cancel the current (and every following) execution
+    pub fn cancel(&self){
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Allow executions again after a cancel
+    pub fn reset(&self){
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    /// Return true if the handle has been canceled
+    pub fn is_canceled(&self) -> bool{
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                 Executor
+// ===========================================================================
+
+/// The Executor runs Tasks as child processes on the local machine. It takes \
+/// care of the Task lifecycle: a waiting Task is queued and started before \
+/// the process is spawned, and depending on the Outcome it is finished, \
+/// errored or aborted afterwards.
+///
+/// The Executor can be configured with a timeout and a replacement for the \
+/// `blender` program (e.g. a specific blender build, or a fake blender script \
+/// for testing).
+#[derive(Debug, Clone)]
+pub struct Executor{
+    pub timeout: Option<Duration>,
+    pub blender: Option<CommandLine>,
+    pub poll_interval: Duration,
+    cancel: CancelHandle
+}
+
+impl Default for Executor{
+    fn default() -> Self{
+        Executor::new()
+    }
+}
+
+impl Executor{
+    /// Create a new Executor without timeout
+    pub fn new() -> Self{
+        Executor{
+            timeout: None,
+            blender: None,
+            poll_interval: Duration::from_millis(50),
+            cancel: CancelHandle::default()
+        }
+    }
+
+    /// Kill the process and error the Task if it runs longer than `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self{
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Replace the `blender` program of BlenderCommands with the given \
+    /// CommandLine. The arguments of the CommandLine are put in front of the \
+    /// arguments of the BlenderCommand
+    pub fn with_blender(mut self, blender: CommandLine) -> Self{
+        self.blender = Some(blender);
+        self
+    }
+
+    /// Return a handle that allows to cancel executions from other threads
+    pub fn cancel_handle(&self) -> CancelHandle{
+        self.cancel.clone()
+    }
+
+    /// Run the Task and discard its output. See `run_with()`
+    pub fn run(&self, task: &mut Task) -> GenResult<Execution>{
+        self.run_with(task, |_| ())
+    }
+
+    /// Run the Task's command as a child process and call the handler for \
+    /// every line written to stdout or stderr. Blender Tasks must have been \
+    /// constructed before. Return a Error if the Task cannot be started or the \
+    /// process cannot be spawned (the Task is errored in the latter case).
+    pub fn run_with<F>(&self, task: &mut Task, mut handler: F) -> GenResult<Execution> where F: FnMut(&Output){
+        let line = self.command_line(&task.command)?;
+
+        // Bring the Task into the running state
+        if task.is_waiting(){ task.queue(); }
+        task.start();
+        if !task.is_running(){
+            let message = format!("Error: Couldn't execute Task {}, because it couldn't be started from its status {:?}", task.id, task.status);
+            return Err(From::from(message));
+        }
+
+        let started = Instant::now();
+        let mut child = match line.to_process()
+                                  .stdin(Stdio::null())
+                                  .stdout(Stdio::piped())
+                                  .stderr(Stdio::piped())
+                                  .spawn(){
+            Ok(child) => child,
+            Err(err) => {
+                let message = format!("Couldn't spawn \"{}\": {}", line, err);
+                task.add_data("executor.error", message.as_str());
+                task.error();
+                return Err(From::from(format!("Error: {}", message)));
+            }
+        };
+
+        // Stream stdout and stderr through a channel
+        let (sender, receiver) = mpsc::channel();
+        let mut readers = Vec::new();
+        if let Some(stdout) = child.stdout.take(){
+            readers.push(stream_lines(stdout, sender.clone(), Output::Stdout));
+        }
+        if let Some(stderr) = child.stderr.take(){
+            readers.push(stream_lines(stderr, sender.clone(), Output::Stderr));
+        }
+        drop(sender);
+
+        let mut outcome = None;
+        let mut exit_code = None;
+        while outcome.is_none(){
+            match receiver.recv_timeout(self.poll_interval){
+                Ok(output) => handler(&output),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(self.poll_interval)
+            }
+
+            let exited = match child.try_wait(){
+                Ok(exited) => exited,
+                Err(err) => {
+                    // Don't leave a orphaned process and a running Task behind
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    let message = format!("Couldn't wait for \"{}\": {}", line, err);
+                    task.add_data("executor.error", message.as_str());
+                    task.error();
+                    return Err(From::from(format!("Error: {}", message)));
+                }
+            };
+
+            if let Some(status) = exited{
+                exit_code = status.code();
+                outcome = if status.success() { Some(Outcome::Finished) } else { Some(Outcome::Failed) };
+            }else if self.cancel.is_canceled(){
+                let _ = child.kill();
+                let _ = child.wait();
+                outcome = Some(Outcome::Canceled);
+            }else if self.timeout.filter(|timeout| started.elapsed() > *timeout).is_some(){
+                let _ = child.kill();
+                let _ = child.wait();
+                outcome = Some(Outcome::TimedOut);
+            }
+        }
+        let outcome = outcome.unwrap();
+
+        // Only wait for the remaining output if the process exited on its own,
+        // a killed process might have left children behind that hold the pipes
+        if outcome == Outcome::Finished || outcome == Outcome::Failed{
+            readers.into_iter().for_each(|reader| { let _ = reader.join(); });
+        }
+        receiver.try_iter().for_each(|output| handler(&output));
+
+        // Update the Task according to the outcome
+        match outcome{
+            Outcome::Finished => task.finish(),
+            Outcome::Failed => {
+                if let Some(code) = exit_code{
+                    task.add_data("executor.exit_code".to_string(), code.to_string());
+                }
+                task.error();
+            },
+            Outcome::Canceled => task.abort(),
+            Outcome::TimedOut => {
+                let timeout = self.timeout.unwrap_or_default().as_secs();
+                task.add_data("executor.timeout".to_string(), timeout.to_string());
+                task.error();
+            }
+        }
+
+        Ok(Execution{
+            outcome,
+            exit_code,
+            duration: started.elapsed()
+        })
+    }
+
+    /// Return the CommandLine that should be executed for the Command, with \
+    /// the blender program replaced if the Executor has a replacement set
+    pub fn command_line(&self, command: &Command) -> GenResult<CommandLine>{
+        let mut line = command.command_line()?;
+        if let (Command::Blender(_), Some(ref blender)) = (command, &self.blender){
+            let mut args = blender.args.clone();
+            args.append(&mut line.args);
+            line.program = blender.program.clone();
+            line.args = args;
+            line.env.extend(blender.env.clone());
+            if line.working_dir.is_none(){
+                line.working_dir = blender.working_dir.clone();
+            }
+        }
+        Ok(line)
+    }
+}
+
+
+
+/// Read lines from the reader in a separate thread and send them wrapped in \
+/// the given Output variant
+fn stream_lines<R>(reader: R, sender: Sender<Output>, wrap: fn(String) -> Output) -> thread::JoinHandle<()>
+where R: Read + Send + 'static{
+    thread::spawn(move ||{
+        for line in BufReader::new(reader).lines(){
+            match line{
+                Ok(line) => {
+                    if sender.send(wrap(line)).is_err(){ break; }
+                },
+                Err(_) => break
+            }
+        }
+    })
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use common::tempfile::TempDir;
+
+    /// Write a fake blender shell script into a tempdir and return a \
+    /// CommandLine that runs it via sh
+    fn fake_blender(script: &str) -> (CommandLine, TempDir){
+        let dir = TempDir::new().expect("Couldn't create tempdir");
+        let path = dir.path().join("blender.sh");
+        fs::write(&path, script).expect("Couldn't write fake blender");
+        (CommandLine::new("sh").arg(path.to_string_lossy().to_string()), dir)
+    }
+
+    fn blender_task() -> Task{
+        let mut task = Task::new_blender_single(12, "PNG", "55067970443c49eaafdb60541fbde157");
+        task.construct("my blends/shot.blend", "/tmp/out");
+        task
+    }
+
+    #[test]
+    fn finished() {
+        let (blender, _dir) = fake_blender("echo \"Fra:12 Mem:12M\"\necho \"args: $*\"\necho oops >&2\nexit 0\n");
+        let mut task = blender_task();
+        let mut lines = Vec::new();
+        let execution = Executor::new().with_blender(blender)
+                                       .run_with(&mut task, |output| lines.push(output.clone()))
+                                       .unwrap();
+        assert_eq!(execution.outcome, Outcome::Finished);
+        assert_eq!(execution.exit_code, Some(0));
+        assert!(task.is_finished());
+        assert!(task.time.start.is_some());
+        assert!(task.time.finish.is_some());
+        assert!(lines.contains(&Output::Stdout("Fra:12 Mem:12M".to_string())));
+        assert!(lines.contains(&Output::Stdout("args: -b --disable-autoexec my blends/shot.blend -o /tmp/out/######.png -F PNG -f 12".to_string())));
+        assert!(lines.contains(&Output::Stderr("oops".to_string())));
+    }
+
+    #[test]
+    fn failed() {
+        let (blender, _dir) = fake_blender("echo \"Error: Cannot read file\"\nexit 3\n");
+        let mut task = blender_task();
+        let execution = Executor::new().with_blender(blender).run(&mut task).unwrap();
+        assert_eq!(execution.outcome, Outcome::Failed);
+        assert_eq!(execution.exit_code, Some(3));
+        assert!(task.is_errored());
+        assert!(task.time.error.is_some());
+        assert_eq!(task.data.get("executor.exit_code"), Some(&"3".to_string()));
+    }
+
+    #[test]
+    fn timeout() {
+        let (blender, _dir) = fake_blender("exec sleep 5\n");
+        let mut task = blender_task();
+        let execution = Executor::new().with_blender(blender)
+                                       .with_timeout(Duration::from_millis(200))
+                                       .run(&mut task).unwrap();
+        assert_eq!(execution.outcome, Outcome::TimedOut);
+        assert!(execution.duration < Duration::from_secs(4));
+        assert!(task.is_errored());
+    }
+
+    #[test]
+    fn cancel() {
+        let (blender, _dir) = fake_blender("exec sleep 5\n");
+        let mut task = blender_task();
+        let executor = Executor::new().with_blender(blender);
+        let handle = executor.cancel_handle();
+        thread::spawn(move ||{
+            thread::sleep(Duration::from_millis(200));
+            handle.cancel();
+        });
+        let execution = executor.run(&mut task).unwrap();
+        assert_eq!(execution.outcome, Outcome::Canceled);
+        assert!(execution.duration < Duration::from_secs(4));
+        assert!(task.is_aborted());
+        assert!(task.time.abort.is_some());
+    }
+
+    #[test]
+    fn basic() {
+        let mut task = Task::new_basic("sh -c 'echo hello world'", "55067970443c49eaafdb60541fbde157");
+        let mut lines = Vec::new();
+        Executor::new().run_with(&mut task, |output| lines.push(output.line().to_string())).unwrap();
+        assert_eq!(lines, vec!["hello world".to_string()]);
+        assert!(task.is_finished());
+    }
+
+    #[test]
+    fn unconstructed() {
+        let mut task = Task::new_blender_single(1, "PNG", "55067970443c49eaafdb60541fbde157");
+        assert!(Executor::new().run(&mut task).is_err());
+        assert!(task.is_waiting());
+    }
+
+    #[test]
+    fn spawn_error() {
+        let mut task = Task::new_basic("/this/program/does/not/exist", "55067970443c49eaafdb60541fbde157");
+        assert!(Executor::new().run(&mut task).is_err());
+        assert!(task.is_errored());
+    }
+
+    #[test]
+    fn ended_task_is_not_run() {
+        let mut task = Task::new_basic("true", "55067970443c49eaafdb60541fbde157");
+        task.abort();
+        assert!(Executor::new().run(&mut task).is_err());
+        assert!(task.is_aborted());
+    }
+}
@@ -1,7 +1,9 @@
 use ::*;
 use std::io::prelude::Read;
 use std::collections::BTreeMap;
+use std::time::Duration;
 use blake2::{Blake2b, Digest};
+use renderlog::RenderEvent;
 
 
 /// A Frames holds all frames for a given task.
@@ -64,6 +66,10 @@ pub trait FrameMap{
     /// a String of "-s 1 -e 250 -j 2"
     fn to_flags(&self) -> String;
 
+    /// Same as `to_flags()`, but returns every flag and value as a separate \
+    /// argument, e.g. `["-s", "1", "-e", "250"]`
+    fn to_args(&self) -> Vec<String>;
+
     /// Merge the Frames stored in self with the Frames stored in other. This \
     /// only updates values set in other which are not set in self
     fn merge(&mut self, other: &Self);
@@ -143,6 +149,52 @@ pub trait FrameMap{
     /// if it differs and return Error if the read fails or the framenumber is \
     /// out of bounds or not contained within Frames
     fn same_hash<R: Read>(&mut self, framenumber: usize, reader: R) -> GenResult<bool>;
+
+    /// Set the render time for a given frame. Return Err if the frame is \
+    /// not contained
+    fn set_render_time(&mut self, framenumber: usize, render_time: Duration) -> GenResult<()>;
+
+    /// Set the peak memory in bytes for a given frame. Return Err if the \
+    /// frame is not contained
+    fn set_peak_memory(&mut self, framenumber: usize, peak_memory: usize) -> GenResult<()>;
+
+    /// Set the worker for a given frame. Return Err if the frame is not \
+    /// contained
+    fn set_worker<S>(&mut self, framenumber: usize, worker: S) -> GenResult<()> where S: Into<String>;
+
+    /// Set the attempt number for a given frame. Return Err if the frame is \
+    /// not contained
+    fn set_attempt(&mut self, framenumber: usize, attempt: usize) -> GenResult<()>;
+
+    /// Set the blender version for a given frame. Return Err if the frame is \
+    /// not contained
+    fn set_blender_version<S>(&mut self, framenumber: usize, blender_version: S) -> GenResult<()> where S: Into<String>;
+
+    /// Record the statistics carried by a [RenderEvent](../renderlog/enum.RenderEvent.html) \
+    /// (render time, peak memory and blender version). Return true if any \
+    /// frame has been updated
+    fn apply_render_event(&mut self, event: &RenderEvent) -> bool;
+
+    /// Return the n frames with the longest render time, slowest first. \
+    /// Frames without a render time are ignored
+    fn slowest(&self, n: usize) -> Vec<(usize, Duration)>;
+
+    /// Return the sum of all known render times
+    fn total_render_time(&self) -> Duration;
+
+    /// Return the mean render time of all frames with a known render time, \
+    /// or None if no render time is known
+    fn mean_render_time(&self) -> Option<Duration>;
+
+    /// Return the frame with the highest peak memory and its peak memory in \
+    /// bytes (the memory high-water mark), or None if no peak memory is known
+    fn peak_memory(&self) -> Option<(usize, usize)>;
+
+    /// Return true if any of the frames is marked as corrupt
+    fn any_corrupt(&self) -> bool;
+
+    /// Return the framenumbers of all frames marked as corrupt
+    fn corrupt_frames(&self) -> Vec<usize>;
 }
 
 
@@ -207,14 +259,21 @@ impl FrameMap for Frames{
     }
 
     fn to_flags(&self) -> String{
+        self.to_args().join(" ")
+    }
+
+    fn to_args(&self) -> Vec<String>{
         if self.is_single(){
-            format!("-f {}", self.start())
+            vec!["-f".to_string(), self.start().to_string()]
         }else{
+            let mut args = vec!["-s".to_string(), self.start().to_string(), 
+                                "-e".to_string(), self.end().to_string()];
             let step = self.step();
-            match step{
-                1 => format!("-s {} -e {}", self.start(), self.end()),
-                _ => format!("-s {} -e {} -j {}", self.start(), self.end(), step)
+            if step != 1{
+                args.push("-j".to_string());
+                args.push(step.to_string());
             }
+            args
         }
     }
 
@@ -270,7 +329,7 @@ impl FrameMap for Frames{
 
     fn get_filesize(&self, framenumber: usize) -> Option<usize>{
         match self.get(&framenumber){
-            Some(frame) => frame.filesize,
+            Some(frame) => frame.get_filesize(),
             None => None
         }
     }
@@ -286,28 +345,28 @@ impl FrameMap for Frames{
 
     fn get_uploaded(&self, framenumber: usize) -> bool{
         match self.get(&framenumber){
-            Some(frame) => frame.uploaded,
+            Some(frame) => frame.is_uploaded(),
             None => false
         }
     }
 
     fn is_filesize(&self, framenumber: usize) -> bool{
         match self.get(&framenumber){
-            Some(frame) => frame.filesize.is_some(),
+            Some(frame) => frame.is_filesize(),
             None => false
         }
     }
 
     fn is_hash(&self, framenumber: usize) -> bool{
         match self.get(&framenumber){
-            Some(frame) => frame.hash.is_some(),
+            Some(frame) => frame.is_hash(),
             None => false
         }
     }
 
     fn is_uploaded(&self, framenumber: usize) -> bool{
         match self.get(&framenumber){
-            Some(frame) => frame.uploaded,
+            Some(frame) => frame.is_uploaded(),
             None => false
         }
     }
@@ -376,6 +435,108 @@ impl FrameMap for Frames{
         }
     }
 
+    fn set_render_time(&mut self, framenumber: usize, render_time: Duration) -> GenResult<()>{
+        contained_frame(self, framenumber, "set_render_time()")?.set_render_time(render_time);
+        Ok(())
+    }
+
+    fn set_peak_memory(&mut self, framenumber: usize, peak_memory: usize) -> GenResult<()>{
+        contained_frame(self, framenumber, "set_peak_memory()")?.set_peak_memory(peak_memory);
+        Ok(())
+    }
+
+    fn set_worker<S>(&mut self, framenumber: usize, worker: S) -> GenResult<()> where S: Into<String>{
+        contained_frame(self, framenumber, "set_worker()")?.set_worker(worker);
+        Ok(())
+    }
+
+    fn set_attempt(&mut self, framenumber: usize, attempt: usize) -> GenResult<()>{
+        contained_frame(self, framenumber, "set_attempt()")?.set_attempt(attempt);
+        Ok(())
+    }
+
+    fn set_blender_version<S>(&mut self, framenumber: usize, blender_version: S) -> GenResult<()> where S: Into<String>{
+        contained_frame(self, framenumber, "set_blender_version()")?.set_blender_version(blender_version);
+        Ok(())
+    }
+
+    fn apply_render_event(&mut self, event: &RenderEvent) -> bool{
+        match event{
+            RenderEvent::Version(version) => {
+                self.iter_mut().for_each(|(_, frame)| frame.set_blender_version(version.as_str()));
+                !self.is_empty()
+            },
+            RenderEvent::Memory{ frame, peak } => {
+                match self.get_mut(frame){
+                    Some(f) if f.peak_memory.unwrap_or(0) < *peak => {
+                        f.set_peak_memory(*peak);
+                        true
+                    },
+                    _ => false
+                }
+            },
+            RenderEvent::FrameSaved{ frame: Some(frame), render_time: Some(render_time), .. } => {
+                self.set_render_time(*frame, *render_time).is_ok()
+            },
+            _ => false
+        }
+    }
+
+    fn slowest(&self, n: usize) -> Vec<(usize, Duration)>{
+        let mut times: Vec<(usize, Duration)> = self.iter()
+            .filter_map(|(number, frame)| frame.get_render_time().map(|t| (*number, t)))
+            .collect();
+        times.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        times.truncate(n);
+        times
+    }
+
+    fn total_render_time(&self) -> Duration{
+        self.values()
+            .filter_map(|frame| frame.get_render_time())
+            .fold(Duration::from_secs(0), |sum, t| sum + t)
+    }
+
+    fn mean_render_time(&self) -> Option<Duration>{
+        let count = self.values().filter(|frame| frame.render_time.is_some()).count();
+        match count{
+            0 => None,
+            _ => Some(self.total_render_time() / count as u32)
+        }
+    }
+
+    fn peak_memory(&self) -> Option<(usize, usize)>{
+        self.iter()
+            .filter_map(|(number, frame)| frame.peak_memory.map(|p| (*number, p)))
+            .fold(None, |max, (number, peak)| match max{
+                Some((_, max_peak)) if max_peak >= peak => max,
+                _ => Some((number, peak))
+            })
+    }
+
+    fn any_corrupt(&self) -> bool{
+        self.values().any(|frame| frame.is_corrupt())
+    }
+
+    fn corrupt_frames(&self) -> Vec<usize>{
+        self.iter()
+            .filter(|(_, frame)| frame.is_corrupt())
+            .map(|(number, _)| *number)
+            .collect()
+    }
+
+}
+
+
+/// Return the contained Frame or a Error mentioning the call that failed
+fn contained_frame<'a>(frames: &'a mut Frames, framenumber: usize, call: &str) -> GenResult<&'a mut Frame>{
+    match frames.get_mut(&framenumber){
+        Some(frame) => Ok(frame),
+        None => {
+            let errmessage = format!("Error: Couldn't {} for frame {}. Frame not contained in this Task.", call, framenumber);
+            Err(From::from(&*errmessage))
+        }
+    }
 }
 
 
@@ -391,11 +552,41 @@ impl FrameMap for Frames{
 /// - filesize in bytes
 /// - hash is the Blake2b result of the rendered frame
 /// - uploaded is a flag that signifies a sucessful upload
+///
+/// Additionally it holds statistics about the rendering of the frame:
+/// - render_time in milliseconds (as reported by blender)
+/// - peak_memory in bytes (as reported by blender)
+/// - worker is the name of the worker that rendered the frame
+/// - attempt counts how often the frame has been rendered (starting at 1)
+/// - blender_version is the version of blender that rendered the frame
+///
+/// A frame whose file failed [verification](../verify/index.html) is marked \
+/// as corrupt with the reason.
+///
+/// If blender writes more than one file per frame (see [OutputSpec](../outputs/struct.OutputSpec.html)), \
+/// each file is tracked as a named [FrameOutput](struct.FrameOutput.html) \
+/// with its own filesize, hash, upload flag and corrupt mark. The frame's \
+/// filesize, hash and uploaded flag are then only set, if they are set for \
+/// all of its outputs.
 #[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Frame{
     filesize: Option<usize>,
     hash: Option<String>,
-    uploaded: bool
+    uploaded: bool,
+    #[serde(default)]
+    render_time: Option<u64>,
+    #[serde(default)]
+    peak_memory: Option<usize>,
+    #[serde(default)]
+    worker: Option<String>,
+    #[serde(default)]
+    attempt: Option<usize>,
+    #[serde(default)]
+    blender_version: Option<String>,
+    #[serde(default)]
+    corrupt: Option<String>,
+    #[serde(default)]
+    outputs: BTreeMap<String, FrameOutput>
 }
 
 
@@ -407,12 +598,20 @@ impl Frame{
         Frame::default()
     }
 
-    /// Return a default Frame struct, without filesize, hash and uploaded flag
+    /// Return a default Frame struct, without filesize, hash, uploaded flag \
+    /// and render statistics
     pub fn default() -> Self{
         Frame{
             filesize: None,
             hash: None,
-            uploaded: false
+            uploaded: false,
+            render_time: None,
+            peak_memory: None,
+            worker: None,
+            attempt: None,
+            blender_version: None,
+            corrupt: None,
+            outputs: BTreeMap::new()
         }
     }
 
@@ -428,8 +627,35 @@ impl Frame{
         }
 
         // If either is uploaded, set to true
-        self.uploaded = self.uploaded || other.uploaded
+        self.uploaded = self.uploaded || other.uploaded;
 
+        if self.render_time.is_none() && other.render_time.is_some(){
+            self.render_time = other.render_time;
+        }
+
+        if self.peak_memory.is_none() && other.peak_memory.is_some(){
+            self.peak_memory = other.peak_memory;
+        }
+
+        if self.worker.is_none() && other.worker.is_some(){
+            self.worker = other.worker.clone();
+        }
+
+        if self.attempt.is_none() && other.attempt.is_some(){
+            self.attempt = other.attempt;
+        }
+
+        if self.blender_version.is_none() && other.blender_version.is_some(){
+            self.blender_version = other.blender_version.clone();
+        }
+
+        if self.corrupt.is_none() && other.corrupt.is_some(){
+            self.corrupt = other.corrupt.clone();
+        }
+
+        for (name, other_output) in other.outputs.iter(){
+            self.output_entry(name.as_str()).merge(other_output);
+        }
     }
 
     /// Set the Frame's filesize
@@ -443,13 +669,20 @@ impl Frame{
         self.hash = Some(hash)
     }
 
-    /// Set the Frame's upload flag to true
+    /// Set the Frame's upload flag (and that of all its outputs) to true
     pub fn set_uploaded(&mut self){
-        self.uploaded = true
+        self.uploaded = true;
+        self.outputs.values_mut().for_each(|output| output.set_uploaded());
     }
 
-    /// Get a the Frame's filesize
+    /// Get a the Frame's filesize. For a Frame with outputs this is the sum \
+    /// of their filesizes, if all of them are known
     pub fn get_filesize(&self) -> Option<usize>{
+        if self.has_outputs(){
+            return self.outputs.values()
+                               .map(|output| output.get_filesize())
+                               .sum();
+        }
         match self.filesize{
             Some(s) => Some(s),
             None => None
@@ -464,19 +697,135 @@ impl Frame{
         }
     }
 
-    /// Return true if the Frame's filesize is set
+    /// Return true if the Frame's filesize is set (for all outputs)
     pub fn is_filesize(&self) -> bool{
-        self.filesize.is_some()
+        if self.has_outputs(){
+            self.outputs.values().all(|output| output.is_filesize())
+        }else{
+            self.filesize.is_some()
+        }
     }
 
-    /// Return true if the Frame's hash is set
+    /// Return true if the Frame's hash is set (for all outputs)
     pub fn is_hash(&self) -> bool{
-        self.hash.is_some()
+        if self.has_outputs(){
+            self.outputs.values().all(|output| output.is_hash())
+        }else{
+            self.hash.is_some()
+        }
     }
 
-    /// Return true if the Frame's upload flag is set
+    /// Return true if the Frame's upload flag is set (for all outputs)
     pub fn is_uploaded(&self) -> bool{
-        self.uploaded
+        if self.has_outputs(){
+            self.uploaded || self.outputs.values().all(|output| output.is_uploaded())
+        }else{
+            self.uploaded
+        }
+    }
+
+    /// Add a output with the given name, if it doesn't exist yet
+    pub fn add_output<S>(&mut self, name: S) where S: Into<String>{
+        self.output_entry(name);
+    }
+
+    /// Return the output with the given name, create it if it doesn't exist
+    pub fn output_entry<S>(&mut self, name: S) -> &mut FrameOutput where S: Into<String>{
+        self.outputs.entry(name.into()).or_default()
+    }
+
+    /// Get the output with the given name
+    pub fn get_output(&self, name: &str) -> Option<&FrameOutput>{
+        self.outputs.get(name)
+    }
+
+    /// Get the names of all outputs
+    pub fn output_names(&self) -> Vec<String>{
+        self.outputs.keys().cloned().collect()
+    }
+
+    /// Return true if the Frame tracks named outputs
+    pub fn has_outputs(&self) -> bool{
+        !self.outputs.is_empty()
+    }
+
+    /// Set the time it took to render the Frame
+    pub fn set_render_time(&mut self, render_time: Duration){
+        let millis = render_time.as_secs() * 1000 + u64::from(render_time.subsec_millis());
+        self.render_time = Some(millis)
+    }
+
+    /// Get the time it took to render the Frame
+    pub fn get_render_time(&self) -> Option<Duration>{
+        self.render_time.map(Duration::from_millis)
+    }
+
+    /// Set the peak memory (in bytes) blender used while rendering the Frame
+    pub fn set_peak_memory(&mut self, peak_memory: usize){
+        self.peak_memory = Some(peak_memory)
+    }
+
+    /// Get the peak memory (in bytes) blender used while rendering the Frame
+    pub fn get_peak_memory(&self) -> Option<usize>{
+        self.peak_memory
+    }
+
+    /// Set the name of the worker that rendered the Frame
+    pub fn set_worker<S>(&mut self, worker: S) where S: Into<String>{
+        self.worker = Some(worker.into())
+    }
+
+    /// Get the name of the worker that rendered the Frame
+    pub fn get_worker(&self) -> Option<String>{
+        self.worker.clone()
+    }
+
+    /// Set the attempt number (the first render of a Frame is attempt 1)
+    pub fn set_attempt(&mut self, attempt: usize){
+        self.attempt = Some(attempt)
+    }
+
+    /// Get the attempt number
+    pub fn get_attempt(&self) -> Option<usize>{
+        self.attempt
+    }
+
+    /// Set the version of blender that rendered the Frame
+    pub fn set_blender_version<S>(&mut self, blender_version: S) where S: Into<String>{
+        self.blender_version = Some(blender_version.into())
+    }
+
+    /// Get the version of blender that rendered the Frame
+    pub fn get_blender_version(&self) -> Option<String>{
+        self.blender_version.clone()
+    }
+
+    /// Mark the Frame as corrupt for the given reason
+    pub fn set_corrupt<S>(&mut self, reason: S) where S: Into<String>{
+        self.corrupt = Some(reason.into())
+    }
+
+    /// Remove the corrupt mark (e.g. after the file passed verification) \
+    /// from the Frame and all its outputs
+    pub fn clear_corrupt(&mut self){
+        self.corrupt = None;
+        self.outputs.values_mut().for_each(|output| output.clear_corrupt());
+    }
+
+    /// Get the reason why the Frame is corrupt. For a corrupt output the \
+    /// reason is prefixed with the name of the output
+    pub fn get_corrupt(&self) -> Option<String>{
+        if self.corrupt.is_some(){
+            return self.corrupt.clone();
+        }
+        self.outputs.iter()
+                    .filter_map(|(name, output)| output.get_corrupt().map(|reason| format!("{}: {}", name, reason)))
+                    .next()
+    }
+
+    /// Return true if the Frame or any of its outputs is marked as corrupt
+    pub fn is_corrupt(&self) -> bool{
+        self.corrupt.is_some() || self.outputs.values().any(|output| output.is_corrupt())
     }
 
     /// Set the filesize from the Frame's file. This takes anything that \
@@ -577,6 +926,130 @@ impl Frame{
 
 
 
+// ===========================================================================
+//                               FRAME OUTPUT
+// ===========================================================================
+
+/// A FrameOutput holds the data of one of the files rendered for a \
+/// [Frame](struct.Frame.html) if blender writes more than one file per \
+/// frame (e.g. one per view or per File Output node slot):
+/// - filesize in bytes
+/// - hash is the Blake2b result of the rendered file
+/// - uploaded is a flag that signifies a sucessful upload
+/// - corrupt is the reason the file failed verification
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FrameOutput{
+    filesize: Option<usize>,
+    hash: Option<String>,
+    #[serde(default)]
+    uploaded: bool,
+    #[serde(default)]
+    corrupt: Option<String>
+}
+
+impl FrameOutput{
+    /// Create a new FrameOutput without filesize, hash and uploaded flag
+    pub fn new() -> Self{
+        FrameOutput::default()
+    }
+
+    /// merge one FrameOutput into another, overwriting existing fields only, \
+    /// if they are None or false in self
+    pub fn merge(&mut self, other: &Self){
+        if self.filesize.is_none() { self.filesize = other.filesize; }
+        if self.hash.is_none() { self.hash = other.hash.clone(); }
+        self.uploaded = self.uploaded || other.uploaded;
+        if self.corrupt.is_none() { self.corrupt = other.corrupt.clone(); }
+    }
+
+    /// Set the filesize
+    pub fn set_filesize(&mut self, filesize: usize){
+        self.filesize = Some(filesize)
+    }
+
+    /// Set the hash to the supplied String
+    pub fn set_hash<S>(&mut self, hash: S) where S: Into<String>{
+        self.hash = Some(hash.into())
+    }
+
+    /// Set the upload flag to true
+    pub fn set_uploaded(&mut self){
+        self.uploaded = true
+    }
+
+    /// Get the filesize
+    pub fn get_filesize(&self) -> Option<usize>{
+        self.filesize
+    }
+
+    /// Get the hash
+    pub fn get_hash(&self) -> Option<String>{
+        self.hash.clone()
+    }
+
+    /// Return true if the filesize is set
+    pub fn is_filesize(&self) -> bool{
+        self.filesize.is_some()
+    }
+
+    /// Return true if the hash is set
+    pub fn is_hash(&self) -> bool{
+        self.hash.is_some()
+    }
+
+    /// Return true if the upload flag is set
+    pub fn is_uploaded(&self) -> bool{
+        self.uploaded
+    }
+
+    /// Mark the output as corrupt for the given reason
+    pub fn set_corrupt<S>(&mut self, reason: S) where S: Into<String>{
+        self.corrupt = Some(reason.into())
+    }
+
+    /// Remove the corrupt mark
+    pub fn clear_corrupt(&mut self){
+        self.corrupt = None
+    }
+
+    /// Get the reason why the output is corrupt
+    pub fn get_corrupt(&self) -> Option<String>{
+        self.corrupt.clone()
+    }
+
+    /// Return true if the output is marked as corrupt
+    pub fn is_corrupt(&self) -> bool{
+        self.corrupt.is_some()
+    }
+
+    /// Set the filesize to the number of bytes read from the reader and \
+    /// return it (see `Frame::filesize_from_file()`)
+    pub fn filesize_from_file<R: Read>(&mut self, mut reader: R) -> GenResult<usize>{
+        let mut buffer = Vec::new();
+        let filesize = reader.read_to_end(&mut buffer)?;
+        self.set_filesize(filesize);
+        Ok(filesize)
+    }
+
+    /// Set the Blake2b hash of the bytes read from the reader and return it \
+    /// (see `Frame::hash_from_file()`)
+    pub fn hash_from_file<R: Read>(&mut self, mut reader: R) -> GenResult<String>{
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+        let mut hasher = Blake2b::new();
+        hasher.input(buffer);
+        let hash = format!("{:x}", hasher.result());
+        self.set_hash(hash.as_str());
+        Ok(hash)
+    }
+}
+
+
+
+
+
+
+
 // ===========================================================================
 //                                UNIT TESTS
 // ===========================================================================
@@ -750,6 +1223,37 @@ mod frame {
         assert_eq!(this.hash, duplicate.hash);
         assert_eq!(this.uploaded, duplicate.uploaded);
     }
+
+    #[test]
+    fn render_stats() {
+        let mut f = Frame::new();
+        assert_eq!(f.get_render_time(), None);
+        f.set_render_time(Duration::from_millis(9310));
+        f.set_peak_memory(310);
+        f.set_worker("worker-01");
+        f.set_attempt(2);
+        f.set_blender_version("2.79 (sub 0)");
+        assert_eq!(f.get_render_time(), Some(Duration::from_millis(9310)));
+        assert_eq!(f.get_peak_memory(), Some(310));
+        assert_eq!(f.get_worker(), Some("worker-01".to_string()));
+        assert_eq!(f.get_attempt(), Some(2));
+        assert_eq!(f.get_blender_version(), Some("2.79 (sub 0)".to_string()));
+
+        let mut this = Frame::new();
+        this.set_attempt(3);
+        this.merge(&f);
+        assert_eq!(this.get_render_time(), f.get_render_time());
+        assert_eq!(this.get_worker(), f.get_worker());
+        assert_eq!(this.get_attempt(), Some(3));
+    }
+
+    #[test]
+    fn deserialize_without_stats() {
+        let f: Frame = serde_json::from_str(r#"{"filesize":8,"hash":null,"uploaded":true}"#).unwrap();
+        assert_eq!(f.get_filesize(), Some(8));
+        assert_eq!(f.get_render_time(), None);
+        assert_eq!(f.get_worker(), None);
+    }
 }
 
 
@@ -1001,5 +1505,107 @@ mod frames {
         assert_eq!(this, duplicate);
     }
 
+    #[test]
+    fn render_time_queries() {
+        let mut f = frames::Frames::new_range(1, 5, 1);
+        assert_eq!(f.mean_render_time(), None);
+        assert!(f.slowest(3).is_empty());
+        f.set_render_time(1, Duration::from_secs(10)).unwrap();
+        f.set_render_time(2, Duration::from_secs(40)).unwrap();
+        f.set_render_time(3, Duration::from_secs(25)).unwrap();
+        f.set_render_time(4, Duration::from_secs(40)).unwrap();
+        assert!(f.set_render_time(6, Duration::from_secs(1)).is_err());
+
+        assert_eq!(f.slowest(3), vec![(2, Duration::from_secs(40)),
+                                      (4, Duration::from_secs(40)),
+                                      (3, Duration::from_secs(25))]);
+        assert_eq!(f.total_render_time(), Duration::from_secs(115));
+        assert_eq!(f.mean_render_time(), Some(Duration::from_millis(28750)));
+    }
+
+    #[test]
+    fn peak_memory() {
+        let mut f = frames::Frames::new_range(1, 3, 1);
+        assert_eq!(f.peak_memory(), None);
+        f.set_peak_memory(1, 100).unwrap();
+        f.set_peak_memory(3, 300).unwrap();
+        f.set_peak_memory(2, 300).unwrap();
+        assert_eq!(f.peak_memory(), Some((2, 300)));
+    }
+
+    #[test]
+    fn apply_render_events() {
+        let mut f = frames::Frames::new_range(12, 13, 1);
+        let log = "Blender 2.79 (sub 0) (hash 5bd8ac9abfa built 2017-09-11 10:43:32)
+Fra:12 Mem:230M (Peak 310M) | Time:00:04.12 | Rendering 12/64 Tiles
+Saved: '/data/frames/000012.png'
+ Time: 00:09.31 (Saving: 00:00.12)";
+        for event in RenderLog::parse(log){
+            f.apply_render_event(&event);
+        }
+        assert!(!f.apply_render_event(&RenderEvent::Memory{ frame: 99, peak: 1 }));
+
+        let frame = &f[&12];
+        assert_eq!(frame.get_render_time(), Some(Duration::from_millis(9310)));
+        assert_eq!(frame.get_peak_memory(), Some(310 * 1024 * 1024));
+        assert_eq!(frame.get_blender_version(), Some("2.79 (sub 0)".to_string()));
+        assert_eq!(f[&13].get_render_time(), None);
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut f = frames::Frames::new_range(1, 4, 1);
+        assert!(!f.any_corrupt());
+        f.get_mut(&2).unwrap().set_corrupt("The PNG file is truncated");
+        f.get_mut(&4).unwrap().set_corrupt("The PNG file is truncated");
+        assert!(f.any_corrupt());
+        assert_eq!(f.corrupt_frames(), vec![2, 4]);
+        f.get_mut(&2).unwrap().clear_corrupt();
+        assert_eq!(f.corrupt_frames(), vec![4]);
+    }
+
+    #[test]
+    fn outputs() {
+        let mut f = frames::Frames::new_range(1, 2, 1);
+        for frame in f.values_mut(){
+            frame.add_output("render_L");
+            frame.add_output("render_R");
+        }
+        assert!(!f.any_filesize());
+        f.get_mut(&1).unwrap().output_entry("render_L").filesize_from_file("1234".as_bytes()).unwrap();
+        assert!(!f.is_filesize(1));
+        assert_eq!(f.get_filesize(1), None);
+        f.get_mut(&1).unwrap().output_entry("render_R").filesize_from_file("123".as_bytes()).unwrap();
+        assert!(f.is_filesize(1));
+        assert_eq!(f.get_filesize(1), Some(7));
+        assert!(!f.all_filesize());
+
+        f.get_mut(&2).unwrap().output_entry("render_R").set_corrupt("The PNG file is truncated");
+        assert_eq!(f.corrupt_frames(), vec![2]);
+        assert_eq!(f[&2].get_corrupt(), Some("render_R: The PNG file is truncated".to_string()));
+
+        f.set_uploaded(1).unwrap();
+        assert!(f[&1].get_output("render_L").unwrap().is_uploaded());
+        assert!(f.is_uploaded(1));
+        assert!(!f.is_uploaded(2));
+    }
+
+    #[test]
+    fn outputs_merge_and_legacy() {
+        let legacy: Frame = serde_json::from_str("{\"filesize\": 8, \"hash\": null, \"uploaded\": false}").unwrap();
+        assert!(!legacy.has_outputs());
+        assert!(legacy.is_filesize());
+
+        let mut a = Frame::new();
+        a.add_output("render");
+        a.add_output("Out/depth");
+        let mut b = a.clone();
+        b.output_entry("Out/depth").set_hash("abc");
+        a.merge(&b);
+        assert_eq!(a.get_output("Out/depth").unwrap().get_hash(), Some("abc".to_string()));
+        assert_eq!(a.output_names(), vec!["Out/depth".to_string(), "render".to_string()]);
+        assert!(!a.is_hash());
+    }
+
 }
 
@@ -0,0 +1,481 @@
+//! The framespec module defines the [FrameSpec](struct.FrameSpec.html), an \
+//! arbitrary selection of frames written as a comma separated list:
+//! - `15` a single frame
+//! - `1-10` a range of frames (inclusive)
+//! - `20-40x5` a range of frames with a step (20, 25, 30, 35, 40)
+//! - `!25` or `!30-35` excludes frames from the selection, regardless of the \
+//!   position in the list
+//!
+//! A FrameSpec is a set of frames, so it supports union, difference and \
+//! intersection. It is formatted as the shortest list of runs and compiles \
+//! to the blender flags (or Tasks) needed to render these runs.
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::framespec::FrameSpec;
+//! let spec = FrameSpec::parse("1-10,15,20-40x5,!25").unwrap();
+//! assert_eq!(spec.len(), 15);
+//! assert!(!spec.contains(25));
+//! assert_eq!(spec.to_string(), "1-9,10-20x5,30-40x5");
+//! assert_eq!(spec.to_flags(), vec!["-s 1 -e 9", "-s 10 -e 20 -j 5", "-s 30 -e 40 -j 5"]);
+//! ```
+
+use ::*;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::iter::FromIterator;
+use std::str::FromStr;
+use frames::FrameMap;
+
+
+/// The maximum number of frames a parsed FrameSpec may contain
+pub const MAX_FRAMES: usize = 1_000_000;
+
+
+
+
+// ===========================================================================
+//                                 FrameRun
+// ===========================================================================
+
+/// A run of frames from start to end (inclusive) with a step, the unit \
+/// blender can render with a single set of flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRun{
+    pub start: usize,
+    pub end: usize,
+    pub step: usize
+}
+
+impl FrameRun{
+    /// Return the number of frames in the run (saturating at `usize::MAX`)
+    pub fn count(&self) -> usize{
+        self.checked_count().unwrap_or(usize::MAX)
+    }
+
+    /// Return the number of frames in the run, or None if the run is empty \
+    /// or has more frames than fit into a usize
+    pub fn checked_count(&self) -> Option<usize>{
+        if self.step == 0{
+            return None;
+        }
+        self.end.checked_sub(self.start)
+                .and_then(|distance| (distance / self.step).checked_add(1))
+    }
+
+    /// Return true if the run is a single frame
+    pub fn is_single(&self) -> bool{
+        self.start == self.end
+    }
+
+    /// Return the blender flags for the run as separate arguments, e.g. \
+    /// `["-s", "1", "-e", "10"]`
+    pub fn to_args(&self) -> Vec<String>{
+        self.frames().to_args()
+    }
+
+    /// Return the blender flags for the run, e.g. `-s 1 -e 10`
+    pub fn to_flags(&self) -> String{
+        self.frames().to_flags()
+    }
+
+    /// Create a Task rendering the run
+    pub fn to_task<S>(&self, image_format: S, id: S) -> Task where S: Into<String>{
+        match self.is_single(){
+            true => Task::new_blender_single(self.start, image_format, id),
+            false => Task::new_blender_range(self.start, self.end, self.step, image_format, id)
+        }
+    }
+
+    fn frames(&self) -> frames::Frames{
+        match self.is_single(){
+            true => frames::Frames::new_single(self.start),
+            false => frames::Frames::new_range(self.start, self.end, self.step)
+        }
+    }
+}
+
+impl fmt::Display for FrameRun {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.is_single(), self.step){
+            (true, _) => write!(f, "{}", self.start),
+            (false, 1) => write!(f, "{}-{}", self.start, self.end),
+            (false, step) => write!(f, "{}-{}x{}", self.start, self.end, step)
+        }
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                 FrameSpec
+// ===========================================================================
+
+/// A set of frames. It is serialized as its (formatted) specification string
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub struct FrameSpec{
+    frames: BTreeSet<usize>
+}
+
+impl FrameSpec{
+    /// Create an empty FrameSpec
+    pub fn new() -> Self{
+        Self::default()
+    }
+
+    /// Create a FrameSpec of a range with a step (like `data::Frames`)
+    pub fn from_range(start: usize, end: usize, step: usize) -> Self{
+        if step == 0 || start > end{
+            return Self::default();
+        }
+        (start..=end).step_by(step).collect()
+    }
+
+    /// Parse a specification like `1-10,15,20-40x5,!25`. Whitespace is \
+    /// ignored. Return an Error for malformed items, ranges with the start \
+    /// after the end, a step of 0 or more than `MAX_FRAMES` frames
+    pub fn parse(spec: &str) -> GenResult<Self>{
+        let mut included = BTreeSet::new();
+        let mut excluded = Vec::new();
+        let spec: String = spec.chars().filter(|c| !c.is_whitespace()).collect();
+        for item in spec.split(',').filter(|item| !item.is_empty()){
+            match item.starts_with('!'){
+                true => excluded.push(parse_run(&item[1..], item)?),
+                false => {
+                    let run = parse_run(item, item)?;
+                    match run.checked_count(){
+                        Some(count) if count <= MAX_FRAMES - included.len() => (),
+                        _ => {
+                            let message = format!("Error: The frame specification \"{}\" contains more than {} frames", spec, MAX_FRAMES);
+                            return Err(From::from(message));
+                        }
+                    }
+                    included.extend((run.start..=run.end).step_by(run.step));
+                }
+            }
+        }
+        for run in excluded{
+            included.retain(|frame| !(run.start <= *frame && *frame <= run.end && (frame - run.start) % run.step == 0));
+        }
+        Ok(FrameSpec{ frames: included })
+    }
+
+    /// Return the number of frames
+    pub fn len(&self) -> usize{
+        self.frames.len()
+    }
+
+    /// Return true if no frame is selected
+    pub fn is_empty(&self) -> bool{
+        self.frames.is_empty()
+    }
+
+    /// Return true if the frame is selected
+    pub fn contains(&self, framenumber: usize) -> bool{
+        self.frames.contains(&framenumber)
+    }
+
+    /// Return the lowest frame
+    pub fn first(&self) -> Option<usize>{
+        self.frames.iter().next().cloned()
+    }
+
+    /// Return the highest frame
+    pub fn last(&self) -> Option<usize>{
+        self.frames.iter().next_back().cloned()
+    }
+
+    /// Iterate over the frames in ascending order
+    pub fn iter(&self) -> impl Iterator<Item=usize> + '_{
+        self.frames.iter().cloned()
+    }
+
+    /// Return the frames in ascending order
+    pub fn as_vec(&self) -> Vec<usize>{
+        self.iter().collect()
+    }
+
+    /// Return the frames selected in self or other
+    pub fn union(&self, other: &Self) -> Self{
+        FrameSpec{ frames: self.frames.union(&other.frames).cloned().collect() }
+    }
+
+    /// Return the frames selected in self, but not in other
+    pub fn difference(&self, other: &Self) -> Self{
+        FrameSpec{ frames: self.frames.difference(&other.frames).cloned().collect() }
+    }
+
+    /// Return the frames selected in self and other
+    pub fn intersection(&self, other: &Self) -> Self{
+        FrameSpec{ frames: self.frames.intersection(&other.frames).cloned().collect() }
+    }
+
+    /// Return true if every frame of self is selected in other
+    pub fn is_subset(&self, other: &Self) -> bool{
+        self.frames.is_subset(&other.frames)
+    }
+
+    /// Split the frames into as few runs as possible. Runs with a step other \
+    /// than 1 need at least three frames, otherwise the frames are single \
+    /// runs. Every run covers frames that follow each other in the set
+    pub fn runs(&self) -> Vec<FrameRun>{
+        let frames = self.as_vec();
+        let n = frames.len();
+        // Going backwards, count[i] is the smallest number of runs covering
+        // frames[i..], next[i] the end (exclusive) of the first of these runs
+        // and reach[i] the last frame of the stretch with the step from
+        // frames[i] to frames[i + 1]
+        let mut count = vec![0; n + 1];
+        let mut next = vec![0; n];
+        let mut reach = vec![0; n];
+        for i in (0..n).rev(){
+            let mut candidates = Vec::with_capacity(3);
+            if i + 1 < n{
+                let step = frames[i + 1] - frames[i];
+                reach[i] = match i + 2 < n && frames[i + 2] - frames[i + 1] == step{
+                    true => reach[i + 1],
+                    false => i + 1
+                };
+                // A run either covers the whole stretch or leaves its last
+                // frame to the next run, stopping earlier never saves a run
+                let shortest = if step == 1 { 2 } else { 3 };
+                candidates.extend(vec![reach[i] + 1, reach[i]].into_iter().filter(|end| end - i >= shortest));
+            }
+            candidates.push(i + 1);
+            // The longest run wins ties
+            let end = candidates.into_iter().min_by_key(|end| count[*end]).unwrap();
+            count[i] = count[end] + 1;
+            next[i] = end;
+        }
+
+        let mut runs = Vec::with_capacity(count[0]);
+        let mut i = 0;
+        while i < n{
+            let end = next[i];
+            let step = if end - i > 1 { frames[i + 1] - frames[i] } else { 1 };
+            runs.push(FrameRun{ start: frames[i], end: frames[end - 1], step });
+            i = end;
+        }
+        runs
+    }
+
+    /// Return the blender flags for each run, e.g. `["-s 1 -e 10", "-f 15"]`
+    pub fn to_flags(&self) -> Vec<String>{
+        self.runs().iter().map(|run| run.to_flags()).collect()
+    }
+
+    /// Return the blender flags for each run as separate arguments
+    pub fn to_args(&self) -> Vec<Vec<String>>{
+        self.runs().iter().map(|run| run.to_args()).collect()
+    }
+
+    /// Create one Task per run, or one Task per frame if `single` is true
+    pub fn to_tasks<S>(&self, image_format: S, id: S, single: bool) -> VecDeque<Task> where S: Into<String>{
+        let image_format = image_format.into();
+        let id = id.into();
+        match single{
+            true => self.iter().map(|f| Task::new_blender_single(f, image_format.clone(), id.clone())).collect(),
+            false => self.runs().iter().map(|run| run.to_task(image_format.clone(), id.clone())).collect()
+        }
+    }
+}
+
+
+/// Parse a single (not excluded) item: `N`, `A-B` or `A-BxS`
+fn parse_run(run: &str, item: &str) -> GenResult<FrameRun>{
+    let error = |reason: &str| -> GenError {
+        From::from(format!("Error: Invalid item \"{}\" in frame specification: {}", item, reason))
+    };
+    let number = |s: &str| s.parse::<usize>().map_err(|_| error(&format!("\"{}\" is not a framenumber", s)));
+    let (range, step) = match run.find('x'){
+        Some(position) => (&run[..position], number(&run[position + 1..])?),
+        None => (run, 1)
+    };
+    if step == 0{
+        return Err(error("the step must be at least 1"));
+    }
+    let (start, end) = match range.find('-'){
+        Some(position) => (number(&range[..position])?, number(&range[position + 1..])?),
+        None => {
+            let frame = number(range)?;
+            (frame, frame)
+        }
+    };
+    if start > end{
+        return Err(error("the start is after the end"));
+    }
+    // Normalize the end to the last frame of the run
+    let end = end - (end - start) % step;
+    Ok(FrameRun{ start, end, step })
+}
+
+
+impl FromIterator<usize> for FrameSpec {
+    fn from_iter<I: IntoIterator<Item=usize>>(iter: I) -> Self{
+        FrameSpec{ frames: iter.into_iter().collect() }
+    }
+}
+
+impl From<&data::Frames> for FrameSpec {
+    fn from(frames: &data::Frames) -> Self{
+        match frames.is_default(){
+            true => Self::default(),
+            false => Self::from_range(frames.start, frames.end, frames.step)
+        }
+    }
+}
+
+impl FromStr for FrameSpec {
+    type Err = GenError;
+
+    fn from_str(spec: &str) -> GenResult<Self>{
+        Self::parse(spec)
+    }
+}
+
+impl TryFrom<String> for FrameSpec {
+    type Error = GenError;
+
+    fn try_from(spec: String) -> GenResult<Self>{
+        Self::parse(&spec)
+    }
+}
+
+impl From<FrameSpec> for String {
+    fn from(spec: FrameSpec) -> Self{
+        spec.to_string()
+    }
+}
+
+impl fmt::Display for FrameSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let runs: Vec<String> = self.runs().iter().map(|run| run.to_string()).collect();
+        write!(f, "{}", runs.join(","))
+    }
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let spec = FrameSpec::parse("1-10, 15, 20-40x5, !25").unwrap();
+        assert_eq!(spec.as_vec(), vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 15, 20, 30, 35, 40]);
+        assert_eq!(FrameSpec::parse("!3,1-5").unwrap().as_vec(), vec![1, 2, 4, 5]);
+        assert_eq!(FrameSpec::parse("1-20x2,!1-20x4").unwrap().as_vec(), vec![3, 7, 11, 15, 19]);
+        assert_eq!(FrameSpec::parse("1-10x4").unwrap().as_vec(), vec![1, 5, 9]);
+        assert!(FrameSpec::parse("").unwrap().is_empty());
+        assert_eq!(FrameSpec::parse("3,3,3").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(FrameSpec::parse("a").is_err());
+        assert!(FrameSpec::parse("1-").is_err());
+        assert!(FrameSpec::parse("10-1").is_err());
+        assert!(FrameSpec::parse("1-10x0").is_err());
+        assert!(FrameSpec::parse("1-10x").is_err());
+        assert!(FrameSpec::parse("-5").is_err());
+        assert!(FrameSpec::parse("1-2000000").is_err());
+        assert!(FrameSpec::parse("!x").is_err());
+        assert!(FrameSpec::parse("0-18446744073709551615").is_err());
+        assert!(FrameSpec::parse("1-5,0-18446744073709551614x2").is_err());
+        assert!(FrameSpec::parse("1-1000000,1000001").is_err());
+        assert_eq!(FrameSpec::parse("!0-18446744073709551615,1").unwrap().len(), 0);
+        assert_eq!(FrameRun{ start: 0, end: usize::MAX, step: 1 }.checked_count(), None);
+        assert_eq!(FrameRun{ start: 0, end: usize::MAX, step: 1 }.count(), usize::MAX);
+        assert_eq!(FrameRun{ start: 5, end: 1, step: 1 }.checked_count(), None);
+    }
+
+    #[test]
+    fn format() {
+        let format = |s: &str| FrameSpec::parse(s).unwrap().to_string();
+        assert_eq!(format("1-10,15,20-40x5"), "1-10,15-40x5");
+        assert_eq!(format("5,1,2,3"), "1-3,5");
+        assert_eq!(format("1,3,4,5,6"), "1,3-6");
+        assert_eq!(format("1,2"), "1-2");
+        assert_eq!(format("1,5"), "1,5");
+        assert_eq!(format("1,2,4,6"), "1,2-6x2");
+        assert_eq!(format("1,2,3,5,7"), "1-2,3-7x2");
+        assert_eq!(format("1-10,15,20,30-40x5"), "1-9,10-20x5,30-40x5");
+        assert_eq!(format("1-3,5,6,7"), "1-3,5-7");
+        assert_eq!(format("7"), "7");
+        assert_eq!(format(""), "");
+        let spec = FrameSpec::parse("1-100x3,!50-60,200").unwrap();
+        assert_eq!(FrameSpec::parse(&spec.to_string()).unwrap(), spec);
+    }
+
+    #[test]
+    fn minimal_runs() {
+        // Try every split of the frames into runs
+        fn fewest(frames: &[usize]) -> usize{
+            if frames.is_empty(){
+                return 0;
+            }
+            (1..=frames.len()).filter(|&len| {
+                let run = &frames[..len];
+                let step = if len > 1 { run[1] - run[0] } else { 1 };
+                len == 1 || ((step == 1 || len >= 3) && run.windows(2).all(|w| w[1] - w[0] == step))
+            })
+            .map(|len| 1 + fewest(&frames[len..]))
+            .min()
+            .unwrap()
+        }
+        for mask in 0..(1 << 10){
+            let spec: FrameSpec = (0..10).filter(|bit| mask & (1 << bit) != 0).collect();
+            let runs = spec.runs();
+            assert_eq!(runs.len(), fewest(&spec.as_vec()), "{}", spec);
+            assert_eq!(runs.iter().map(|run| run.count()).sum::<usize>(), spec.len());
+            assert_eq!(FrameSpec::parse(&spec.to_string()).unwrap(), spec);
+        }
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = FrameSpec::parse("1-10").unwrap();
+        let b = FrameSpec::parse("5-15").unwrap();
+        assert_eq!(a.union(&b).to_string(), "1-15");
+        assert_eq!(a.difference(&b).to_string(), "1-4");
+        assert_eq!(a.intersection(&b).to_string(), "5-10");
+        assert!(a.intersection(&b).is_subset(&a));
+        assert_eq!(a.first(), Some(1));
+        assert_eq!(b.last(), Some(15));
+    }
+
+    #[test]
+    fn compile() {
+        let spec = FrameSpec::parse("1-10,15,20-40x5,!25").unwrap();
+        assert_eq!(spec.to_args()[2], vec!["-s", "30", "-e", "40", "-j", "5"]);
+        let tasks = spec.to_tasks("PNG", "5873c0033e78b222bec2cb2a221487cf", false);
+        assert_eq!(tasks.len(), 3);
+        let frames: usize = tasks.iter().map(|t| match t.command{
+            Command::Blender(ref c) => c.frame.count(),
+            _ => 0
+        }).sum();
+        assert_eq!(frames, spec.len());
+        assert_eq!(spec.to_tasks("PNG", "5873c0033e78b222bec2cb2a221487cf", true).len(), 15);
+    }
+
+    #[test]
+    fn conversions() {
+        let frames = data::Frames{ start: 1, end: 9, current: 1, step: 2, fps: 25 };
+        assert_eq!(FrameSpec::from(&frames).to_string(), "1-9x2");
+        assert!(FrameSpec::from(&data::Frames::default()).is_empty());
+        let spec: FrameSpec = "4-6".parse().unwrap();
+        assert_eq!(serde_json::to_string(&spec).unwrap(), "\"4-6\"");
+        assert_eq!(serde_json::from_str::<FrameSpec>("\"6,5,4\"").unwrap(), spec);
+        assert!(serde_json::from_str::<FrameSpec>("\"6-4\"").is_err());
+    }
+}
@@ -108,7 +108,8 @@ impl Gaffer for Job{
                 .arg("--disable-autoexec")
                 .arg("--python")
                 .arg(pythonpath)
-                .env("BENDER_OVERRIDEFORMAT", "PNG") // use only if format was not allowed
+                .env("BENDER_OVERRIDEFORMAT", ImageFormat::Png.blender_name()) // use only if format was not allowed
+                .env("BENDER_ALLOWEDFORMATS", ImageFormat::blender_names().join(","))
                 .output()?;
 
         // Collect all lines starting with "{" for JSON
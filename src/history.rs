@@ -1,15 +1,478 @@
 //! The history module defines functionality related to a Jobs history logging capabilities
+//!
+//! A [History](struct.History.html) is a list of structured [Events](struct.Event.html) \
+//! ordered by time. Each Event has a level, the source (e.g. the service or \
+//! script that logged it, empty for bender-job itself), a kind, a message and \
+//! a optional JSON payload. Events with the same timestamp are kept in the \
+//! order they were added.
+//!
+//! Histories are serialized as list of Events. Histories that were stored \
+//! as map of timestamps to strings (e.g. by older versions or by \
+//! optimize_blend.py) still deserialize: the source and the level are \
+//! parsed from the text, so `"optimize_blend.py: Error: No camera"` becomes \
+//! a Event with the source `optimize_blend.py`, the level `Error` and the \
+//! message `Error: No camera`.
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::history::{History, Event, Level};
+//! let legacy = r#"{"2019-03-07T17:13:28.613844+00:00": "optimize_blend.py: Error: No camera"}"#;
+//! let history: History = serde_json::from_str(legacy).unwrap();
+//! let event = history.iter().next().unwrap();
+//! assert_eq!(event.source, "optimize_blend.py");
+//! assert_eq!(event.level, Level::Error);
+//! assert_eq!(event.text(), "optimize_blend.py: Error: No camera");
+//! ```
+//!
+//! Histories can be filtered with a [HistoryFilter](struct.HistoryFilter.html) \
+//! (by time, source, level, kind or keyword), merged into one timeline (see \
+//! `Job::timeline()`, which includes the histories of all Tasks) and \
+//! exported as text, JSON Lines or CSV:
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::Job;
+//! # use bender_job::history::{HistoryFilter, Level};
+//! let mut job = Job::new("/data/blendfiles/5873c0033e78b222bec2cb2a221487cf/shot.blend", "dh@atoav.com", true);
+//! job.add_history("optimize_blend.py: Error: Failed to set compute_device_type to CUDA");
+//! job.add_history("Scanning finished");
+//! let errors = job.timeline().filter(&HistoryFilter::new().source("optimize_blend").level(Level::Error));
+//! assert_eq!(errors.len(), 1);
+//! println!("{}", errors.to_csv());
+//! ```
 
 use ::*;
+use std::slice::Iter;
+use serde::{Deserialize, Deserializer};
+use csv::csv_escape;
+
+
+/// The kind of Events created from plain text
+pub const KIND_MESSAGE: &str = "message";
+
+/// The kind of Events logged by Status transitions
+pub const KIND_STATUS: &str = "status";
+
+/// The columns of the CSV export
+pub const CSV_HEADER: &str = "time,level,source,task,kind,message,payload";
+
+
+
+
+// ===========================================================================
+//                                 Level
+// ===========================================================================
+
+/// The severity of a Event
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Level{
+    Debug,
+    #[default]
+    Info,
+    Warning,
+    Error
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                 Event
+// ===========================================================================
+
+/// A single entry of a History
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Event{
+    pub time: DateTime<Utc>,
+    #[serde(default)]
+    pub level: Level,
+    #[serde(default)]
+    pub source: String,
+    #[serde(default = "default_kind")]
+    pub kind: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+    /// The id of the Task the Event belongs to (set in timelines)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>
+}
+
+fn default_kind() -> String{
+    KIND_MESSAGE.to_string()
+}
+
+impl Event{
+    /// Create a Event at the current time
+    pub fn new<S>(level: Level, message: S) -> Self where S: Into<String>{
+        Event{
+            time: Utc::now(),
+            level,
+            source: String::new(),
+            kind: default_kind(),
+            message: message.into(),
+            payload: None,
+            task: None
+        }
+    }
+
+    /// Create a Event from a plain text like `"optimize_blend.py: Error: …"`. \
+    /// A leading word without whitespace that contains `.`, `_` or `-` is \
+    /// taken as source. The level is Error if the message starts with or \
+    /// contains `Error`, Warning if it starts with `Warning` and Info otherwise
+    pub fn from_text<S>(time: DateTime<Utc>, text: S) -> Self where S: Into<String>{
+        let text = text.into();
+        let (source, message) = match text.find(": "){
+            Some(position) if is_source(&text[..position]) => (text[..position].to_string(), text[position + 2..].to_string()),
+            _ => (String::new(), text)
+        };
+        let level = if message.starts_with("Error") || message.contains("Error:") {
+                        Level::Error
+                    } else if message.starts_with("Warning") {
+                        Level::Warning
+                    } else {
+                        Level::Info
+                    };
+        Event{ time, level, source, kind: default_kind(), message, payload: None, task: None }
+    }
+
+    /// Set the time
+    pub fn at(mut self, time: DateTime<Utc>) -> Self{
+        self.time = time;
+        self
+    }
+
+    /// Set the source (e.g. `bender-worker`)
+    pub fn with_source<S>(mut self, source: S) -> Self where S: Into<String>{
+        self.source = source.into();
+        self
+    }
+
+    /// Set the kind (e.g. `status`)
+    pub fn with_kind<S>(mut self, kind: S) -> Self where S: Into<String>{
+        self.kind = kind.into();
+        self
+    }
+
+    /// Attach a JSON payload
+    pub fn with_payload(mut self, payload: serde_json::Value) -> Self{
+        self.payload = Some(payload);
+        self
+    }
+
+    /// Set the id of the Task the Event belongs to
+    pub fn with_task<S>(mut self, task: S) -> Self where S: Into<String>{
+        self.task = Some(task.into());
+        self
+    }
+
+    /// Return the message prefixed with the source, like it was written in \
+    /// plain text histories
+    pub fn text(&self) -> String{
+        match self.source.is_empty(){
+            true => self.message.clone(),
+            false => format!("{}: {}", self.source, self.message)
+        }
+    }
+
+    /// Return the Event formatted like `[<time>]: <text>`
+    pub fn format(&self) -> String{
+        format!("[{}]: {}", self.time, self.text())
+    }
+
+    /// Return the Event as a line of CSV (see `CSV_HEADER`)
+    pub fn to_csv(&self) -> String{
+        let payload = self.payload.as_ref().map(|p| p.to_string()).unwrap_or_default();
+        let fields = [
+            self.time.to_rfc3339(),
+            self.level.to_string(),
+            self.source.clone(),
+            self.task.clone().unwrap_or_default(),
+            self.kind.clone(),
+            self.message.clone(),
+            payload
+        ];
+        fields.iter().map(|field| csv_escape(field)).collect::<Vec<String>>().join(",")
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format())
+    }
+}
+
+
+/// Return true if the prefix of a plain text looks like the name of a service
+fn is_source(prefix: &str) -> bool{
+    !prefix.is_empty() &&
+    !prefix.contains(char::is_whitespace) &&
+    prefix.contains(&['.', '_', '-'][..])
+}
+
+
 
 
 // ===========================================================================
 //                                History
 // ===========================================================================
 
-pub type History = BTreeMap<DateTime<Utc>, String>;
+/// The Events of a Job, ordered by time
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct History(Vec<Event>);
 
+impl History{
+    pub fn new() -> Self{
+        Self::default()
+    }
 
+    pub fn len(&self) -> usize{
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.0.is_empty()
+    }
+
+    /// Iterate over the Events, oldest first
+    pub fn iter(&self) -> Iter<'_, Event>{
+        self.0.iter()
+    }
+
+    /// Iterate over the texts of the Events, oldest first
+    pub fn texts(&self) -> impl DoubleEndedIterator<Item=String> + '_{
+        self.0.iter().map(|event| event.text())
+    }
+
+    /// Iterate over the times of the Events, oldest first
+    #[deprecated(note = "History is no longer a map, use `iter()` and `Event::time`")]
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item=&DateTime<Utc>> + '_{
+        self.0.iter().map(|event| &event.time)
+    }
+
+    /// Iterate over the texts of the Events, oldest first
+    #[deprecated(note = "History is no longer a map, use `texts()`")]
+    pub fn values(&self) -> impl DoubleEndedIterator<Item=String> + '_{
+        self.texts()
+    }
+
+    /// Add a Event after all Events with the same or a earlier time. Return \
+    /// false if the exact same Event is already in the History
+    pub fn push(&mut self, event: Event) -> bool{
+        let start = self.0.iter().rposition(|e| e.time < event.time).map_or(0, |p| p + 1);
+        let end = self.0.iter().rposition(|e| e.time <= event.time).map_or(0, |p| p + 1);
+        if self.0[start..end].contains(&event){
+            return false;
+        }
+        self.0.insert(end, event);
+        true
+    }
+
+    /// Add a Event parsed from plain text (see `Event::from_text()`). Unlike \
+    /// the former map based History this never replaces a Event with the same \
+    /// time and returns whether the Event was added
+    pub fn insert<S>(&mut self, time: DateTime<Utc>, text: S) -> bool where S: Into<String>{
+        self.push(Event::from_text(time, text))
+    }
+
+    /// Move all Events of other into self, skipping Events self already has
+    pub fn append(&mut self, other: &mut History){
+        for event in other.0.drain(..){
+            self.push(event);
+        }
+    }
+
+    /// Merge several Histories into one timeline
+    pub fn merged<'a, I>(histories: I) -> Self where I: IntoIterator<Item=&'a History>{
+        let mut merged = History::new();
+        for history in histories{
+            merged.append(&mut history.clone());
+        }
+        merged
+    }
+
+    //  ------------------------------ QUERIES ------------------------------
+
+    /// Return the Events that match the filter
+    pub fn filter(&self, filter: &HistoryFilter) -> Self{
+        History(self.0.iter().filter(|event| filter.matches(event)).cloned().collect())
+    }
+
+    /// Return the Events from start (inclusive) to end (exclusive)
+    pub fn between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self{
+        self.filter(&HistoryFilter::new().since(start).until(end))
+    }
+
+    /// Return the Events whose source starts with the prefix
+    pub fn from_source(&self, prefix: &str) -> Self{
+        self.filter(&HistoryFilter::new().source(prefix))
+    }
+
+    /// Return the Events with the level or a more severe one
+    pub fn at_least(&self, level: Level) -> Self{
+        self.filter(&HistoryFilter::new().level(level))
+    }
+
+    /// Return the Events whose text contains the keyword (ignoring case)
+    pub fn containing(&self, keyword: &str) -> Self{
+        self.filter(&HistoryFilter::new().keyword(keyword))
+    }
+
+    //  ------------------------------ EXPORT -------------------------------
+
+    /// Export the Events as text, one line per Event
+    pub fn to_text(&self) -> String{
+        self.0.iter().map(|event| {
+            let task = event.task.as_ref().map(|task| format!(" [{}]", task)).unwrap_or_default();
+            format!("{} {:<7}{} {}\n", event.time.to_rfc3339(), event.level.to_string(), task, event.text())
+        }).collect()
+    }
+
+    /// Export the Events as JSON Lines, one JSON object per line
+    pub fn to_json_lines(&self) -> GenResult<String>{
+        let mut lines = String::new();
+        for event in self.0.iter(){
+            lines.push_str(&serde_json::to_string(event)?);
+            lines.push('\n');
+        }
+        Ok(lines)
+    }
+
+    /// Export the Events as CSV with a header line
+    pub fn to_csv(&self) -> String{
+        let mut csv = format!("{}\n", CSV_HEADER);
+        for event in self.0.iter(){
+            csv.push_str(&event.to_csv());
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+impl<'de> Deserialize<'de> for History{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de>{
+        /// Histories are either lists of Events or (legacy) maps of texts
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Stored{
+            Events(Vec<Event>),
+            Legacy(BTreeMap<DateTime<Utc>, String>)
+        }
+        let mut history = History::new();
+        match Stored::deserialize(deserializer)?{
+            Stored::Events(events) => events.into_iter().for_each(|event| { history.push(event); }),
+            Stored::Legacy(map) => map.into_iter().for_each(|(time, text)| { history.insert(time, text); })
+        }
+        Ok(history)
+    }
+}
+
+impl<'a> IntoIterator for &'a History{
+    type Item = &'a Event;
+    type IntoIter = Iter<'a, Event>;
+
+    fn into_iter(self) -> Self::IntoIter{
+        self.0.iter()
+    }
+}
+
+
+
+
+// ===========================================================================
+//                              HistoryFilter
+// ===========================================================================
+
+/// Selects Events of a History. All criteria that are set have to match
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HistoryFilter{
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub source: Option<String>,
+    pub level: Option<Level>,
+    pub kind: Option<String>,
+    pub keyword: Option<String>
+}
+
+impl HistoryFilter{
+    /// A filter that matches every Event
+    pub fn new() -> Self{
+        Self::default()
+    }
+
+    /// Only Events at or after the time
+    pub fn since(mut self, start: DateTime<Utc>) -> Self{
+        self.start = Some(start);
+        self
+    }
+
+    /// Only Events before the time
+    pub fn until(mut self, end: DateTime<Utc>) -> Self{
+        self.end = Some(end);
+        self
+    }
+
+    /// Only Events whose source starts with the prefix (e.g. `optimize_blend`)
+    pub fn source<S>(mut self, prefix: S) -> Self where S: Into<String>{
+        self.source = Some(prefix.into());
+        self
+    }
+
+    /// Only Events with the level or a more severe one
+    pub fn level(mut self, level: Level) -> Self{
+        self.level = Some(level);
+        self
+    }
+
+    /// Only Events of the kind (e.g. `status`)
+    pub fn kind<S>(mut self, kind: S) -> Self where S: Into<String>{
+        self.kind = Some(kind.into());
+        self
+    }
+
+    /// Only Events whose text contains the keyword (ignoring case)
+    pub fn keyword<S>(mut self, keyword: S) -> Self where S: Into<String>{
+        self.keyword = Some(keyword.into().to_lowercase());
+        self
+    }
+
+    /// Return true if the Event matches all criteria
+    pub fn matches(&self, event: &Event) -> bool{
+        if let Some(start) = self.start{
+            if event.time < start{ return false; }
+        }
+        if let Some(end) = self.end{
+            if event.time >= end{ return false; }
+        }
+        if let Some(ref prefix) = self.source{
+            if !event.source.starts_with(prefix.as_str()){ return false; }
+        }
+        if let Some(level) = self.level{
+            if event.level < level{ return false; }
+        }
+        if let Some(ref kind) = self.kind{
+            if event.kind != *kind{ return false; }
+        }
+        match self.keyword{
+            Some(ref keyword) => event.text().to_lowercase().contains(keyword.as_str()),
+            None => true
+        }
+    }
+}
+
+
+
+
+// ===========================================================================
+//                              HistoryMethods
+// ===========================================================================
 
 pub trait HistoryMethods{
     fn last(&self) -> Option<Event>;
@@ -21,10 +484,7 @@ pub trait HistoryMethods{
 
 impl HistoryMethods for History{
     fn last(&self) -> Option<Event>{
-        match self.iter().next_back(){
-            Some(e) => Some((e.0.clone(), e.1.clone())),
-            None => None
-        }
+        self.0.last().cloned()
     }
 
     fn format_last(&self) -> String{
@@ -33,36 +493,178 @@ impl HistoryMethods for History{
             None => "".to_string()
         }
     }
+
     fn last_message(&self) -> String{
         match self.last(){
-            Some(event) => event.message(),
+            Some(event) => event.text(),
             None => "".to_string()
         }
     }
 }
 
 
+
+
 // ===========================================================================
-//                                Event
+//                                   Job
 // ===========================================================================
 
-pub type Event = (DateTime<Utc>, String);
+impl Job{
+    /// Return the history of the Job merged with the histories of all its \
+    /// Tasks in one timeline. Events of Tasks are marked with the Task id
+    pub fn timeline(&self) -> History{
+        let mut timeline = self.history.clone();
+        for task in self.tasks.iter(){
+            for event in task.history.iter(){
+                timeline.push(event.clone().with_task(task.id.as_str()));
+            }
+        }
+        timeline
+    }
+}
 
 
 
-pub trait EventMethods{
-    fn format(&self) -> String;
-    fn message(&self) -> String;
-}
 
 
 
-impl EventMethods for Event{
-    fn format(&self) -> String{
-        format!("[{}]: {}", self.0.to_string(), self.1)
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{JobBuilder, TEST_JOB_ID};
+
+    fn time(s: &str) -> DateTime<Utc>{
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_text() {
+        let t = time("2019-03-07T17:13:28Z");
+        let event = Event::from_text(t, "optimize_blend.py: Found these cycles devices: CPU");
+        assert_eq!((event.source.as_str(), event.level), ("optimize_blend.py", Level::Info));
+        assert_eq!(event.message, "Found these cycles devices: CPU");
+        let event = Event::from_text(t, "Error: Job::status::queue() failed: was not atomized");
+        assert_eq!((event.source.as_str(), event.level), ("", Level::Error));
+        let event = Event::from_text(t, "Validated with version: 2.80");
+        assert_eq!(event.source, "");
+        assert_eq!(Event::from_text(t, "Warning: slow").level, Level::Warning);
+    }
+
+    #[test]
+    fn same_time() {
+        let t = time("2019-03-07T17:13:28Z");
+        let mut history = History::new();
+        assert!(history.insert(t, "second"));
+        assert!(history.insert(t - chrono::Duration::seconds(1), "first"));
+        assert!(history.insert(t, "third"));
+        assert!(!history.insert(t, "second"));
+        assert_eq!(history.texts().collect::<Vec<_>>(), vec!["first", "second", "third"]);
+        assert_eq!(history.last_message(), "third");
+
+        let mut other = History::new();
+        other.insert(t, "third");
+        other.insert(t, "fourth");
+        history.append(&mut other);
+        assert_eq!(history.len(), 4);
+        assert!(other.is_empty());
+    }
+
+    #[test]
+    fn serialization() {
+        let legacy = r#"{"2019-03-07T17:13:28.613844+00:00": "optimize_blend.py: Sucessfully started blender", "2019-03-07T17:13:28.614029+00:00": "optimize_blend.py: Error: Failed to set compute_device_type to CUDA"}"#;
+        let history: History = serde_json::from_str(legacy).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.last().unwrap().level, Level::Error);
+
+        let mut history = history;
+        let event = Event::new(Level::Debug, "Tile 3 of 16")
+                          .at(time("2019-03-07T17:13:29Z"))
+                          .with_source("bender-worker")
+                          .with_kind("render")
+                          .with_payload(serde_json::json!({"tile": 3}));
+        history.push(event.clone());
+        let serialized = serde_json::to_string(&history).unwrap();
+        assert!(serialized.starts_with("[{"));
+        assert!(serialized.contains(r#""level":"debug","source":"bender-worker","kind":"render""#));
+        let deserialized: History = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, history);
+        assert_eq!(deserialized.last(), Some(event));
+        assert_eq!(deserialized.format_last(), "[2019-03-07 17:13:29 UTC]: bender-worker: Tile 3 of 16");
+    }
+
+
+    fn history() -> History{
+        let mut history = History::new();
+        history.insert(time("2019-01-01T10:00:00Z"), "Validated the request");
+        history.insert(time("2019-01-01T10:05:00Z"), "optimize_blend.py: Warning: No GPU found");
+        history.insert(time("2019-01-01T10:06:00Z"), "optimize_blend.py: Error: Failed to set compute_device_type to CUDA");
+        history.insert(time("2019-01-01T11:00:00Z"), "bender-worker: Rendered frame 12, \"final\"");
+        history
+    }
+
+    #[test]
+    fn filters() {
+        let history = history();
+        assert_eq!(history.between(time("2019-01-01T10:05:00Z"), time("2019-01-01T11:00:00Z")).len(), 2);
+        assert_eq!(history.from_source("optimize_blend").len(), 2);
+        assert_eq!(history.from_source("bender").len(), 1);
+        assert_eq!(history.at_least(Level::Warning).len(), 2);
+        assert_eq!(history.at_least(Level::Error).len(), 1);
+        assert_eq!(history.containing("CUDA").len(), 1);
+        assert_eq!(history.containing("frame 12").len(), 1);
+
+        let filter = HistoryFilter::new()
+            .since(time("2019-01-01T10:01:00Z"))
+            .source("optimize_blend")
+            .level(Level::Warning)
+            .keyword("gpu");
+        let filtered = history.filter(&filter);
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered.last_message(), "optimize_blend.py: Warning: No GPU found");
+        assert_eq!(history.filter(&HistoryFilter::new()), history);
+        assert!(history.filter(&HistoryFilter::new().kind(KIND_STATUS)).is_empty());
     }
 
-    fn message(&self) -> String{
-        self.1.clone()
+    #[test]
+    fn export() {
+        let history = history();
+        let text = history.to_text();
+        assert_eq!(text.lines().count(), 4);
+        assert_eq!(text.lines().nth(2).unwrap(), "2019-01-01T10:06:00+00:00 error   optimize_blend.py: Error: Failed to set compute_device_type to CUDA");
+
+        let lines = history.to_json_lines().unwrap();
+        let events: Vec<Event> = lines.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(events, history.iter().cloned().collect::<Vec<Event>>());
+
+        let csv = history.to_csv();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[0], CSV_HEADER);
+        assert_eq!(rows[4], "2019-01-01T11:00:00+00:00,info,bender-worker,,message,\"Rendered frame 12, \"\"final\"\"\",");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn timeline() {
+        let mut job = JobBuilder::new().build();
+        job.history = History::new();
+        job.history.insert(time("2019-01-01T10:00:00Z"), "Validated the request");
+        job.history.insert(time("2019-01-01T12:00:00Z"), "Finished the Job");
+        let mut task = Task::new_blender_single(12, "PNG", TEST_JOB_ID);
+        task.history.insert(time("2019-01-01T11:00:00Z"), "bender-worker: Rendered frame 12");
+        let id = task.id.clone();
+        job.tasks.push_back(task);
+
+        let timeline = job.timeline();
+        let tasks: Vec<Option<String>> = timeline.iter().map(|event| event.task.clone()).collect();
+        assert_eq!(tasks, vec![None, Some(id.clone()), None]);
+        assert!(timeline.to_text().contains(&format!("[{}] bender-worker: Rendered frame 12", id)));
+        assert_eq!(job.history.len(), 2);
+
+        let merged = History::merged(vec![&job.history, &job.tasks[0].history]);
+        assert_eq!(merged.len(), 3);
+    }
+}
@@ -0,0 +1,228 @@
+//! The imageformat module holds the [ImageFormat](enum.ImageFormat.html) enum, \
+//! which lists the still image formats bender allows for rendering. Each \
+//! variant knows its Blender name (as used in `-F` and in \
+//! `scene.render.image_settings.file_format`) and the extension Blender \
+//! actually writes.
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::ImageFormat;
+//! let format = ImageFormat::from_blender("OPEN_EXR").unwrap();
+//! assert_eq!(format.extension(), "exr");
+//! assert!(format.is_hdr());
+//! assert_eq!(format.to_string(), "OPEN_EXR");
+//! ```
+
+use ::*;
+use std::str::FromStr;
+use verify::ImageKind;
+
+
+/// The still image formats bender allows. Video formats are not allowed, \
+/// because the frames of a job are rendered independently. Serialized as \
+/// the Blender name.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFormat{
+    #[serde(rename = "PNG")]
+    Png,
+    #[serde(rename = "BMP")]
+    Bmp,
+    #[serde(rename = "JPEG")]
+    Jpeg,
+    #[serde(rename = "JPEG2000")]
+    Jpeg2000,
+    #[serde(rename = "TARGA")]
+    Targa,
+    #[serde(rename = "TARGA_RAW")]
+    TargaRaw,
+    #[serde(rename = "CINEON")]
+    Cineon,
+    #[serde(rename = "DPX")]
+    Dpx,
+    #[serde(rename = "OPEN_EXR_MULTILAYER")]
+    OpenExrMultilayer,
+    #[serde(rename = "OPEN_EXR")]
+    OpenExr,
+    #[serde(rename = "HDR")]
+    Hdr,
+    #[serde(rename = "TIFF")]
+    Tiff
+}
+
+impl ImageFormat{
+    /// All allowed formats
+    pub const ALL: [ImageFormat; 12] = [
+        ImageFormat::Png,
+        ImageFormat::Bmp,
+        ImageFormat::Jpeg,
+        ImageFormat::Jpeg2000,
+        ImageFormat::Targa,
+        ImageFormat::TargaRaw,
+        ImageFormat::Cineon,
+        ImageFormat::Dpx,
+        ImageFormat::OpenExrMultilayer,
+        ImageFormat::OpenExr,
+        ImageFormat::Hdr,
+        ImageFormat::Tiff
+    ];
+
+    /// Return the format for a Blender name (e.g. "OPEN_EXR"), or None if \
+    /// the format is not allowed
+    pub fn from_blender<S>(name: S) -> Option<Self> where S: AsRef<str>{
+        let name = name.as_ref();
+        Self::ALL.iter().cloned().find(|format| format.blender_name() == name)
+    }
+
+    /// Return the Blender names of all allowed formats
+    pub fn blender_names() -> Vec<&'static str>{
+        Self::ALL.iter().map(|format| format.blender_name()).collect()
+    }
+
+    /// The name Blender uses for the format
+    pub fn blender_name(&self) -> &'static str{
+        match self{
+            ImageFormat::Png => "PNG",
+            ImageFormat::Bmp => "BMP",
+            ImageFormat::Jpeg => "JPEG",
+            ImageFormat::Jpeg2000 => "JPEG2000",
+            ImageFormat::Targa => "TARGA",
+            ImageFormat::TargaRaw => "TARGA_RAW",
+            ImageFormat::Cineon => "CINEON",
+            ImageFormat::Dpx => "DPX",
+            ImageFormat::OpenExrMultilayer => "OPEN_EXR_MULTILAYER",
+            ImageFormat::OpenExr => "OPEN_EXR",
+            ImageFormat::Hdr => "HDR",
+            ImageFormat::Tiff => "TIFF"
+        }
+    }
+
+    /// The file extension Blender writes (without the dot)
+    pub fn extension(&self) -> &'static str{
+        match self{
+            ImageFormat::Png => "png",
+            ImageFormat::Bmp => "bmp",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Jpeg2000 => "jp2",
+            ImageFormat::Targa => "tga",
+            ImageFormat::TargaRaw => "tga",
+            ImageFormat::Cineon => "cin",
+            ImageFormat::Dpx => "dpx",
+            ImageFormat::OpenExrMultilayer => "exr",
+            ImageFormat::OpenExr => "exr",
+            ImageFormat::Hdr => "hdr",
+            ImageFormat::Tiff => "tif"
+        }
+    }
+
+    /// Return true if the format stores pixels without loss with Blender's \
+    /// default settings (JPEG and JPEG2000 are lossy, Radiance HDR quantizes \
+    /// to a shared exponent)
+    pub fn is_lossless(&self) -> bool{
+        !matches!(self, ImageFormat::Jpeg | ImageFormat::Jpeg2000 | ImageFormat::Hdr)
+    }
+
+    /// Return true if the format stores high dynamic range (float) pixels
+    pub fn is_hdr(&self) -> bool{
+        matches!(self, ImageFormat::OpenExr | ImageFormat::OpenExrMultilayer | ImageFormat::Hdr)
+    }
+
+    /// The typical number of bytes per pixel of the uncompressed image data \
+    /// (with Blender's default color depth and channels). This is meant for \
+    /// rough size estimates only
+    pub fn bytes_per_pixel(&self) -> usize{
+        match self{
+            ImageFormat::Png => 4,
+            ImageFormat::Bmp => 3,
+            ImageFormat::Jpeg => 3,
+            ImageFormat::Jpeg2000 => 3,
+            ImageFormat::Targa => 4,
+            ImageFormat::TargaRaw => 4,
+            ImageFormat::Cineon => 4,
+            ImageFormat::Dpx => 4,
+            ImageFormat::OpenExrMultilayer => 8,
+            ImageFormat::OpenExr => 8,
+            ImageFormat::Hdr => 4,
+            ImageFormat::Tiff => 4
+        }
+    }
+
+    /// The kind of file the [verify](../verify/index.html) module checks for \
+    /// this format, or None if the format cannot be verified
+    pub fn image_kind(&self) -> Option<ImageKind>{
+        match self{
+            ImageFormat::Png => Some(ImageKind::Png),
+            ImageFormat::Bmp => Some(ImageKind::Bmp),
+            ImageFormat::Jpeg => Some(ImageKind::Jpeg),
+            ImageFormat::Targa | ImageFormat::TargaRaw => Some(ImageKind::Targa),
+            ImageFormat::OpenExr | ImageFormat::OpenExrMultilayer => Some(ImageKind::OpenExr),
+            ImageFormat::Hdr => Some(ImageKind::Hdr),
+            ImageFormat::Tiff => Some(ImageKind::Tiff),
+            ImageFormat::Jpeg2000 | ImageFormat::Cineon | ImageFormat::Dpx => None
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = GenError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>{
+        match Self::from_blender(s){
+            Some(format) => Ok(format),
+            None => Err(From::from(format!("Error: \"{}\" is not a allowed image format (allowed: {})", s, Self::blender_names().join(", "))))
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.blender_name())
+    }
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip_names() {
+        for format in ImageFormat::ALL.iter(){
+            assert_eq!(ImageFormat::from_blender(format.blender_name()), Some(*format));
+            assert_eq!(format.to_string().parse::<ImageFormat>().unwrap(), *format);
+            let json = serde_json::to_string(format).unwrap();
+            assert_eq!(json, format!("\"{}\"", format.blender_name()));
+        }
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(ImageFormat::OpenExr.extension(), "exr");
+        assert_eq!(ImageFormat::Jpeg.extension(), "jpg");
+        assert_eq!(ImageFormat::Tiff.extension(), "tif");
+        assert_eq!(ImageFormat::TargaRaw.extension(), "tga");
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(ImageFormat::from_blender("FFMPEG"), None);
+        assert_eq!(ImageFormat::from_blender("png"), None);
+        assert!("AVI_JPEG".parse::<ImageFormat>().is_err());
+    }
+
+    #[test]
+    fn properties() {
+        assert!(ImageFormat::Png.is_lossless());
+        assert!(!ImageFormat::Jpeg.is_lossless());
+        assert!(ImageFormat::OpenExrMultilayer.is_hdr());
+        assert!(!ImageFormat::Tiff.is_hdr());
+        assert_eq!(ImageFormat::OpenExr.bytes_per_pixel(), 8);
+    }
+}
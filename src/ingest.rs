@@ -0,0 +1,455 @@
+//! The ingest module is the receiving side of the [Uploader](../upload/struct.Uploader.html). \
+//! It implements the Ingest trait for [Job](../job/struct.Job.html), which \
+//! takes a uploaded frame as a stream together with the claimed filesize \
+//! and Blake2b hash (see [FrameClaim](struct.FrameClaim.html)).
+//!
+//! The stream is written to a temporary file in `JobPaths::frames` while it \
+//! is hashed and counted. Only if filesize and hash match the claim, the \
+//! file is moved atomically to its final path and the matching Task's frame \
+//! is updated (filesize, hash and uploaded flag). Frames that have already \
+//! been ingested are rejected, as are outputs whose path (taken from the \
+//! File Output slots of the blendfile) would leave the frames directory.
+//!
+//! ```no_run
+//! # extern crate bender_job;
+//! # use bender_job::Job;
+//! # use bender_job::ingest::{Ingest, FrameClaim};
+//! # use std::collections::HashMap;
+//! # use std::fs::File;
+//! let mut job = Job::from_datajson("/data/blendfiles/5873c0033e78b222bec2cb2a221487cf/data.json").unwrap();
+//! # let fields: HashMap<String, String> = HashMap::new();
+//! // fields holds the text fields of the multipart form
+//! let claim = FrameClaim::from_fields(&fields).unwrap();
+//! let file = File::open("/tmp/upload").unwrap();
+//! match job.ingest_frame(&claim, file){
+//!     Ok(receipt) => println!("Stored {}", receipt.path.to_string_lossy()),
+//!     Err(err) => eprintln!("{}", err)
+//! }
+//! job.write_to_file().unwrap();
+//! ```
+
+use ::*;
+use std::io::{Read, Write};
+use atomicwrites::{AtomicFile, DisallowOverwrite};
+use blake2::{Blake2b, Digest};
+use command::BlenderCommand;
+use std::path::{Path, Component};
+
+
+
+
+// ===========================================================================
+//                               IngestError
+// ===========================================================================
+
+/// The reasons a uploaded frame is rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum IngestError{
+    /// A field of the form is missing
+    MissingField(String),
+    /// A field of the form couldn't be parsed
+    InvalidField{ field: String, value: String },
+    /// No Task of the Job renders this frame
+    UnknownFrame(usize),
+    /// The Task that renders this frame has no such output
+    UnknownOutput{ frame: usize, output: String },
+    /// The path of the frame (or output) leaves the frames directory
+    OutsideFrames(String),
+    /// The frame has been ingested before
+    Duplicate(String),
+    /// The received file doesn't have the claimed size
+    SizeMismatch{ claimed: usize, received: usize },
+    /// The received file doesn't have the claimed hash
+    HashMismatch{ claimed: String, received: String },
+    /// Reading the stream or writing the file failed
+    Io(String)
+}
+
+impl fmt::Display for IngestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self{
+            IngestError::MissingField(field) => write!(f, "Error: The upload is missing the field \"{}\"", field),
+            IngestError::InvalidField{ field, value } => write!(f, "Error: The upload has a invalid value for \"{}\": {}", field, value),
+            IngestError::UnknownFrame(frame) => write!(f, "Error: No Task renders frame {}", frame),
+            IngestError::UnknownOutput{ frame, output } => write!(f, "Error: Frame {} has no output \"{}\"", frame, output),
+            IngestError::OutsideFrames(path) => write!(f, "Error: The path {} leaves the frames directory", path),
+            IngestError::Duplicate(what) => write!(f, "Error: {} has been uploaded before", what),
+            IngestError::SizeMismatch{ claimed, received } => write!(f, "Error: The upload should have {} bytes, but has {} bytes", claimed, received),
+            IngestError::HashMismatch{ claimed, received } => write!(f, "Error: The upload should have the hash {}, but has the hash {}", claimed, received),
+            IngestError::Io(message) => write!(f, "Error: Couldn't store the upload: {}", message)
+        }
+    }
+}
+
+impl Error for IngestError {}
+
+
+
+
+// ===========================================================================
+//                                FrameClaim
+// ===========================================================================
+
+/// What the uploader claims about a uploaded frame. The output is the name \
+/// of the frame output (None if frames consist of a single file)
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameClaim{
+    pub framenumber: usize,
+    pub output: Option<String>,
+    pub filesize: usize,
+    pub hash: String
+}
+
+impl FrameClaim{
+    /// Create a new FrameClaim for a frame consisting of a single file
+    pub fn new<S>(framenumber: usize, filesize: usize, hash: S) -> Self where S: Into<String>{
+        FrameClaim{
+            framenumber,
+            output: None,
+            filesize,
+            hash: hash.into()
+        }
+    }
+
+    /// Claim the given output of the frame
+    pub fn with_output<S>(mut self, output: S) -> Self where S: Into<String>{
+        self.output = Some(output.into());
+        self
+    }
+
+    /// Read the claim from the text fields of the multipart form posted by \
+    /// the Uploader (`frame`, `filesize`, `filehash` and optionally `output`)
+    pub fn from_fields(fields: &HashMap<String, String>) -> Result<Self, IngestError>{
+        let field = |name: &str| fields.get(name).ok_or_else(|| IngestError::MissingField(name.to_string()));
+        let number = |name: &str| -> Result<usize, IngestError>{
+            let value = field(name)?;
+            value.trim().parse().map_err(|_| IngestError::InvalidField{ field: name.to_string(), value: value.clone() })
+        };
+        Ok(FrameClaim{
+            framenumber: number("frame")?,
+            output: fields.get("output").cloned(),
+            filesize: number("filesize")?,
+            hash: field("filehash")?.trim().to_lowercase()
+        })
+    }
+}
+
+impl fmt::Display for FrameClaim {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.output{
+            Some(ref output) => write!(f, "Frame {} ({})", self.framenumber, output),
+            None => write!(f, "Frame {}", self.framenumber)
+        }
+    }
+}
+
+
+/// Describes a sucessfully ingested frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct IngestReceipt{
+    pub task_id: String,
+    pub framenumber: usize,
+    pub output: Option<String>,
+    pub path: PathBuf,
+    pub filesize: usize,
+    pub hash: String
+}
+
+
+
+
+// ===========================================================================
+//                                  Ingest
+// ===========================================================================
+
+/// This Trait is implemented by a [Job](struct.Job.html) and stores uploaded \
+/// frames in the Job's frames directory.
+pub trait Ingest{
+    /// Verify the stream against the claim, store it and mark the frame as \
+    /// uploaded. The Job is not written to disk.
+    fn ingest_frame<R: Read>(&mut self, claim: &FrameClaim, reader: R) -> Result<IngestReceipt, IngestError>;
+
+    /// Return the path in `JobPaths::frames` where the frame (or output) is \
+    /// stored
+    fn ingest_path(&self, framenumber: usize, output: Option<&str>) -> Result<PathBuf, IngestError>;
+}
+
+impl Ingest for Job{
+    fn ingest_frame<R: Read>(&mut self, claim: &FrameClaim, reader: R) -> Result<IngestReceipt, IngestError>{
+        let path = self.ingest_path(claim.framenumber, claim.output.as_deref())?;
+        let (task_id, already_uploaded) = {
+            let (task, command) = find_command(&self.tasks, claim.framenumber)?;
+            let frame = &command.frame[&claim.framenumber];
+            let uploaded = match claim.output{
+                Some(ref output) => frame.get_output(output).filter(|o| o.is_uploaded()).is_some(),
+                None => frame.is_uploaded()
+            };
+            (task.id.clone(), uploaded)
+        };
+        if already_uploaded || path.exists(){
+            return Err(IngestError::Duplicate(claim.to_string()));
+        }
+
+        // Write to a temporary file first and only move it into place if the
+        // upload matches the claim
+        if let Some(parent) = path.parent(){
+            fs::create_dir_all(parent).map_err(|err| IngestError::Io(err.to_string()))?;
+        }
+        let atomicfile = AtomicFile::new(&path, DisallowOverwrite);
+        let hash = atomicfile.write(|f| receive(claim, reader, f))
+                             .map_err(|err| match err{
+                                 atomicwrites::Error::User(err) => err,
+                                 atomicwrites::Error::Internal(ref err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                                     IngestError::Duplicate(claim.to_string())
+                                 },
+                                 atomicwrites::Error::Internal(err) => IngestError::Io(err.to_string())
+                             })?;
+
+        let task = self.tasks.get_mut_by_id(task_id.as_str()).unwrap();
+        if let Command::Blender(ref mut command) = task.command{
+            let frame = command.frame.get_mut(&claim.framenumber).unwrap();
+            match claim.output{
+                Some(ref output) => {
+                    let output = frame.output_entry(output.as_str());
+                    output.set_filesize(claim.filesize);
+                    output.set_hash(hash.as_str());
+                    output.set_uploaded();
+                },
+                None => {
+                    frame.set_filesize(claim.filesize);
+                    frame.set_hash(hash.as_str());
+                    frame.set_uploaded();
+                }
+            }
+        }
+
+        Ok(IngestReceipt{
+            task_id,
+            framenumber: claim.framenumber,
+            output: claim.output.clone(),
+            path,
+            filesize: claim.filesize,
+            hash
+        })
+    }
+
+    fn ingest_path(&self, framenumber: usize, output: Option<&str>) -> Result<PathBuf, IngestError>{
+        let (_, command) = find_command(&self.tasks, framenumber)?;
+        output_path(&naming_command(&self.paths, command), framenumber, output)
+    }
+}
+
+
+/// Return a copy of the command (without its frames) that names files like \
+/// the worker did, but in the frames directory of the Job. Building it once \
+/// per Task avoids copying the frames for every single frame
+pub(crate) fn naming_command(paths: &JobPaths, command: &BlenderCommand) -> BlenderCommand{
+    BlenderCommand{
+        frame: frames::Frames::new(),
+        image_format: command.image_format.clone(),
+        blendfile: command.blendfile.clone().or_else(|| Some(paths.blend.clone())),
+        outpath: Some(paths.frames.clone()),
+        command: None,
+        line: None,
+        template: command.template.clone(),
+        outputs: command.outputs.clone()
+    }
+}
+
+
+/// Return the path of the frame (or output) for a command returned by \
+/// `naming_command()`. Paths that leave the frames directory (e.g. because \
+/// a File Output slot contains `..`) are rejected
+pub(crate) fn output_path(command: &BlenderCommand, framenumber: usize, output: Option<&str>) -> Result<PathBuf, IngestError>{
+    let paths = command.paths_for_frame(framenumber);
+    let path = match (output, command.outputs.is_empty()){
+        (None, true) => Ok(paths[0].1.clone()),
+        (Some(output), false) => {
+            paths.into_iter()
+                 .find(|(name, _)| name == output)
+                 .map(|(_, path)| path)
+                 .ok_or_else(|| IngestError::UnknownOutput{ frame: framenumber, output: output.to_string() })
+        },
+        (Some(output), true) => Err(IngestError::UnknownOutput{ frame: framenumber, output: output.to_string() }),
+        (None, false) => Err(IngestError::MissingField("output".to_string()))
+    }?;
+    let frames = command.outpath.clone().unwrap_or_default();
+    match path.strip_prefix(&frames){
+        Ok(relative) if relative.components().next().is_some() && is_contained(relative) => Ok(path),
+        _ => Err(IngestError::OutsideFrames(path.to_string_lossy().to_string()))
+    }
+}
+
+
+/// Return true if the path is relative and has no `..` components
+pub(crate) fn is_contained(path: &Path) -> bool{
+    path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+
+/// Return the Task and the BlenderCommand that renders the frame
+fn find_command(tasks: &Tasks, framenumber: usize) -> Result<(&Task, &BlenderCommand), IngestError>{
+    tasks.iter()
+         .filter_map(|task| match task.command{
+             Command::Blender(ref command) if command.frame.has_frame(framenumber) => Some((task, command)),
+             _ => None
+         })
+         .next()
+         .ok_or(IngestError::UnknownFrame(framenumber))
+}
+
+
+/// Copy the stream into the file while counting and hashing it. Return the \
+/// hash if size and hash match the claim. At most one byte more than the \
+/// claimed size is read.
+fn receive<R: Read, W: Write>(claim: &FrameClaim, reader: R, writer: &mut W) -> Result<String, IngestError>{
+    let mut reader = reader.take((claim.filesize as u64).saturating_add(1));
+    let mut hasher = Blake2b::new();
+    let mut received = 0;
+    let mut buffer = vec![0; 64 * 1024];
+    loop{
+        let n = reader.read(&mut buffer).map_err(|err| IngestError::Io(err.to_string()))?;
+        if n == 0{ break; }
+        hasher.input(&buffer[..n]);
+        writer.write_all(&buffer[..n]).map_err(|err| IngestError::Io(err.to_string()))?;
+        received += n;
+    }
+    if received != claim.filesize{
+        return Err(IngestError::SizeMismatch{ claimed: claim.filesize, received });
+    }
+    let hash = format!("{:x}", hasher.result());
+    if hash != claim.hash.to_lowercase(){
+        return Err(IngestError::HashMismatch{ claimed: claim.hash.clone(), received: hash });
+    }
+    Ok(hash)
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::tempfile::TempDir;
+    use common::{JobBuilder, TEST_JOB_ID};
+
+    const HASH: &str = "f5560c3296de4e0ef868574bf96fc778bc580931a8cae2d2631de27ba055db1be2afd769d658c684d8bc5ee0c1b2a7583ec862d5e994b806c6fa2ab4d54cd7f4";
+
+    fn job(dir: &TempDir) -> Job{
+        JobBuilder::new().in_directory(dir.path().join("blendfiles"))
+                         .with_task(Task::new_blender_range(1, 3, 1, "PNG", TEST_JOB_ID))
+                         .build()
+    }
+
+    fn frames_in(job: &Job) -> Vec<String>{
+        let mut names: Vec<String> = fs::read_dir(&job.paths.frames).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn ingest() {
+        let dir = TempDir::new().unwrap();
+        let mut job = job(&dir);
+        let receipt = job.ingest_frame(&FrameClaim::new(2, 8, HASH), "12345678".as_bytes()).unwrap();
+        assert_eq!(receipt.path, PathBuf::from(&job.paths.frames).join("000002.png"));
+        assert_eq!(fs::read(&receipt.path).unwrap(), b"12345678");
+        assert_eq!(receipt.task_id, job.tasks[0].id);
+        assert_eq!(frames_in(&job), vec!["000002.png"]);
+
+        if let Command::Blender(ref command) = job.tasks[0].command{
+            assert!(command.frame.is_uploaded(2));
+            assert_eq!(command.frame.get_filesize(2), Some(8));
+            assert_eq!(command.frame.get_hash(2), Some(HASH.to_string()));
+            assert!(!command.frame.is_uploaded(1));
+        }
+
+        // The same frame again is rejected
+        let again = job.ingest_frame(&FrameClaim::new(2, 8, HASH), "12345678".as_bytes());
+        assert_eq!(again, Err(IngestError::Duplicate("Frame 2".to_string())));
+    }
+
+    #[test]
+    fn mismatches() {
+        let dir = TempDir::new().unwrap();
+        let mut job = job(&dir);
+        let result = job.ingest_frame(&FrameClaim::new(1, 8, HASH), "123456789".as_bytes());
+        assert_eq!(result, Err(IngestError::SizeMismatch{ claimed: 8, received: 9 }));
+        let result = job.ingest_frame(&FrameClaim::new(1, 8, "abc"), "87654321".as_bytes());
+        match result{
+            Err(IngestError::HashMismatch{ claimed, .. }) => assert_eq!(claimed, "abc"),
+            other => panic!("Expected a HashMismatch, got {:?}", other)
+        }
+        assert_eq!(job.ingest_frame(&FrameClaim::new(9, 8, HASH), "12345678".as_bytes()),
+                   Err(IngestError::UnknownFrame(9)));
+        assert!(frames_in(&job).is_empty());
+    }
+
+    #[test]
+    fn outputs() {
+        let dir = TempDir::new().unwrap();
+        let mut job = job(&dir);
+        if let Command::Blender(ref mut command) = job.tasks[0].command{
+            command.set_outputs(vec![OutputSpec::view("_L"), OutputSpec::file_output("Depth", "", "PNG")]);
+        }
+        assert_eq!(job.ingest_frame(&FrameClaim::new(1, 8, HASH), "12345678".as_bytes()),
+                   Err(IngestError::MissingField("output".to_string())));
+        let claim = FrameClaim::new(1, 8, HASH).with_output("Depth/");
+        let receipt = job.ingest_frame(&claim, "12345678".as_bytes()).unwrap();
+        assert_eq!(receipt.path, PathBuf::from(&job.paths.frames).join("Depth").join("0001.png"));
+        if let Command::Blender(ref command) = job.tasks[0].command{
+            assert!(command.frame[&1].get_output("Depth/").unwrap().is_uploaded());
+            assert!(!command.frame.is_uploaded(1));
+        }
+        assert!(job.ingest_frame(&FrameClaim::new(1, 8, HASH).with_output("render_R"), "12345678".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn traversal() {
+        let dir = TempDir::new().unwrap();
+        let mut job = job(&dir);
+        if let Command::Blender(ref mut command) = job.tasks[0].command{
+            command.set_outputs(vec![OutputSpec::file_output("Depth", "../../../escape", "PNG")]);
+        }
+        let claim = FrameClaim::new(1, 8, HASH).with_output("Depth/../../../escape");
+        match job.ingest_frame(&claim, "12345678".as_bytes()){
+            Err(IngestError::OutsideFrames(path)) => assert!(path.contains("escape")),
+            other => panic!("Expected OutsideFrames, got {:?}", other)
+        }
+        assert!(!dir.path().join("blendfiles").join("escape0001.png").exists());
+        assert!(!Path::new(&job.paths.frames).exists());
+        if let Command::Blender(ref command) = job.tasks[0].command{
+            assert!(!command.frame[&1].get_output("Depth/../../../escape").unwrap().is_uploaded());
+        }
+    }
+
+    #[test]
+    fn huge_claim() {
+        let dir = TempDir::new().unwrap();
+        let mut job = job(&dir);
+        let claim = FrameClaim::new(1, usize::MAX, HASH);
+        assert_eq!(job.ingest_frame(&claim, "12345678".as_bytes()),
+                   Err(IngestError::SizeMismatch{ claimed: usize::MAX, received: 8 }));
+        assert!(frames_in(&job).is_empty());
+    }
+
+    #[test]
+    fn claim_from_fields() {
+        let mut fields = HashMap::new();
+        fields.insert("frame".to_string(), "12".to_string());
+        fields.insert("filesize".to_string(), "8".to_string());
+        assert_eq!(FrameClaim::from_fields(&fields), Err(IngestError::MissingField("filehash".to_string())));
+        fields.insert("filehash".to_string(), HASH.to_uppercase());
+        assert_eq!(FrameClaim::from_fields(&fields).unwrap(), FrameClaim::new(12, 8, HASH));
+        fields.insert("filesize".to_string(), "eight".to_string());
+        assert!(FrameClaim::from_fields(&fields).is_err());
+    }
+}
@@ -4,6 +4,8 @@ use ::*;
 use std::path::Path;
 use atomicwrites::{AtomicFile, AllowOverwrite};
 use std::io::Write;
+use template::TemplateContext;
+use history::{Event, KIND_STATUS};
 
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
@@ -47,10 +49,14 @@ use std::os::unix::fs::PermissionsExt;
 /// - `Job::time: JobTime` a struct that holds all timestamps relevant for a job. Also see [JobTime](jobtime/struct.JobTime.html)
 /// - `Job::status: String` the dot delimited Status of a job (e.g. "request.denied", "request.bouncer.finished", "job.done", etc)
 /// - `Job::data: HashMap<String, String>` a HashMap that holds arbitrary data for the job that cannot be known on startup (e.g. "frames: 250")
-/// - `Job::history: History` a ordered Treemap that acts as a timestampable Log for each Job.
+/// - `Job::history: History` a time ordered list of structured Events that acts as a Log for each Job (see [history](history/index.html)).
 /// - `Job::resolution: Resolution` stores x and y size, as well as the scale of the scene
 /// - `Job::render: Render` stores general values about the renderer, such as fps etc
 /// - `Job::frames: data::Frames` stores data related to the frame range
+/// - `Job::output_template: OutputTemplate` describes how rendered frames are named (see [template](template/index.html))
+/// - `Job::frame_override: FrameOverride` stores the frames requested by the user, which take precedence over the scanned `frames` (see [FrameOverride](data/struct.FrameOverride.html))
+/// - `Job::webhooks: Webhooks` stores the urls job events are posted to and the secret they are signed with (see [webhook](webhook/index.html))
+/// - `Job::observers: Observers` receive every Status transition of the Job and are not serialized (see [observer](observer/index.html))
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Job {
     pub id: String,
@@ -70,7 +76,15 @@ pub struct Job {
     #[serde(default)]
     pub frames: data::Frames,
     #[serde(default)]
-    pub tasks: Tasks
+    pub tasks: Tasks,
+    #[serde(default)]
+    pub output_template: OutputTemplate,
+    #[serde(default)]
+    pub frame_override: FrameOverride,
+    #[serde(default)]
+    pub webhooks: Webhooks,
+    #[serde(skip)]
+    pub observers: Observers
 }
 
 
@@ -88,7 +102,10 @@ impl PartialEq for Job {
         self.resolution == other.resolution &&
         self.render == other.render &&
         self.frames == other.frames &&
-        self.tasks == other.tasks
+        self.tasks == other.tasks &&
+        self.output_template == other.output_template &&
+        self.frame_override == other.frame_override &&
+        self.webhooks == other.webhooks
     }
 }
 
@@ -102,6 +119,47 @@ impl Job{
         self.paths.get_id()
     }
 
+    /// Return the values for the placeholders of the output template that \
+    /// are known for this Job. Scene and camera are only known after the scan
+    pub fn template_context(&self) -> TemplateContext{
+        let non_empty = |s: &String| if s.is_empty() { None } else { Some(s.clone()) };
+        let blend = PathBuf::from(&self.paths.filename)
+                        .file_stem()
+                        .map(|s| s.to_string_lossy().to_string())
+                        .filter(|s| !s.is_empty());
+        TemplateContext{
+            blend,
+            scene: non_empty(&self.render.scene),
+            camera: non_empty(&self.render.camera),
+            job: non_empty(&self.id)
+        }
+    }
+
+    /// Return the output template with all placeholders known for this Job \
+    /// resolved (see `template_context()`)
+    pub fn resolved_output_template(&self) -> OutputTemplate{
+        self.output_template.resolve(&self.template_context())
+    }
+
+    /// Return the frames that will be rendered: the frame override applied \
+    /// to the scanned frames (see [FrameOverride](data/struct.FrameOverride.html)). \
+    /// Return an Error if the override is invalid for the scanned scene
+    pub fn effective_frames(&self) -> GenResult<FrameSpec>{
+        self.frame_override.apply(&self.frames, self.animation)
+    }
+
+    /// Set the frames requested by the user. If the Job has been scanned \
+    /// already, the override is validated against the scene first
+    pub fn set_frame_override(&mut self, frame_override: FrameOverride) -> GenResult<()>{
+        if !self.frames.is_default(){
+            frame_override.apply(&self.frames, self.animation)?;
+        }
+        let message = format!("Frame override requested: {}", frame_override);
+        self.frame_override = frame_override;
+        self.add_history(message.as_str());
+        Ok(())
+    }
+
     /// Check if the frames have been downloaded
     pub fn is_downloaded(&self) -> bool{
         let mut path = PathBuf::from(self.paths.blend.clone());
@@ -119,17 +177,22 @@ impl Job{
     }
 
     /// Add to the history of a Job
-    /// key is a DateTime constructed via `chrono::Utc::now()`
-    /// value can be any String
+    /// the time of the Event is `chrono::Utc::now()`
+    /// value can be any String, source and level are parsed from it (see `Event::from_text()`)
     pub fn add_history<S>(&mut self, value: S) where S: Into<String> {
-        self.history.insert(Utc::now(), value.into());
+        self.history.insert(Utc::now(), value);
+    }
+
+    /// Add a structured Event to the history of a Job
+    pub fn add_event(&mut self, event: Event) {
+        self.history.push(event);
     }
 
     /// Add to the history of a job only if the added value changed from the last value
     /// Return Ok(()) if the value has been added otherwise return a boxed error
     pub fn add_history_debounced<S>(&mut self, value: S) where S: Into<String>{
         let value = value.into();
-        let addtohistory =  self.history.values().next_back() != Some(&value);
+        let addtohistory =  self.history.texts().next_back() != Some(value.clone());
         if addtohistory{
             self.add_history(value);
         }
@@ -303,11 +366,16 @@ impl Job{
             resolution: Resolution::default(),
             render: Render::default(),
             frames: data::Frames::default(),
-            tasks: VecDeque::<Task>::new()
+            tasks: VecDeque::<Task>::new(),
+            output_template: OutputTemplate::default(),
+            frame_override: FrameOverride::default(),
+            webhooks: Webhooks::default(),
+            observers: Observers::default()
         }
     }
 
     pub fn merge(&mut self, other: &Self){
+        let previous = self.status.clone();
         self.time.merge(&other.time);
         self.status.merge(&other.status);
         self.data.extend(other.data.clone());
@@ -317,7 +385,13 @@ impl Job{
         self.render.merge(&other.render);
         self.frames.merge(&other.frames);
         self.tasks.merge(&other.tasks);
-
+        if self.output_template.is_default(){
+            self.output_template = other.output_template.clone();
+        }
+        self.frame_override.merge(&other.frame_override);
+        self.webhooks.merge(&other.webhooks);
+        let reason = self.last_status_message();
+        self.announce_transition(previous, reason);
     }
 
 
@@ -358,6 +432,16 @@ impl Job{
         Ok(())
     }
 
+    /// The message of the last status Event in the history. Used as reason \
+    /// when announcing a Status that was read from disk
+    fn last_status_message(&self) -> String{
+        self.history.iter()
+                    .rev()
+                    .find(|event| event.kind == KIND_STATUS)
+                    .map(|event| event.text())
+                    .unwrap_or_else(|| "Status changed on disk".to_string())
+    }
+
     /// Reload the Job from disk only if the job stored there is different from \
     /// self. This does no checks if the job on disk is actually newer than the \
     /// one at hand!
@@ -365,7 +449,12 @@ impl Job{
         let datapath = self.paths.data.clone();
         let mut on_disk = Self::from_datajson(datapath)?;
         if self != &mut on_disk{
+            // Keep the Observers and tell them about the new Status
+            let previous = self.status.clone();
+            on_disk.observers = self.observers.clone();
             *self = on_disk;
+            let reason = self.last_status_message();
+            self.announce_transition(previous, reason);
         }
         Ok(())
     }
@@ -453,7 +542,9 @@ impl Job{
 
         // Finally update
         if should_update{
-            self.status = on_disk.status
+            let previous = self.status.clone();
+            self.status = on_disk.status.clone();
+            self.announce_transition(previous, on_disk.last_status_message());
         }
 
         // Cancel if the user cancled it
@@ -601,10 +692,12 @@ impl Job {
 impl Job{
     /// Validate the self and log it to history, log errors
     pub fn set_validate(&mut self){
+        let previous = self.status.clone();
         match self.status.validate(){
             Ok(_) => {
                 let message = format!("Validated with version: {}", self.version);
-                self.add_history(message.as_str());
+                self.add_event(Event::from_text(Utc::now(), message.as_str()).with_kind(KIND_STATUS));
+                self.announce_transition(previous, message);
             },
             Err(err) => {
                 let message = format!("Error: Job::status::validate() failed: {}", err);
@@ -615,10 +708,12 @@ impl Job{
 
     /// Deny the self and log it to history, log errors
     pub fn set_deny(&mut self){
+        let previous = self.status.clone();
         match self.status.deny(){
             Ok(_) => {
                 let message = "Denied Blendfile as invalid".to_string();
-                self.add_history(message.as_str());
+                self.add_event(Event::from_text(Utc::now(), message.as_str()).with_kind(KIND_STATUS));
+                self.announce_transition(previous, message);
             },
             Err(err) => {
                 let message = format!("Error: Job::status::deny() failed: {}", err);
@@ -630,11 +725,13 @@ impl Job{
     /// Error self and log it to history, log errors
     pub fn set_error<S>(&mut self, error_message: S) where S: Into<String>{
         let error_message = error_message.into();
+        let previous = self.status.clone();
         match self.status.error(){
             Ok(_) => {
                 let message = format!("Error: {}", error_message);
-                self.add_history(message.as_str());
+                self.add_event(Event::from_text(Utc::now(), message.as_str()).with_kind(KIND_STATUS));
                 self.time.error();
+                self.announce_transition(previous, message);
             },
             Err(err) => {
                 let message = format!("Error: Job::status::validate() failed with: {}\nat Error:{}", err, error_message);
@@ -645,10 +742,12 @@ impl Job{
 
     /// Scan self and log it to history, log errors
     pub fn set_scan(&mut self){
+        let previous = self.status.clone();
         match self.status.scan(){
             Ok(_) => {
                 let message = "Scanning finished".to_string();
-                self.add_history(message.as_str());
+                self.add_event(Event::from_text(Utc::now(), message.as_str()).with_kind(KIND_STATUS));
+                self.announce_transition(previous, message);
             },
             Err(err) => {
                 let message = format!("Error: Job::status::scan() failed: {}", err);
@@ -659,14 +758,19 @@ impl Job{
 
     /// Atomize self and log it to history, log errors
     pub fn set_atomize(&mut self){
+        let previous = self.status.clone();
         match self.status.atomize(){
             Ok(_) => {
-                let message = if self.animation { 
+                let message = if !self.frame_override.is_default() {
+                                let frames = self.effective_frames().map(|frames| frames.len()).unwrap_or(0);
+                                format!("Atomization finished: created {} atomic tasks (for {} frames)", self.tasks.len(), frames)
+                              } else if self.animation { 
                                 format!("Atomization finished: created {} atomic tasks (for {} frames)", self.tasks.len(), self.frames.count()) 
                               } else { 
                                 format!("Atomization finished: created {} atomic task (for current frame {})", self.tasks.len(), self.frames.current) 
                               };
-                self.add_history(message.as_str());
+                self.add_event(Event::from_text(Utc::now(), message.as_str()).with_kind(KIND_STATUS));
+                self.announce_transition(previous, message);
             },
             Err(err) => {
                 let message = format!("Error: Job::status::atomize() failed: {}", err);
@@ -677,10 +781,13 @@ impl Job{
 
     /// Queue self and log it to history, log errors
     pub fn set_queue(&mut self){
+        let previous = self.status.clone();
         match self.status.queue(){
             Ok(_) => {
                 let message = "Queued Job to job queue".to_string();
-                self.add_history(message.as_str());
+                self.add_event(Event::from_text(Utc::now(), message.as_str()).with_kind(KIND_STATUS));
+                self.time.queue();
+                self.announce_transition(previous, message);
             },
             Err(err) => {
                 let message = format!("Error: Job::status::queue() failed: {}", err);
@@ -691,11 +798,13 @@ impl Job{
 
     /// Run self and log it to history, log errors
     pub fn set_run(&mut self){
+        let previous = self.status.clone();
         match self.status.run(){
             Ok(_) => {
                 let message = "running Job".to_string();
-                self.add_history(message.as_str());
+                self.add_event(Event::from_text(Utc::now(), message.as_str()).with_kind(KIND_STATUS));
                 self.time.start();
+                self.announce_transition(previous, message);
             },
             Err(err) => {
                 let message = format!("Error: Job::status::run() failed: {}", err);
@@ -706,11 +815,13 @@ impl Job{
 
     /// Finish self and log it to history, log errors
     pub fn set_finish(&mut self){
+        let previous = self.status.clone();
         match self.status.finish(){
             Ok(_) => {
                 let message = "Finished Job".to_string();
-                self.add_history(message.as_str());
+                self.add_event(Event::from_text(Utc::now(), message.as_str()).with_kind(KIND_STATUS));
                 self.time.finish();
+                self.announce_transition(previous, message);
             },
             Err(err) => {
                 let message = format!("Error: Job::status::finish() failed: {}", err);
@@ -721,11 +832,13 @@ impl Job{
 
     /// Cancel self and log it to history, log errors
     pub fn set_cancel(&mut self){
+        let previous = self.status.clone();
         match self.status.cancel(){
             Ok(_) => {
                 let message = "Canceled Job".to_string();
-                self.add_history(message.as_str());
+                self.add_event(Event::from_text(Utc::now(), message.as_str()).with_kind(KIND_STATUS));
                 self.time.abort();
+                self.announce_transition(previous, message);
             },
             Err(err) => {
                 let message = format!("Error: Job::status::cancel() failed: {}", err);
@@ -231,7 +231,7 @@ impl JobTime{
 
     /// Return the duration (duration since queued) of Job as a chrono duration
     pub fn waiting_for(&self) -> Option<Duration>{
-        match self.start{
+        match self.queued{
             Some(t) =>{
                 // Use the stat time if the task started, otherwise use now
                 let end = match self.start{
@@ -70,22 +70,84 @@ pub mod status;
 pub use status::{Status, JobStatus, RequestStatus};
 
 pub mod data;
-pub use data::{Render, Resolution};
+pub use data::{Render, Resolution, FrameOverride};
+
+pub mod framespec;
+pub use framespec::FrameSpec;
+
+pub mod imageformat;
+pub use imageformat::ImageFormat;
+
+pub mod template;
+pub use template::OutputTemplate;
+
+pub mod outputs;
+pub use outputs::OutputSpec;
 
 pub mod gaffer;
 pub use gaffer::{Gaffer};
 
 pub mod command;
-pub use command::Command;
+pub use command::{Command, CommandLine};
+
+pub mod executor;
+pub use executor::Executor;
+
+pub mod renderlog;
+pub use renderlog::{RenderLog, RenderEvent};
+
+pub mod verify;
+pub use verify::{ImageInfo, VerifyError};
+
+pub mod upload;
+pub use upload::Uploader;
+
+pub mod ingest;
+pub use ingest::{Ingest, FrameClaim, IngestError};
+
+pub mod manifest;
+pub use manifest::{Manifest, ManifestReport};
+
+pub mod archive;
+pub use archive::{Archive, Archiver};
 
 pub mod atomizer;
 pub use atomizer::Atomizer;
 
+pub mod completeness;
+pub use completeness::{Completeness, FrameCheck};
+
+pub mod estimate;
+pub use estimate::{Estimator, Estimate};
+
+pub mod progress;
+pub use progress::Progress;
+
+pub mod csv;
+
+pub mod report;
+pub use report::JobReport;
+
+pub mod stats;
+pub use stats::{FarmStats, StatsWindow};
+
+pub mod metrics;
+pub use metrics::MetricsServer;
+
+pub mod notify;
+pub use notify::{Composer, Notification, Transition};
+
+pub mod observer;
+pub use observer::{Observer, Observers, StatusChange};
+
+pub mod webhook;
+pub use webhook::{Webhooks, WebhookSender, WebhookEvent};
+
 pub mod bouncer;
 pub use bouncer::Bouncer;
 
 pub mod history;
-pub use history::{History, HistoryMethods};
+pub use history::{History, HistoryFilter, HistoryMethods};
 
 pub mod common;
 
@@ -0,0 +1,350 @@
+//! The manifest module describes the set of frames a Job is expected to \
+//! produce. A [Manifest](struct.Manifest.html) is built from the Tasks of a \
+//! Job and lists every expected file with its path (relative to \
+//! `JobPaths::frames`), size and Blake2b hash. It is written as \
+//! `manifest.json` into the frames directory, so a downloaded set of frames \
+//! can be checked for completeness and integrity.
+//!
+//! ```no_run
+//! # extern crate bender_job;
+//! # use bender_job::Job;
+//! # use bender_job::manifest::Manifest;
+//! let job = Job::from_datajson("/data/blendfiles/5873c0033e78b222bec2cb2a221487cf/data.json").unwrap();
+//! let manifest = Manifest::from_job(&job);
+//! manifest.write_to(&job.paths.frames).unwrap();
+//!
+//! // Later (e.g. after downloading the frames somewhere else)
+//! let manifest = Manifest::read_from("/home/user/Downloads/frames").unwrap();
+//! let report = manifest.verify("/home/user/Downloads/frames");
+//! println!("{}", report);
+//! ```
+
+use ::*;
+use std::io::{Read, Write};
+use std::path::Path;
+use atomicwrites::{AtomicFile, AllowOverwrite};
+use blake2::{Blake2b, Digest};
+use ingest::{naming_command, output_path, is_contained};
+
+
+/// The filename of the manifest in the frames directory
+pub const MANIFEST_FILENAME: &str = "manifest.json";
+
+
+
+
+// ===========================================================================
+//                                 Manifest
+// ===========================================================================
+
+/// A single expected file. Filesize and hash are None as long as the frame \
+/// hasn't been uploaded
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ManifestEntry{
+    pub frame: usize,
+    #[serde(default)]
+    pub output: Option<String>,
+    pub path: String,
+    pub filesize: Option<usize>,
+    pub hash: Option<String>
+}
+
+
+/// The list of files a Job is expected to produce
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Manifest{
+    pub job: String,
+    pub created: DateTime<Utc>,
+    pub entries: Vec<ManifestEntry>
+}
+
+impl Manifest{
+    /// Build the Manifest from the blender Tasks of a Job, ordered by frame
+    pub fn from_job(job: &Job) -> Self{
+        let frames = PathBuf::from(&job.paths.frames);
+        let mut entries = Vec::new();
+        for task in job.tasks.iter(){
+            let command = match task.command{
+                Command::Blender(ref command) => command,
+                _ => continue
+            };
+            let naming = naming_command(&job.paths, command);
+            for (framenumber, frame) in command.frame.iter(){
+                let outputs: Vec<Option<String>> = match command.outputs.is_empty(){
+                    true => vec![None],
+                    false => command.outputs.iter().map(|o| Some(o.name.clone())).collect()
+                };
+                for output in outputs{
+                    let path = match output_path(&naming, *framenumber, output.as_deref()){
+                        Ok(path) => path,
+                        Err(_) => continue
+                    };
+                    let path = path.strip_prefix(&frames).unwrap_or(&path).to_string_lossy().to_string();
+                    let (filesize, hash) = match output{
+                        Some(ref name) => match frame.get_output(name){
+                            Some(o) => (o.get_filesize(), o.get_hash()),
+                            None => (None, None)
+                        },
+                        None => (frame.get_filesize(), frame.get_hash())
+                    };
+                    entries.push(ManifestEntry{ frame: *framenumber, output, path, filesize, hash });
+                }
+            }
+        }
+        entries.sort_by(|a, b| a.frame.cmp(&b.frame).then(a.path.cmp(&b.path)));
+        Manifest{
+            job: job.id.clone(),
+            created: Utc::now(),
+            entries
+        }
+    }
+
+    /// Return the entry for the given path (relative to the frames directory)
+    pub fn get(&self, path: &str) -> Option<&ManifestEntry>{
+        self.entries.iter().find(|entry| entry.path == path)
+    }
+
+    /// Return true if every entry has a filesize and a hash
+    pub fn is_complete(&self) -> bool{
+        self.entries.iter().all(|entry| entry.filesize.is_some() && entry.hash.is_some())
+    }
+
+    /// Write the manifest atomically as `manifest.json` into the directory
+    pub fn write_to<P>(&self, directory: P) -> GenResult<()> where P: AsRef<Path>{
+        let directory = directory.as_ref();
+        fs::create_dir_all(directory)?;
+        let serialized = serde_json::to_string_pretty(self)?;
+        let atomicfile = AtomicFile::new(directory.join(MANIFEST_FILENAME), AllowOverwrite);
+        atomicfile.write(|f| f.write_all(serialized.as_bytes()))?;
+        Ok(())
+    }
+
+    /// Read the `manifest.json` from the directory
+    pub fn read_from<P>(directory: P) -> GenResult<Self> where P: AsRef<Path>{
+        let path = directory.as_ref().join(MANIFEST_FILENAME);
+        let content = fs::read_to_string(&path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Check the files in the directory against the manifest. Entries \
+    /// without filesize or hash are only checked for existence. Entries with \
+    /// a absolute path or `..` components are reported as mismatching \
+    /// without touching the filesystem
+    pub fn verify<P>(&self, directory: P) -> ManifestReport where P: AsRef<Path>{
+        let directory = directory.as_ref();
+        let mut report = ManifestReport::default();
+        for entry in self.entries.iter(){
+            // Never look outside of the directory
+            if !is_contained(Path::new(&entry.path)){
+                report.mismatched.push((entry.path.clone(), "the path leaves the directory".to_string()));
+                continue;
+            }
+            let path = directory.join(&entry.path);
+            if !path.is_file(){
+                report.missing.push(entry.path.clone());
+                continue;
+            }
+            match hash_file(&path){
+                Ok((filesize, hash)) => {
+                    if entry.filesize.filter(|expected| *expected != filesize).is_some(){
+                        report.mismatched.push((entry.path.clone(), format!("expected {} bytes, found {} bytes", entry.filesize.unwrap(), filesize)));
+                    }else if entry.hash.as_ref().filter(|expected| **expected != hash).is_some(){
+                        report.mismatched.push((entry.path.clone(), "the hash differs".to_string()));
+                    }else{
+                        report.ok += 1;
+                    }
+                },
+                Err(err) => report.mismatched.push((entry.path.clone(), format!("couldn't be read: {}", err)))
+            }
+        }
+        let mut files = Vec::new();
+        list_files(directory, directory, &mut files);
+        report.extra = files.into_iter()
+                            .filter(|file| file != MANIFEST_FILENAME && self.get(file).is_none())
+                            .collect();
+        report.extra.sort();
+        report
+    }
+}
+
+
+
+
+// ===========================================================================
+//                              ManifestReport
+// ===========================================================================
+
+/// The result of a verification against a Manifest. All paths are relative \
+/// to the verified directory
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ManifestReport{
+    /// The number of files that match the manifest
+    pub ok: usize,
+    /// Files listed in the manifest that don't exist
+    pub missing: Vec<String>,
+    /// Files that exist, but are not listed in the manifest
+    pub extra: Vec<String>,
+    /// Files whose size or hash differs from the manifest, with the reason
+    pub mismatched: Vec<(String, String)>
+}
+
+impl ManifestReport{
+    /// Return true if no file is missing or mismatching (extra files are \
+    /// allowed)
+    pub fn is_ok(&self) -> bool{
+        self.missing.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+impl fmt::Display for ManifestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} files ok, {} missing, {} mismatching, {} extra",
+            self.ok, self.missing.len(), self.mismatched.len(), self.extra.len())?;
+        for path in self.missing.iter(){
+            writeln!(f, "missing:    {}", path)?;
+        }
+        for (path, reason) in self.mismatched.iter(){
+            writeln!(f, "mismatch:   {} ({})", path, reason)?;
+        }
+        for path in self.extra.iter(){
+            writeln!(f, "extra:      {}", path)?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Return the size and the Blake2b hash of a file without reading it into \
+/// memory at once
+pub(crate) fn hash_file(path: &Path) -> std::io::Result<(usize, String)>{
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Blake2b::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut filesize = 0;
+    loop{
+        let n = file.read(&mut buffer)?;
+        if n == 0{ break; }
+        hasher.input(&buffer[..n]);
+        filesize += n;
+    }
+    Ok((filesize, format!("{:x}", hasher.result())))
+}
+
+
+/// Collect all files below directory (relative to root). Hidden entries \
+/// (like the temporary directories of atomic writes) are skipped
+fn list_files(root: &Path, directory: &Path, files: &mut Vec<String>){
+    if let Ok(entries) = fs::read_dir(directory){
+        for entry in entries.filter_map(|entry| entry.ok()){
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.'){
+                continue;
+            }
+            if path.is_dir(){
+                list_files(root, &path, files);
+            }else if let Ok(relative) = path.strip_prefix(root){
+                files.push(relative.to_string_lossy().to_string());
+            }
+        }
+    }
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ingest::{FrameClaim, Ingest};
+    use common::tempfile::TempDir;
+    use common::{JobBuilder, TEST_JOB_ID};
+
+    const HASH: &str = "f5560c3296de4e0ef868574bf96fc778bc580931a8cae2d2631de27ba055db1be2afd769d658c684d8bc5ee0c1b2a7583ec862d5e994b806c6fa2ab4d54cd7f4";
+
+    fn job(dir: &TempDir) -> Job{
+        let mut stereo = Task::new_blender_single(3, "PNG", TEST_JOB_ID);
+        if let Command::Blender(ref mut command) = stereo.command{
+            command.set_outputs(vec![OutputSpec::view("_L"), OutputSpec::view("_R")]);
+        }
+        JobBuilder::new().in_directory(dir.path().join("blendfiles"))
+                         .with_task(Task::new_blender_range(1, 2, 1, "PNG", TEST_JOB_ID))
+                         .with_task(stereo)
+                         .build()
+    }
+
+    #[test]
+    fn build() {
+        let dir = TempDir::new().unwrap();
+        let mut job = job(&dir);
+        job.ingest_frame(&FrameClaim::new(1, 8, HASH), "12345678".as_bytes()).unwrap();
+        let manifest = Manifest::from_job(&job);
+        let paths: Vec<&str> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["000001.png", "000002.png", "000003_L.png", "000003_R.png"]);
+        assert_eq!(manifest.entries[0].hash, Some(HASH.to_string()));
+        assert_eq!(manifest.entries[2].output, Some("render_L".to_string()));
+        assert!(!manifest.is_complete());
+    }
+
+    #[test]
+    fn write_read_verify() {
+        let dir = TempDir::new().unwrap();
+        let mut job = job(&dir);
+        job.ingest_frame(&FrameClaim::new(1, 8, HASH), "12345678".as_bytes()).unwrap();
+        job.ingest_frame(&FrameClaim::new(2, 8, HASH), "12345678".as_bytes()).unwrap();
+        job.ingest_frame(&FrameClaim::new(3, 8, HASH).with_output("render_L"), "12345678".as_bytes()).unwrap();
+        job.ingest_frame(&FrameClaim::new(3, 8, HASH).with_output("render_R"), "12345678".as_bytes()).unwrap();
+        let manifest = Manifest::from_job(&job);
+        assert!(manifest.is_complete());
+        manifest.write_to(&job.paths.frames).unwrap();
+        let read = Manifest::read_from(&job.paths.frames).unwrap();
+        assert_eq!(read, manifest);
+
+        let report = read.verify(&job.paths.frames);
+        assert!(report.is_ok());
+        assert_eq!(report.ok, 4);
+        assert!(report.extra.is_empty());
+
+        let frames = PathBuf::from(&job.paths.frames);
+        fs::remove_file(frames.join("000002.png")).unwrap();
+        fs::write(frames.join("000003_L.png"), "1234567").unwrap();
+        fs::write(frames.join("000003_R.png"), "87654321").unwrap();
+        fs::create_dir(frames.join("extra")).unwrap();
+        fs::write(frames.join("extra").join("notes.txt"), "hello").unwrap();
+        let report = read.verify(&job.paths.frames);
+        assert!(!report.is_ok());
+        assert_eq!(report.ok, 1);
+        assert_eq!(report.missing, vec!["000002.png"]);
+        assert_eq!(report.extra, vec!["extra/notes.txt"]);
+        assert_eq!(report.mismatched, vec![
+            ("000003_L.png".to_string(), "expected 8 bytes, found 7 bytes".to_string()),
+            ("000003_R.png".to_string(), "the hash differs".to_string())
+        ]);
+        assert!(report.to_string().starts_with("1 files ok, 1 missing, 2 mismatching, 1 extra"));
+    }
+
+    #[test]
+    fn paths_outside_the_directory() {
+        let dir = TempDir::new().unwrap();
+        let frames = dir.path().join("frames");
+        fs::create_dir(&frames).unwrap();
+        fs::write(dir.path().join("secret.txt"), "hello").unwrap();
+        let entry = |path: &str| ManifestEntry{ frame: 1, output: None, path: path.to_string(), filesize: None, hash: None };
+        let secret = dir.path().join("secret.txt").to_string_lossy().to_string();
+        let manifest = Manifest{
+            job: TEST_JOB_ID.to_string(),
+            created: Utc::now(),
+            entries: vec![entry("../secret.txt"), entry(secret.as_str()), entry("./a/../../secret.txt")]
+        };
+        let report = manifest.verify(&frames);
+        assert_eq!(report.ok, 0);
+        assert!(report.missing.is_empty());
+        assert_eq!(report.mismatched.len(), 3);
+        assert!(report.mismatched.iter().all(|(_, reason)| reason == "the path leaves the directory"));
+    }
+}
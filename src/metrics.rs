@@ -0,0 +1,365 @@
+//! The metrics module renders the state of a set of Jobs in the Prometheus \
+//! text exposition format, so it can be scraped by an existing monitoring:
+//!
+//! - `bender_jobs{status="…"}`: number of Jobs per Status (gauge)
+//! - `bender_tasks{status="…"}`: number of Tasks per Status (gauge)
+//! - `bender_queue_age_seconds`: age of all Jobs that haven't ended yet (histogram)
+//! - `bender_frames_rendered`: frames of finished Tasks (gauge)
+//! - `bender_bytes_uploaded`: size of all uploaded frames (gauge)
+//!
+//! All metrics are computed from the Jobs that are passed in, so frames and \
+//! bytes are gauges: they drop when Jobs are removed.
+//!
+//! The [MetricsServer](struct.MetricsServer.html) is an optional tiny HTTP \
+//! endpoint that answers `GET /metrics` with freshly rendered metrics. It \
+//! serves at most `MAX_CONNECTIONS` clients at once and drops clients that \
+//! don't send a request within `READ_TIMEOUT`:
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::{read_all, metrics};
+//! let server = metrics::MetricsServer::start("127.0.0.1:0", || {
+//!     metrics::render(read_all("/data/blendfiles").iter())
+//! }).unwrap();
+//! println!("Serving metrics at {}", server.url());
+//! ```
+
+use ::*;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+use status::{RequestStatus, JobStatus};
+use estimate::to_seconds;
+
+
+/// The upper bounds of the queue age histogram in seconds
+pub const QUEUE_AGE_BUCKETS: [f64; 8] = [60.0, 300.0, 900.0, 3600.0, 4.0*3600.0, 12.0*3600.0, 86400.0, 7.0*86400.0];
+
+/// The content type of the text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// The number of connections the MetricsServer serves at once, more are closed
+pub const MAX_CONNECTIONS: usize = 8;
+
+/// How long the MetricsServer waits for a client to send or receive
+pub const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+
+
+
+// ===========================================================================
+//                                 Metrics
+// ===========================================================================
+
+/// Render the metrics of the Jobs in the Prometheus text format
+pub fn render<'a, I>(jobs: I) -> String where I: IntoIterator<Item=&'a Job>{
+    // Every possible status is listed so series don't vanish when they hit 0
+    let mut job_counts: Vec<(String, usize)> = job_statuses().iter().map(|s| (s.to_string(), 0)).collect();
+    let mut task_counts: Vec<(String, usize)> = task_statuses().iter().map(|s| (format!("{:?}", s).to_lowercase(), 0)).collect();
+    let mut ages = Vec::new();
+    let mut frames_rendered = 0;
+    let mut bytes_uploaded = 0;
+
+    for job in jobs{
+        let status = job.status.to_string();
+        match job_counts.iter_mut().find(|(s, _)| *s == status){
+            Some(count) => count.1 += 1,
+            None => job_counts.push((status, 1))
+        }
+        if !job.is_ended(){
+            ages.push(to_seconds(job.time.age()));
+        }
+        for task in job.tasks.iter(){
+            if let Some(position) = task_statuses().iter().position(|s| *s == task.status){
+                task_counts[position].1 += 1;
+            }
+            if let Command::Blender(ref command) = task.command{
+                if task.is_finished(){
+                    frames_rendered += command.frame.len();
+                }
+                bytes_uploaded += command.frame.values()
+                                          .filter(|frame| frame.is_uploaded())
+                                          .filter_map(|frame| frame.get_filesize())
+                                          .sum::<usize>();
+            }
+        }
+    }
+
+    let mut text = String::new();
+    header(&mut text, "bender_jobs", "gauge", "Number of jobs per status");
+    for (status, count) in job_counts{
+        text.push_str(&format!("bender_jobs{{status=\"{}\"}} {}\n", escape(&status), count));
+    }
+    header(&mut text, "bender_tasks", "gauge", "Number of tasks per status");
+    for (status, count) in task_counts{
+        text.push_str(&format!("bender_tasks{{status=\"{}\"}} {}\n", escape(&status), count));
+    }
+    header(&mut text, "bender_queue_age_seconds", "histogram", "Age of the jobs that haven't ended yet");
+    for bound in QUEUE_AGE_BUCKETS.iter(){
+        let count = ages.iter().filter(|age| *age <= bound).count();
+        text.push_str(&format!("bender_queue_age_seconds_bucket{{le=\"{}\"}} {}\n", bound, count));
+    }
+    text.push_str(&format!("bender_queue_age_seconds_bucket{{le=\"+Inf\"}} {}\n", ages.len()));
+    text.push_str(&format!("bender_queue_age_seconds_sum {}\n", ages.iter().sum::<f64>()));
+    text.push_str(&format!("bender_queue_age_seconds_count {}\n", ages.len()));
+    header(&mut text, "bender_frames_rendered", "gauge", "Frames of finished tasks");
+    text.push_str(&format!("bender_frames_rendered {}\n", frames_rendered));
+    header(&mut text, "bender_bytes_uploaded", "gauge", "Size of all uploaded frames in bytes");
+    text.push_str(&format!("bender_bytes_uploaded {}\n", bytes_uploaded));
+    text
+}
+
+
+/// Append the HELP and TYPE lines of a metric
+fn header(text: &mut String, name: &str, kind: &str, help: &str){
+    text.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+}
+
+
+/// Escape a label value (backslash, double quote and line feed)
+fn escape(value: &str) -> String{
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+
+/// All Statuses a Job can have
+fn job_statuses() -> Vec<Status>{
+    vec![
+        Status::Request(RequestStatus::Untouched),
+        Status::Request(RequestStatus::Invalid),
+        Status::Request(RequestStatus::Errored),
+        Status::Request(RequestStatus::Checked),
+        Status::Request(RequestStatus::Scanned),
+        Status::Request(RequestStatus::Atomized),
+        Status::Job(JobStatus::Queued),
+        Status::Job(JobStatus::Running),
+        Status::Job(JobStatus::Canceled),
+        Status::Job(JobStatus::Errored),
+        Status::Job(JobStatus::Finished)
+    ]
+}
+
+
+/// All Statuses a Task can have
+fn task_statuses() -> Vec<task::Status>{
+    vec![
+        task::Status::Waiting,
+        task::Status::Queued,
+        task::Status::Running,
+        task::Status::Finished,
+        task::Status::Errored,
+        task::Status::Aborted,
+        task::Status::Paused
+    ]
+}
+
+
+
+
+// ===========================================================================
+//                               MetricsServer
+// ===========================================================================
+
+/// A tiny HTTP server that answers `GET /metrics` with the output of the \
+/// given function and every other request with 404. The server stops when \
+/// the MetricsServer is dropped.
+pub struct MetricsServer{
+    pub addr: SocketAddr,
+    stop: Arc<AtomicBool>
+}
+
+impl MetricsServer{
+    /// Bind to the address (e.g. `127.0.0.1:9090`, use port 0 for a random \
+    /// port) and serve the metrics in a background thread
+    pub fn start<A, F>(address: A, metrics: F) -> GenResult<Self>
+    where A: ToSocketAddrs, F: Fn() -> String + Send + Sync + 'static{
+        let listener = TcpListener::bind(address)?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let metrics = Arc::new(metrics);
+
+        let thread_stop = stop.clone();
+        let active = Arc::new(AtomicUsize::new(0));
+        thread::spawn(move ||{
+            for stream in listener.incoming(){
+                if thread_stop.load(Ordering::SeqCst){ break; }
+                let stream = match stream{
+                    Ok(stream) => stream,
+                    Err(_) => continue
+                };
+                // Close the connection right away if too many are open
+                if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS{
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    continue;
+                }
+                let metrics = metrics.clone();
+                let slot = Slot(active.clone());
+                thread::spawn(move ||{
+                    let stream = stream;
+                    // Declared after the stream, so the slot is freed before
+                    // the client sees the connection close
+                    let _slot = slot;
+                    respond(&stream, &*metrics);
+                });
+            }
+        });
+        Ok(MetricsServer{ addr, stop })
+    }
+
+    /// The url of the metrics endpoint
+    pub fn url(&self) -> String{
+        format!("http://{}/metrics", self.addr)
+    }
+}
+
+impl Drop for MetricsServer{
+    fn drop(&mut self){
+        self.stop.store(true, Ordering::SeqCst);
+        // Wake up the listener
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+
+/// Frees a connection slot of the server when dropped
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot{
+    fn drop(&mut self){
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+
+/// Read the request line and headers from the stream and answer it
+fn respond<F>(mut stream: &TcpStream, metrics: &F) where F: Fn() -> String{
+    if stream.set_read_timeout(Some(READ_TIMEOUT)).is_err() || stream.set_write_timeout(Some(READ_TIMEOUT)).is_err(){
+        return;
+    }
+    let mut reader = BufReader::new(match stream.try_clone(){
+        Ok(s) => s,
+        Err(_) => return
+    });
+    let mut request = String::new();
+    if reader.read_line(&mut request).is_err(){
+        return;
+    }
+    loop{
+        let mut line = String::new();
+        match reader.read_line(&mut line){
+            Ok(0) | Err(_) => break,
+            Ok(_) if line.trim_end().is_empty() => break,
+            Ok(_) => ()
+        }
+    }
+    let mut parts = request.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()){
+        (Some("GET"), Some(path)) if path.split('?').next() == Some("/metrics") => ("200 OK", CONTENT_TYPE, metrics()),
+        _ => ("404 Not Found", "text/plain", "Not Found\n".to_string())
+    };
+    let response = format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body);
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.flush();
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::time::Instant;
+    use common::{JobBuilder, TEST_JOB_ID};
+
+    fn jobs() -> Vec<Job>{
+        let mut queued = JobBuilder::new().with_status(Status::Job(JobStatus::Queued)).build();
+        queued.time.creation = Some(Utc::now() - chrono::Duration::seconds(600));
+        let mut task = Task::new_blender_single(1, "PNG", TEST_JOB_ID);
+        task.queue();
+        queued.tasks.push_back(task);
+
+        let mut finished = JobBuilder::new().with_id("a1b2c3").with_status(Status::Job(JobStatus::Finished)).build();
+        for frame in 1..=3{
+            let mut task = Task::new_blender_single(frame, "PNG", "a1b2c3");
+            if let Command::Blender(ref mut command) = task.command{
+                let f = command.frame.get_mut(&frame).unwrap();
+                f.set_filesize(1000);
+                if frame < 3 { f.set_uploaded(); }
+            }
+            task.queue();
+            task.start();
+            task.finish();
+            finished.tasks.push_back(task);
+        }
+        vec![queued, finished]
+    }
+
+    #[test]
+    fn render_metrics() {
+        let text = render(jobs().iter());
+        assert!(text.contains("# TYPE bender_jobs gauge\n"));
+        assert!(text.contains("bender_jobs{status=\"job.queued\"} 1\n"));
+        assert!(text.contains("bender_jobs{status=\"job.finished\"} 1\n"));
+        assert!(text.contains("bender_jobs{status=\"job.running\"} 0\n"));
+        assert!(text.contains("bender_tasks{status=\"queued\"} 1\n"));
+        assert!(text.contains("bender_tasks{status=\"finished\"} 3\n"));
+        assert!(text.contains("bender_queue_age_seconds_bucket{le=\"300\"} 0\n"));
+        assert!(text.contains("bender_queue_age_seconds_bucket{le=\"900\"} 1\n"));
+        assert!(text.contains("bender_queue_age_seconds_bucket{le=\"+Inf\"} 1\n"));
+        assert!(text.contains("bender_queue_age_seconds_count 1\n"));
+        assert!(text.contains("bender_frames_rendered 3\n"));
+        assert!(text.contains("bender_bytes_uploaded 2000\n"));
+        // Every sample belongs to a declared metric
+        assert!(text.lines().filter(|l| !l.starts_with('#')).all(|l| l.starts_with("bender_")));
+    }
+
+    #[test]
+    fn escape_labels() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn serve() {
+        let server = MetricsServer::start("127.0.0.1:0", || render(jobs().iter())).unwrap();
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(server.addr).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.ends_with("bender_bytes_uploaded 2000\n"));
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+
+        // Connections beyond the limit are closed right away instead of
+        // waiting for idle clients
+        let idle: Vec<TcpStream> = (0..MAX_CONNECTIONS).map(|_| TcpStream::connect(server.addr).unwrap()).collect();
+        thread::sleep(Duration::from_millis(100));
+        let started = Instant::now();
+        let mut stream = TcpStream::connect(server.addr).unwrap();
+        stream.set_read_timeout(Some(READ_TIMEOUT * 2)).unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        assert!(response.is_empty());
+        assert!(started.elapsed() < READ_TIMEOUT);
+
+        // Once the idle clients are gone the server answers again
+        drop(idle);
+        thread::sleep(Duration::from_millis(100));
+        assert!(get("/metrics").starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+}
@@ -0,0 +1,657 @@
+//! The notify module composes the emails sent to the owner of a Job (`Job::email`) \
+//! when it changes its Status, e.g. when a request was validated or denied or \
+//! when a Job was queued, finished, errored or canceled.
+//!
+//! A [Composer](struct.Composer.html) fills a [NotificationTemplate](struct.NotificationTemplate.html) \
+//! for each [Transition](enum.Transition.html) with the details of the Job \
+//! and returns a [Notification](struct.Notification.html) with subject, \
+//! plain text and HTML body. These placeholders can be used in templates:
+//! - `{id}`, `{filename}`, `{email}`, `{status}`, `{transition}`
+//! - `{progress}` e.g. `50% (5 of 10 frames)`
+//! - `{report}` the text of the [JobReport](../report/struct.JobReport.html)
+//! - `{download}` the download url (or the path of the archive)
+//! - `{message}` the last message in the history of the Job
+//!
+//! The addresses of the sender and of the owner are validated before a \
+//! Notification is composed or sent (see `validate_address()`), as the email \
+//! of a Job is user input that ends up in mail headers and SMTP commands.
+//!
+//! Notifications are delivered by a [Transport](trait.Transport.html), either \
+//! via [SMTP](struct.SmtpTransport.html) or into a local [Maildir](struct.MaildirTransport.html) \
+//! for testing:
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::Job;
+//! # use bender_job::notify::{Composer, Transition, MaildirTransport};
+//! let job = Job::new("/data/blendfiles/5873c0033e78b222bec2cb2a221487cf/shot.blend", "dh@atoav.com", true);
+//! let composer = Composer::new("bender@example.com")
+//!                         .with_download_url("https://render.example.com/download");
+//! let notification = composer.compose(&job, Transition::Finished).unwrap();
+//! assert_eq!(notification.subject, "[bender] shot.blend finished rendering");
+//! # let dir = std::env::temp_dir().join("bender-notify-doctest");
+//! let transport = MaildirTransport::new(dir);
+//! composer.notify(&job, Transition::Finished, &transport).unwrap();
+//! ```
+
+use ::*;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use status::{RequestStatus, JobStatus};
+use archive::Archiver;
+
+
+/// The default timeout of SMTP connections
+pub const SMTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Counter that makes the names of Maildir files unique within the process
+static MAILDIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+
+
+
+// ===========================================================================
+//                                Transition
+// ===========================================================================
+
+/// The Status changes a notification is sent for
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Transition{
+    Validated,
+    Denied,
+    Queued,
+    Finished,
+    Errored,
+    Canceled
+}
+
+impl Transition{
+    /// Return the Transition that leads to the Status (if there is one)
+    pub fn from_status(status: &Status) -> Option<Self>{
+        match status{
+            Status::Request(RequestStatus::Checked) => Some(Transition::Validated),
+            Status::Request(RequestStatus::Invalid) => Some(Transition::Denied),
+            Status::Request(RequestStatus::Errored) => Some(Transition::Errored),
+            Status::Job(JobStatus::Queued) => Some(Transition::Queued),
+            Status::Job(JobStatus::Finished) => Some(Transition::Finished),
+            Status::Job(JobStatus::Errored) => Some(Transition::Errored),
+            Status::Job(JobStatus::Canceled) => Some(Transition::Canceled),
+            _ => None
+        }
+    }
+
+    /// Return all Transitions
+    pub fn all() -> Vec<Self>{
+        vec![Transition::Validated, Transition::Denied, Transition::Queued,
+             Transition::Finished, Transition::Errored, Transition::Canceled]
+    }
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+
+
+
+// ===========================================================================
+//                           NotificationTemplate
+// ===========================================================================
+
+/// The subject and bodies of a notification with `{placeholders}`. Without \
+/// a HTML template the HTML body is the plain text body in a `<pre>` block
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NotificationTemplate{
+    pub subject: String,
+    pub text: String,
+    pub html: Option<String>
+}
+
+impl NotificationTemplate{
+    /// Create a template without HTML body
+    pub fn new<S>(subject: S, text: S) -> Self where S: Into<String>{
+        NotificationTemplate{ subject: subject.into(), text: text.into(), html: None }
+    }
+
+    /// Add a HTML template. Values are escaped before they are inserted
+    pub fn with_html<S>(mut self, html: S) -> Self where S: Into<String>{
+        self.html = Some(html.into());
+        self
+    }
+
+    /// The default template for a Transition
+    pub fn default_for(transition: Transition) -> Self{
+        let (subject, intro, details) = match transition{
+            Transition::Validated => ("{filename} was accepted", "your blendfile was accepted and will be rendered soon.", ""),
+            Transition::Denied => ("{filename} was rejected", "your blendfile was rejected:", "{message}\n\n"),
+            Transition::Queued => ("{filename} is queued", "your job was queued and waits for a free worker.", ""),
+            Transition::Finished => ("{filename} finished rendering", "your job finished rendering. You can download the frames here:", "{download}\n\n{report}\n"),
+            Transition::Errored => ("{filename} failed", "your job failed:", "{message}\n\n{report}\n"),
+            Transition::Canceled => ("{filename} was canceled", "your job was canceled.", "")
+        };
+        let text = format!("Hello,\n\n{}\n\n{}Job:       {{id}}\nBlendfile: {{filename}}\nStatus:    {{status}}\nProgress:  {{progress}}\n", intro, details);
+        NotificationTemplate::new(format!("[bender] {}", subject), text)
+    }
+
+    /// Replace the placeholders with the values. Unknown placeholders are \
+    /// left untouched
+    pub fn fill(template: &str, values: &BTreeMap<&str, String>) -> String{
+        let mut filled = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{'){
+            filled.push_str(&rest[..start]);
+            let candidate = &rest[start..];
+            match candidate.find('}').and_then(|end| values.get(&candidate[1..end]).map(|v| (end, v))){
+                Some((end, value)) => {
+                    filled.push_str(value);
+                    rest = &candidate[end + 1..];
+                },
+                None => {
+                    filled.push('{');
+                    rest = &candidate[1..];
+                }
+            }
+        }
+        filled.push_str(rest);
+        filled
+    }
+}
+
+
+
+
+// ===========================================================================
+//                               Notification
+// ===========================================================================
+
+/// A composed email
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Notification{
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+    pub job: String,
+    pub transition: Transition
+}
+
+impl Notification{
+    /// Return Error if the sender or the recipient is not a valid address
+    pub fn validate(&self) -> GenResult<()>{
+        validate_address(&self.from)?;
+        validate_address(&self.to)
+    }
+
+    /// Return the notification as MIME message (multipart/alternative with \
+    /// plain text and HTML) with CRLF line endings
+    pub fn to_mime(&self) -> String{
+        let now = Utc::now();
+        let boundary = format!("bender-{}-{}", self.job, now.timestamp_subsec_nanos());
+        let domain = self.from.rsplit('@').next().unwrap_or("localhost");
+        let mut lines = vec![
+            format!("From: {}", self.from),
+            format!("To: {}", self.to),
+            format!("Subject: {}", encode_header(&self.subject)),
+            format!("Date: {}", now.to_rfc2822()),
+            format!("Message-ID: <{}.{}.{}@{}>", self.job, self.transition, now.timestamp_millis(), domain),
+            "MIME-Version: 1.0".to_string(),
+            format!("Content-Type: multipart/alternative; boundary=\"{}\"", boundary),
+            String::new()
+        ];
+        for (content_type, body) in [("text/plain", &self.text), ("text/html", &self.html)].iter(){
+            lines.push(format!("--{}", boundary));
+            lines.push(format!("Content-Type: {}; charset=utf-8", content_type));
+            lines.push("Content-Transfer-Encoding: 8bit".to_string());
+            lines.push(String::new());
+            lines.extend(body.lines().map(|line| line.to_string()));
+        }
+        lines.push(format!("--{}--", boundary));
+        let mut mime = lines.join("\r\n");
+        mime.push_str("\r\n");
+        mime
+    }
+}
+
+
+/// Return Error unless the address is a single plain email address that is \
+/// safe to use in mail headers and SMTP commands: exactly one `@` with \
+/// something on both sides, no whitespace or control characters (e.g. CR or \
+/// LF) and no angle brackets
+pub fn validate_address(address: &str) -> GenResult<()>{
+    let forbidden = address.chars().any(|c| c.is_control() || c.is_whitespace() || c == '<' || c == '>');
+    let mut parts = address.split('@');
+    let valid = match (parts.next(), parts.next(), parts.next()){
+        (Some(local), Some(domain), None) => !local.is_empty() && !domain.is_empty(),
+        _ => false
+    };
+    if forbidden || !valid{
+        return Err(From::from(format!("Error: {:?} is not a valid email address", address)));
+    }
+    Ok(())
+}
+
+
+/// Encode a header value as RFC 2047 encoded word if it isn't plain ASCII
+fn encode_header(value: &str) -> String{
+    if value.is_ascii(){
+        return value.to_string();
+    }
+    let encoded: String = value.bytes().map(|byte| match byte{
+        b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'.' | b'-' | b'[' | b']' => (byte as char).to_string(),
+        b' ' => "_".to_string(),
+        _ => format!("={:02X}", byte)
+    }).collect();
+    format!("=?utf-8?Q?{}?=", encoded)
+}
+
+
+/// Escape text for HTML
+fn escape_html(value: &str) -> String{
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+
+
+
+// ===========================================================================
+//                                 Composer
+// ===========================================================================
+
+/// Composes Notifications from Jobs. Every Transition uses its default \
+/// template unless another one was set with `with_template()`
+#[derive(Debug, Clone)]
+pub struct Composer{
+    pub from: String,
+    pub download_url: Option<String>,
+    templates: HashMap<Transition, NotificationTemplate>
+}
+
+impl Composer{
+    /// Create a Composer that sends from the given address
+    pub fn new<S>(from: S) -> Self where S: Into<String>{
+        let templates = Transition::all().into_iter()
+                                         .map(|t| (t, NotificationTemplate::default_for(t)))
+                                         .collect();
+        Composer{ from: from.into(), download_url: None, templates }
+    }
+
+    /// The base url for downloads, the id of the Job is appended to it
+    pub fn with_download_url<S>(mut self, url: S) -> Self where S: Into<String>{
+        self.download_url = Some(url.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Replace the template of a Transition
+    pub fn with_template(mut self, transition: Transition, template: NotificationTemplate) -> Self{
+        self.templates.insert(transition, template);
+        self
+    }
+
+    /// Return the template of a Transition
+    pub fn template(&self, transition: Transition) -> &NotificationTemplate{
+        &self.templates[&transition]
+    }
+
+    /// Return the values for the placeholders of the Job
+    pub fn values(&self, job: &Job, transition: Transition) -> BTreeMap<&'static str, String>{
+        let download = match (&self.download_url, job.data.get("archive.path")){
+            (Some(url), _) => format!("{}/{}", url, job.id),
+            (None, Some(path)) => path.clone(),
+            (None, None) => job.archive_path().to_string_lossy().to_string()
+        };
+        let mut values = BTreeMap::new();
+        values.insert("id", job.id.clone());
+        values.insert("filename", job.paths.filename.clone());
+        values.insert("email", job.email.clone());
+        values.insert("status", job.status.to_string());
+        values.insert("transition", transition.to_string());
+        values.insert("progress", job.progress().to_string());
+        values.insert("report", job.report().to_text());
+        values.insert("download", download);
+        values.insert("message", job.last_event_message());
+        values
+    }
+
+    /// Compose the Notification for the Transition of the Job. Return Error \
+    /// if the email of the Job or the sender is not a valid address
+    pub fn compose(&self, job: &Job, transition: Transition) -> GenResult<Notification>{
+        validate_address(&self.from)?;
+        validate_address(&job.email)?;
+        let template = self.template(transition);
+        let values = self.values(job, transition);
+        let subject = NotificationTemplate::fill(&template.subject, &values).replace(&['\r', '\n'][..], " ");
+        let text = NotificationTemplate::fill(&template.text, &values);
+        let html = match template.html{
+            Some(ref html) => {
+                let escaped = values.iter().map(|(k, v)| (*k, escape_html(v))).collect();
+                NotificationTemplate::fill(html, &escaped)
+            },
+            None => format!("<html><body><pre>{}</pre></body></html>", escape_html(&text))
+        };
+        Ok(Notification{
+            from: self.from.clone(),
+            to: job.email.clone(),
+            subject,
+            text,
+            html,
+            job: job.id.clone(),
+            transition
+        })
+    }
+
+    /// Compose the Notification and deliver it via the Transport
+    pub fn notify<T>(&self, job: &Job, transition: Transition, transport: &T) -> GenResult<Notification> where T: Transport{
+        let notification = self.compose(job, transition)?;
+        transport.send(&notification)?;
+        Ok(notification)
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                Transports
+// ===========================================================================
+
+/// Delivers Notifications. Implementations validate the Notification \
+/// (see `Notification::validate()`) before sending it
+pub trait Transport{
+    fn send(&self, notification: &Notification) -> GenResult<()>;
+}
+
+
+/// Delivers Notifications to a SMTP server (without authentication or TLS, \
+/// e.g. a local relay)
+#[derive(Debug, Clone)]
+pub struct SmtpTransport{
+    pub host: String,
+    pub port: u16,
+    pub hello: String,
+    pub timeout: Duration
+}
+
+impl SmtpTransport{
+    /// Create a Transport for the server
+    pub fn new<S>(host: S, port: u16) -> Self where S: Into<String>{
+        SmtpTransport{ host: host.into(), port, hello: "localhost".to_string(), timeout: SMTP_TIMEOUT }
+    }
+
+    /// The name this client uses in its `EHLO`
+    pub fn with_hello<S>(mut self, hello: S) -> Self where S: Into<String>{
+        self.hello = hello.into();
+        self
+    }
+
+    /// The timeout for connecting, reading and writing
+    pub fn with_timeout(mut self, timeout: Duration) -> Self{
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Transport for SmtpTransport{
+    fn send(&self, notification: &Notification) -> GenResult<()>{
+        notification.validate()?;
+        let stream = TcpStream::connect((self.host.as_str(), self.port))?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut stream = stream;
+
+        smtp_reply(&mut reader, 220)?;
+        smtp_command(&mut stream, &mut reader, &format!("EHLO {}", self.hello), 250)?;
+        smtp_command(&mut stream, &mut reader, &format!("MAIL FROM:<{}>", notification.from), 250)?;
+        smtp_command(&mut stream, &mut reader, &format!("RCPT TO:<{}>", notification.to), 250)?;
+        smtp_command(&mut stream, &mut reader, "DATA", 354)?;
+        // Lines starting with a dot get another one (dot stuffing)
+        let data: String = notification.to_mime()
+                                       .split("\r\n")
+                                       .map(|line| if line.starts_with('.') { format!(".{}\r\n", line) } else { format!("{}\r\n", line) })
+                                       .collect();
+        stream.write_all(data.trim_end_matches("\r\n").as_bytes())?;
+        smtp_command(&mut stream, &mut reader, "\r\n.", 250)?;
+        smtp_command(&mut stream, &mut reader, "QUIT", 221)?;
+        Ok(())
+    }
+}
+
+
+/// Send a SMTP command and expect a reply with the code
+fn smtp_command<R: BufRead>(stream: &mut TcpStream, reader: &mut R, command: &str, expected: u16) -> GenResult<()>{
+    stream.write_all(format!("{}\r\n", command).as_bytes())?;
+    stream.flush()?;
+    smtp_reply(reader, expected)
+}
+
+
+/// Read a (multiline) SMTP reply and compare its code with the expected one
+fn smtp_reply<R: BufRead>(reader: &mut R, expected: u16) -> GenResult<()>{
+    loop{
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0{
+            return Err(From::from("Error: SMTP server closed the connection"));
+        }
+        let code: u16 = line.get(..3).and_then(|c| c.parse().ok())
+                            .ok_or_else(|| format!("Error: Invalid SMTP reply: {}", line.trim_end()))?;
+        // "250-..." continues, "250 ..." is the last line of a reply
+        if line.as_bytes().get(3) == Some(&b'-'){
+            continue;
+        }
+        return match code == expected{
+            true => Ok(()),
+            false => Err(From::from(format!("Error: SMTP server replied {} (expected {})", line.trim_end(), expected)))
+        };
+    }
+}
+
+
+/// Delivers Notifications into a Maildir (`tmp`, `new`, `cur`), e.g. for \
+/// testing or for local mail clients
+#[derive(Debug, Clone)]
+pub struct MaildirTransport{
+    pub path: PathBuf
+}
+
+impl MaildirTransport{
+    /// Create a Transport for the Maildir at the path (created on first use)
+    pub fn new<P>(path: P) -> Self where P: Into<PathBuf>{
+        MaildirTransport{ path: path.into() }
+    }
+
+    /// Return the paths of all delivered (new) messages, oldest first
+    pub fn messages(&self) -> GenResult<Vec<PathBuf>>{
+        let new = self.path.join("new");
+        if !new.exists(){
+            return Ok(Vec::new());
+        }
+        let mut messages: Vec<PathBuf> = fs::read_dir(new)?.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+        messages.sort();
+        Ok(messages)
+    }
+}
+
+impl Transport for MaildirTransport{
+    fn send(&self, notification: &Notification) -> GenResult<()>{
+        notification.validate()?;
+        for directory in ["tmp", "new", "cur"].iter(){
+            fs::create_dir_all(self.path.join(directory))?;
+        }
+        let now = Utc::now();
+        let name = format!("{}.M{:06}P{}Q{}.bender", now.timestamp(), now.timestamp_subsec_micros(),
+            std::process::id(), MAILDIR_COUNTER.fetch_add(1, Ordering::SeqCst));
+        let tmp = self.path.join("tmp").join(&name);
+        fs::write(&tmp, notification.to_mime())?;
+        fs::rename(&tmp, self.path.join("new").join(&name))?;
+        Ok(())
+    }
+}
+
+
+impl Job{
+    /// Return the Transition that led to the current Status of the Job
+    pub fn transition(&self) -> Option<Transition>{
+        Transition::from_status(&self.status)
+    }
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use common::tempfile::TempDir;
+    use common::JobBuilder;
+
+    fn job() -> Job{
+        let mut job = JobBuilder::new().with_status(Status::Job(JobStatus::Finished)).build();
+        job.add_history("Finished <all> frames");
+        job
+    }
+
+    #[test]
+    fn transitions() {
+        assert_eq!(Transition::from_status(&Status::Request(RequestStatus::Invalid)), Some(Transition::Denied));
+        assert_eq!(Transition::from_status(&Status::Job(JobStatus::Running)), None);
+        assert_eq!(job().transition(), Some(Transition::Finished));
+        assert_eq!(Transition::Canceled.to_string(), "canceled");
+    }
+
+    #[test]
+    fn fill() {
+        let mut values = BTreeMap::new();
+        values.insert("id", "abc".to_string());
+        assert_eq!(NotificationTemplate::fill("{id} {unknown} {id", &values), "abc {unknown} {id");
+        assert_eq!(NotificationTemplate::fill("{{id}}", &values), "{abc}");
+    }
+
+    #[test]
+    fn compose() {
+        let composer = Composer::new("bender@example.com")
+            .with_download_url("https://render.example.com/download/")
+            .with_template(Transition::Errored, NotificationTemplate::new("{id} failed", "{message}").with_html("<b>{message}</b>"));
+        let notification = composer.compose(&job(), Transition::Finished).unwrap();
+        assert_eq!(notification.to, "dh@atoav.com");
+        assert_eq!(notification.subject, "[bender] shot.blend finished rendering");
+        assert!(notification.text.contains("https://render.example.com/download/5873c0033e78b222bec2cb2a221487cf\n"));
+        assert!(notification.text.contains("Status:    job.finished\n"));
+        assert!(notification.text.contains("Job:          5873c0033e78b222bec2cb2a221487cf\n"));
+        assert!(notification.html.starts_with("<html><body><pre>Hello,"));
+
+        let notification = composer.compose(&job(), Transition::Errored).unwrap();
+        assert_eq!(notification.text, "Finished <all> frames");
+        assert_eq!(notification.html, "<b>Finished &lt;all&gt; frames</b>");
+
+        let mime = notification.to_mime();
+        assert!(mime.contains("Subject: 5873c0033e78b222bec2cb2a221487cf failed\r\n"));
+        assert!(mime.contains("Content-Type: text/html; charset=utf-8\r\n"));
+        assert!(mime.ends_with("--\r\n"));
+        assert_eq!(encode_header("Fertig: Szene ä"), "=?utf-8?Q?Fertig=3A_Szene_=C3=A4?=");
+    }
+
+    #[test]
+    fn maildir() {
+        let dir = TempDir::new().unwrap();
+        let transport = MaildirTransport::new(dir.path().join("mail"));
+        let composer = Composer::new("bender@example.com");
+        composer.notify(&job(), Transition::Finished, &transport).unwrap();
+        composer.notify(&job(), Transition::Canceled, &transport).unwrap();
+        let messages = transport.messages().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert!(fs::read_dir(dir.path().join("mail/tmp")).unwrap().next().is_none());
+        let message = fs::read_to_string(&messages[0]).unwrap();
+        assert!(message.starts_with("From: bender@example.com\r\nTo: dh@atoav.com\r\n"));
+    }
+
+    #[test]
+    fn smtp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move ||{
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut stream = stream;
+            let mut transcript = Vec::new();
+            stream.write_all(b"220 localhost ESMTP\r\n").unwrap();
+            let mut in_data = false;
+            loop{
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 { break; }
+                let line = line.trim_end().to_string();
+                let reply: &[u8] = match (in_data, line.as_str()){
+                    (true, ".") => { in_data = false; b"250 queued\r\n" },
+                    (true, _) => { transcript.push(line); continue },
+                    (false, "DATA") => { in_data = true; b"354 go ahead\r\n" },
+                    (false, "QUIT") => { stream.write_all(b"221 bye\r\n").unwrap(); transcript.push("QUIT".to_string()); break },
+                    (false, l) if l.starts_with("EHLO") => b"250-localhost\r\n250 8BITMIME\r\n",
+                    _ => b"250 ok\r\n"
+                };
+                transcript.push(line);
+                stream.write_all(reply).unwrap();
+            }
+            transcript
+        });
+
+        let mut job = job();
+        job.add_history(".hidden line");
+        let composer = Composer::new("bender@example.com")
+            .with_template(Transition::Finished, NotificationTemplate::new("Done", "{message}"));
+        composer.notify(&job, Transition::Finished, &SmtpTransport::new("127.0.0.1", port)).unwrap();
+
+        let transcript = server.join().unwrap();
+        assert_eq!(transcript[0], "EHLO localhost");
+        assert_eq!(transcript[1], "MAIL FROM:<bender@example.com>");
+        assert_eq!(transcript[2], "RCPT TO:<dh@atoav.com>");
+        assert!(transcript.contains(&"Subject: Done".to_string()));
+        assert!(transcript.contains(&"..hidden line".to_string()));
+        assert_eq!(transcript.last().unwrap(), "QUIT");
+    }
+
+    #[test]
+    fn smtp_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move ||{
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"554 go away\r\n").unwrap();
+        });
+        let result = Composer::new("bender@example.com").notify(&job(), Transition::Finished, &SmtpTransport::new("127.0.0.1", port));
+        assert!(result.unwrap_err().to_string().contains("554 go away"));
+    }
+
+    #[test]
+    fn header_injection() {
+        for address in ["dh@atoav.com\r\nBcc: victim@example.com", "dh@atoav.com>\r\nRCPT TO:<victim@example.com",
+                        "dh@atoav.com\nDATA", "dh", "dh@", "@atoav.com", "dh@atoav@com", "d h@atoav.com", ""].iter(){
+            assert!(validate_address(address).is_err(), "{:?}", address);
+            let mut invalid = job();
+            invalid.email = address.to_string();
+            assert!(Composer::new("bender@example.com").compose(&invalid, Transition::Finished).is_err());
+            assert!(Composer::new(*address).compose(&job(), Transition::Finished).is_err());
+        }
+        assert!(validate_address("dh+bender@atoav.com").is_ok());
+
+        // Transports check Notifications that were built by hand, too
+        let dir = TempDir::new().unwrap();
+        let transport = MaildirTransport::new(dir.path());
+        let mut notification = Composer::new("bender@example.com").compose(&job(), Transition::Finished).unwrap();
+        notification.to = "dh@atoav.com\r\nBcc: victim@example.com".to_string();
+        assert!(transport.send(&notification).is_err());
+        assert!(SmtpTransport::new("127.0.0.1", 1).send(&notification).is_err());
+        assert!(transport.messages().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,268 @@
+//! The observer module lets services react to Status transitions of a Job \
+//! without comparing Jobs. Every successful `set_*` transition of a Job \
+//! (e.g. `set_validate()`, `set_queue()`, `set_finish()`) is announced as a \
+//! [StatusChange](struct.StatusChange.html) to all registered Observers, \
+//! which can be callbacks or channels:
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::Job;
+//! let mut job = Job::new("/data/blendfiles/5873c0033e78b222bec2cb2a221487cf/shot.blend", "dh@atoav.com", true);
+//! job.observe(|change| println!("{}", change));
+//! let changes = job.subscribe();
+//!
+//! job.set_validate();
+//! let change = changes.try_recv().unwrap();
+//! assert_eq!(change.to.to_string(), "request.checked");
+//! ```
+//!
+//! Status changes written by other services or by the user (e.g. a cancel) \
+//! are announced as well, once the Job takes them over from disk via \
+//! `update_from_disk()`, `update_status_from_disk()` or `merge()`.
+//!
+//! Observers are not serialized and are not part of the comparison of Jobs. \
+//! Clones of a Job share the Observers of the original.
+
+use ::*;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Sender, Receiver};
+
+
+
+
+// ===========================================================================
+//                               StatusChange
+// ===========================================================================
+
+/// A transition of a Job from one Status to another
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatusChange{
+    pub job: String,
+    pub from: Status,
+    pub to: Status,
+    pub time: DateTime<Utc>,
+    /// The message the transition was logged with in the history
+    pub reason: String
+}
+
+impl fmt::Display for StatusChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}: {} -> {} ({})", self.time.to_rfc3339(), self.job, self.from, self.to, self.reason)
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                 Observer
+// ===========================================================================
+
+/// Receives the StatusChanges of a Job
+pub trait Observer: Send + Sync{
+    fn notify(&self, change: &StatusChange);
+}
+
+impl<F> Observer for F where F: Fn(&StatusChange) + Send + Sync{
+    fn notify(&self, change: &StatusChange){
+        self(change)
+    }
+}
+
+
+/// Sends every StatusChange into a channel. Changes are dropped silently \
+/// once the Receiver is gone
+pub struct ChannelObserver{
+    sender: Mutex<Sender<StatusChange>>
+}
+
+impl ChannelObserver{
+    pub fn new(sender: Sender<StatusChange>) -> Self{
+        ChannelObserver{ sender: Mutex::new(sender) }
+    }
+}
+
+impl Observer for ChannelObserver{
+    fn notify(&self, change: &StatusChange){
+        if let Ok(sender) = self.sender.lock(){
+            let _ = sender.send(change.clone());
+        }
+    }
+}
+
+
+/// The Observers registered with a Job
+#[derive(Clone, Default)]
+pub struct Observers(Vec<Arc<dyn Observer>>);
+
+impl Observers{
+    /// Register an Observer
+    pub fn add(&mut self, observer: Arc<dyn Observer>){
+        self.0.push(observer);
+    }
+
+    /// Remove all Observers
+    pub fn clear(&mut self){
+        self.0.clear();
+    }
+
+    pub fn len(&self) -> usize{
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.0.is_empty()
+    }
+
+    /// Announce a StatusChange to all Observers
+    pub fn notify(&self, change: &StatusChange){
+        self.0.iter().for_each(|observer| observer.notify(change));
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                   Job
+// ===========================================================================
+
+impl Job{
+    /// Register an Observer that receives every StatusChange of the Job
+    pub fn add_observer(&mut self, observer: Arc<dyn Observer>){
+        self.observers.add(observer);
+    }
+
+    /// Register a callback that is called with every StatusChange of the Job
+    pub fn observe<F>(&mut self, callback: F) where F: Fn(&StatusChange) + Send + Sync + 'static{
+        self.add_observer(Arc::new(callback));
+    }
+
+    /// Return a Receiver that receives every following StatusChange of the Job
+    pub fn subscribe(&mut self) -> Receiver<StatusChange>{
+        let (sender, receiver) = channel();
+        self.add_observer(Arc::new(ChannelObserver::new(sender)));
+        receiver
+    }
+
+    /// Announce the transition from the previous Status to the current one. \
+    /// Called by the `set_*` methods after a successful transition and when \
+    /// a Status is taken over from disk (`update_*from_disk()`, `merge()`)
+    pub fn announce_transition<S>(&self, from: Status, reason: S) where S: Into<String>{
+        if self.observers.is_empty() || from == self.status{
+            return;
+        }
+        let change = StatusChange{
+            job: self.id.clone(),
+            from,
+            to: self.status.clone(),
+            time: Utc::now(),
+            reason: reason.into()
+        };
+        self.observers.notify(&change);
+    }
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use status::{RequestStatus, JobStatus};
+    use common::{JobBuilder, TEST_JOB_ID};
+    use common::tempfile::TempDir;
+
+    #[test]
+    fn lifecycle() {
+        let mut job = JobBuilder::new().build();
+        let changes = job.subscribe();
+        job.set_validate();
+        job.set_scan();
+        job.set_atomize();
+        job.set_queue();
+        job.set_run();
+        job.set_finish();
+        let changes: Vec<StatusChange> = changes.try_iter().collect();
+        assert_eq!(changes.len(), 6);
+        assert_eq!(changes[0].from, Status::Request(RequestStatus::Untouched));
+        assert_eq!(changes[0].to, Status::Request(RequestStatus::Checked));
+        assert_eq!(changes[3].reason, "Queued Job to job queue");
+        assert_eq!(changes[5].to, Status::Job(JobStatus::Finished));
+        assert!(changes.iter().all(|c| c.job == "5873c0033e78b222bec2cb2a221487cf"));
+    }
+
+    #[test]
+    fn failed_transitions() {
+        let mut job = JobBuilder::new().build();
+        let changes = job.subscribe();
+        // A request can't be finished
+        job.set_finish();
+        assert!(changes.try_recv().is_err());
+        job.set_error("Blender crashed");
+        let change = changes.try_recv().unwrap();
+        assert_eq!(change.to, Status::Request(RequestStatus::Errored));
+        assert_eq!(change.reason, "Error: Blender crashed");
+    }
+
+    #[test]
+    fn callbacks() {
+        let mut job = JobBuilder::new().build();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let inner = seen.clone();
+        job.observe(move |change| inner.lock().unwrap().push(change.to.to_string()));
+        let clone = job.clone();
+        job.set_deny();
+        assert_eq!(*seen.lock().unwrap(), vec!["request.invalid"]);
+
+        // Observers are neither compared nor serialized
+        assert_eq!(clone.observers.len(), 1);
+        let deserialized = Job::deserialize_from_u8(&job.serialize_to_u8().unwrap()).unwrap();
+        assert!(deserialized.observers.is_empty());
+        job.observers.clear();
+        assert_eq!(job, deserialized);
+    }
+
+    #[test]
+    fn changes_from_disk() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join(TEST_JOB_ID)).unwrap();
+        let mut job = JobBuilder::new().in_directory(dir.path()).build();
+        job.set_validate();
+        job.write_to_file().unwrap();
+        let changes = job.subscribe();
+
+        // Another service queued the Job, reloading it keeps the Observers
+        let mut other = Job::from_datajson(&job.paths.data).unwrap();
+        other.set_scan();
+        other.set_atomize();
+        other.set_queue();
+        other.write_to_file().unwrap();
+        job.update_from_disk().unwrap();
+        assert_eq!(job.observers.len(), 1);
+        let change = changes.try_recv().unwrap();
+        assert_eq!(change.from, Status::Request(RequestStatus::Checked));
+        assert_eq!(change.to, Status::Job(JobStatus::Queued));
+        assert_eq!(change.reason, "Queued Job to job queue");
+
+        // The user canceled the Job
+        fs::write(dir.path().join(TEST_JOB_ID).join("canceled"), "").unwrap();
+        job.update_status_from_disk().unwrap();
+        let change = changes.try_recv().unwrap();
+        assert_eq!(change.to, Status::Job(JobStatus::Canceled));
+        assert_eq!(change.reason, "Canceled Job");
+        assert!(changes.try_recv().is_err());
+    }
+}
@@ -25,8 +25,12 @@ try:
 except:
     print("Error: Couldn't get bpy.context.scene")
 
-# Only allow still image formats to avoid video encoding
-allowed_formats = ["PNG", "BMP", "JPEG", "JPEG2000", "TARGA", "TARGA_RAW", "CINEON", "DPX", "OPEN_EXR_MULTILAYER", "OPEN_EXR", "HDR", "TIFF"]
+# Only allow still image formats to avoid video encoding. The list is passed
+# by bender-job (see ImageFormat), without it only the override format is allowed
+try:
+    allowed_formats = os.environ['BENDER_ALLOWEDFORMATS'].split(",")
+except KeyError:
+    allowed_formats = [BENDER_OVERRIDEFORMAT]
 
 try:
     # Check if Cycles is used
@@ -71,6 +75,34 @@ if not valid_format:
         scene.render.image_settings.color_depth == "16"
     history[now()] = "optimize_blend.py: Output file format in file ("+str(image_format)+") was not in the list of valid formats. Used "+BENDER_OVERRIDEFORMAT+" instead!"
 
+# Collect the files blender writes per frame. Multiview renders with
+# individual views write one file per view, File Output nodes in the
+# compositor write one file per slot (multilayer EXR: one file per node)
+try:
+    views = [""]
+    if scene.render.use_multiview and scene.render.views_format == 'INDIVIDUAL':
+        views = [view.file_suffix for view in scene.render.views if view.use]
+    outputs = [{"name": "render"+view, "view": view} for view in views]
+    if scene.render.use_compositing and scene.use_nodes and scene.node_tree:
+        for node in scene.node_tree.nodes:
+            if node.type != 'OUTPUT_FILE' or node.mute:
+                continue
+            if node.format.file_format == 'OPEN_EXR_MULTILAYER':
+                slots = [("", node.format.file_format)]
+            else:
+                slots = [(slot.path, node.format.file_format if slot.use_node_format else slot.format.file_format) for slot in node.file_slots]
+            for path, file_format in slots:
+                for view in views:
+                    outputs.append({"name": node.name+"/"+path+view, "node": node.name, "slot": path, "view": view, "image_format": file_format})
+    # A single file per frame is the default and needs no outputs
+    if len(outputs) == 1:
+        outputs = []
+    else:
+        history[now()] = "optimize_blend.py: Found "+str(len(outputs))+" files per frame: "+", ".join([o["name"] for o in outputs])
+except:
+    outputs = []
+    history[now()] = "optimize_blend.py: Error: Couldn't detect the outputs per frame"
+
 # Delete unused Materials:
 n_materials = len(bpy.data.materials)
 n_materials_removed = 0
@@ -116,6 +148,9 @@ status = {
         "device": scene.cycles.device,
         "image_format": image_format,
         "uses_compositing": scene.render.use_compositing,
+        "scene": scene.name,
+        "camera": scene.camera.name if scene.camera else "",
+        "outputs": outputs,
     },
     "materials": {
         "n": n_materials,
@@ -0,0 +1,227 @@
+//! The outputs module defines the [OutputSpec](struct.OutputSpec.html), which \
+//! describes one of the files blender writes per frame. Most blendfiles \
+//! write exactly one file per frame, but these write several:
+//! - multiview renders with individual views write one file per view \
+//!   (e.g. `000001_L.png` and `000001_R.png`)
+//! - File Output nodes in the compositor write one file per slot (or one \
+//!   multilayer EXR per node)
+//!
+//! The expected outputs are detected by the [gaffer](../gaffer/index.html) \
+//! scan and stored in [Render](../data/struct.Render.html). File Output \
+//! nodes are repointed into a subdirectory of the output path named after \
+//! the node, so their files can be found after rendering.
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::outputs::OutputSpec;
+//! let left = OutputSpec::view("_L");
+//! assert_eq!(left.file_name("000001", 1, "PNG"), "000001_L.png");
+//!
+//! let depth = OutputSpec::file_output("Depth Output", "depth_", "OPEN_EXR");
+//! assert_eq!(depth.file_name("000001", 1, "PNG"), "Depth Output/depth_0001.exr");
+//! ```
+
+use ::*;
+use template::sanitize;
+
+
+/// The name of the main render output
+pub const MAIN: &str = "render";
+
+/// The padding blender uses for frames in File Output node slots without `#`
+pub const NODE_PADDING: usize = 4;
+
+
+// ===========================================================================
+//                                OutputSpec
+// ===========================================================================
+
+/// One file blender writes per frame. The name identifies the output within \
+/// a [Frame](../frames/struct.Frame.html). For the main render, node and \
+/// slot are None. The view is the suffix blender appends for multiview \
+/// renders (empty if multiview is off). If no image_format is given, the \
+/// image_format of the command is used.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct OutputSpec{
+    pub name: String,
+    #[serde(default)]
+    pub node: Option<String>,
+    #[serde(default)]
+    pub slot: Option<String>,
+    #[serde(default)]
+    pub view: String,
+    #[serde(default)]
+    pub image_format: Option<String>
+}
+
+impl OutputSpec{
+    /// The main render output
+    pub fn main() -> Self{
+        OutputSpec{
+            name: MAIN.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// The main render output of a single multiview view with the given \
+    /// suffix (e.g. "_L")
+    pub fn view<S>(suffix: S) -> Self where S: Into<String>{
+        let suffix = suffix.into();
+        OutputSpec{
+            name: format!("{}{}", MAIN, suffix),
+            view: suffix,
+            ..Default::default()
+        }
+    }
+
+    /// A slot of a compositor File Output node. The slot is the path of the \
+    /// slot (empty for multilayer EXR nodes)
+    pub fn file_output<S>(node: S, slot: S, image_format: S) -> Self where S: Into<String>{
+        let node = node.into();
+        let slot = slot.into();
+        OutputSpec{
+            name: format!("{}/{}", node, slot),
+            node: Some(node),
+            slot: Some(slot),
+            image_format: Some(image_format.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Return the same output for the view with the given suffix
+    pub fn with_view<S>(mut self, suffix: S) -> Self where S: Into<String>{
+        let suffix = suffix.into();
+        self.name = format!("{}{}", self.name, suffix);
+        self.view = suffix;
+        self
+    }
+
+    /// Return true if the output is written by a File Output node
+    pub fn is_node(&self) -> bool{
+        self.node.is_some()
+    }
+
+    /// Return the directory (relative to the output path) the node writes \
+    /// into, or None for the main render
+    pub fn directory(&self) -> Option<String>{
+        self.node.as_ref().map(|node| sanitize(node))
+    }
+
+    /// Return the image format of the output, falling back to the given one
+    pub fn image_format(&self, default: &str) -> String{
+        self.image_format.clone().unwrap_or_else(|| default.to_string())
+    }
+
+    /// Return the extension blender writes for this output. Unknown formats \
+    /// fall back to the lowercase format name
+    pub fn extension(&self, default: &str) -> String{
+        let image_format = self.image_format(default);
+        match ImageFormat::from_blender(&image_format){
+            Some(format) => format.extension().to_string(),
+            None => image_format.to_lowercase()
+        }
+    }
+
+    /// Return the path of the output relative to the output path. The stem \
+    /// is the filename of the main render (see [OutputTemplate](../template/struct.OutputTemplate.html)), \
+    /// File Output nodes name their files after the slot instead.
+    pub fn file_name(&self, stem: &str, framenumber: usize, default_format: &str) -> String{
+        let extension = self.extension(default_format);
+        match self.directory(){
+            Some(directory) => {
+                let slot = self.slot.as_deref().unwrap_or("");
+                format!("{}/{}{}.{}", directory, slot_stem(slot, framenumber), self.view, extension)
+            },
+            None => format!("{}{}.{}", stem, self.view, extension)
+        }
+    }
+}
+
+
+/// Return the filename of a File Output node slot for a frame: a run of `#` \
+/// is replaced by the zero padded frame, otherwise the frame is appended
+fn slot_stem(slot: &str, framenumber: usize) -> String{
+    match slot.find('#'){
+        Some(start) => {
+            let padding = slot[start..].chars().take_while(|c| *c == '#').count();
+            format!("{}{:0width$}{}", &slot[..start], framenumber, &slot[start+padding..], width = padding)
+        },
+        None => format!("{}{:0width$}", slot, framenumber, width = NODE_PADDING)
+    }
+}
+
+
+/// Return a python expression for blender's `--python-expr` that points the \
+/// File Output nodes of the rendered scene into their directories below the \
+/// outpath, or None if none of the outputs is written by a node
+pub fn python_expr(outpath: &str, outputs: &[OutputSpec]) -> Option<String>{
+    let directories: BTreeMap<&str, String> = outputs.iter()
+        .filter_map(|output| match (output.node.as_ref(), output.directory()){
+            (Some(node), Some(directory)) => Some((node.as_str(), format!("{}/{}/", outpath, directory))),
+            _ => None
+        })
+        .collect();
+    if directories.is_empty(){
+        return None;
+    }
+    // A JSON object of strings is a valid python dict literal
+    let map = serde_json::to_string(&directories).ok()?;
+    Some(format!("import bpy;m={};t=bpy.context.scene.node_tree;\
+[setattr(n,'base_path',m[n.name]) for n in (t.nodes if t else []) if n.type=='OUTPUT_FILE' and n.name in m]", map))
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn main_output() {
+        let o = OutputSpec::main();
+        assert_eq!(o.name, "render");
+        assert!(!o.is_node());
+        assert_eq!(o.file_name("shot_0042", 42, "OPEN_EXR"), "shot_0042.exr");
+    }
+
+    #[test]
+    fn views() {
+        assert_eq!(OutputSpec::view("_R").name, "render_R");
+        assert_eq!(OutputSpec::view("_R").file_name("000007", 7, "JPEG"), "000007_R.jpg");
+        let node = OutputSpec::file_output("Out", "beauty", "PNG").with_view("_L");
+        assert_eq!(node.name, "Out/beauty_L");
+        assert_eq!(node.file_name("000007", 7, "JPEG"), "Out/beauty0007_L.png");
+    }
+
+    #[test]
+    fn node_slots() {
+        let o = OutputSpec::file_output("Pass/Out", "mist_###_x", "TIFF");
+        assert_eq!(o.directory(), Some("Pass_Out".to_string()));
+        assert_eq!(o.file_name("", 12, "PNG"), "Pass_Out/mist_012_x.tif");
+        let multilayer = OutputSpec::file_output("Layers", "", "OPEN_EXR_MULTILAYER");
+        assert_eq!(multilayer.file_name("", 12345, "PNG"), "Layers/12345.exr");
+    }
+
+    #[test]
+    fn python_expression() {
+        assert_eq!(python_expr("/out", &[OutputSpec::main(), OutputSpec::view("_L")]), None);
+        let outputs = vec![OutputSpec::main(),
+                           OutputSpec::file_output("Out", "a", "PNG"),
+                           OutputSpec::file_output("Out", "b", "PNG")];
+        let expr = python_expr("/out", &outputs).unwrap();
+        assert!(expr.starts_with("import bpy;m={\"Out\":\"/out/Out/\"};"));
+    }
+
+    #[test]
+    fn deserialize_defaults() {
+        let o: OutputSpec = serde_json::from_str("{\"name\": \"render\"}").unwrap();
+        assert_eq!(o, OutputSpec::main());
+    }
+}
@@ -0,0 +1,344 @@
+//! The progress module answers the question "how long until my job is \
+//! done?". A [Progress](struct.Progress.html) report is created from the \
+//! Tasks of a Job and holds the percentage of frames done, the state of the \
+//! Tasks, the elapsed time, an ETA and the throughput trend. It serializes \
+//! to JSON, so the frontend and emails can show it directly.
+//!
+//! The ETA divides the remaining render time of the running and waiting \
+//! Tasks by the number of Tasks that are currently running in parallel. \
+//! Frames of errored or aborted Tasks are not part of it. The render time \
+//! per frame comes from the [Estimator](../estimate/struct.Estimator.html).
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::Job;
+//! let job = Job::new("/data/blendfiles/5873c0033e78b222bec2cb2a221487cf/shot.blend", "dh@atoav.com", true);
+//! let progress = job.progress();
+//! assert_eq!(progress.percent, 0.0);
+//! println!("{}", progress.to_json().unwrap());
+//! ```
+
+use ::*;
+use estimate::{Estimator, format_seconds, to_seconds};
+
+
+/// The length of the window the throughput is measured in (in minutes)
+pub const THROUGHPUT_WINDOW_MINUTES: i64 = 10;
+
+/// The relative change of the throughput between two windows that counts \
+/// as a trend
+const TREND_THRESHOLD: f64 = 0.2;
+
+
+
+
+// ===========================================================================
+//                                  Trend
+// ===========================================================================
+
+/// How the throughput of the last window compares to the window before
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Trend{
+    /// Not enough finished Tasks to tell
+    Unknown,
+    Rising,
+    Steady,
+    Falling
+}
+
+impl fmt::Display for Trend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self{
+            Trend::Unknown => write!(f, "unknown"),
+            Trend::Rising => write!(f, "rising"),
+            Trend::Steady => write!(f, "steady"),
+            Trend::Falling => write!(f, "falling")
+        }
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                 Progress
+// ===========================================================================
+
+/// A snapshot of the progress of a Job. Times are in seconds
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Progress{
+    pub id: String,
+    pub status: String,
+    pub created: DateTime<Utc>,
+    /// Frames of all blender Tasks
+    pub frames_total: usize,
+    /// Frames of finished Tasks
+    pub frames_done: usize,
+    /// Frames of running Tasks
+    pub frames_running: usize,
+    /// Percentage of frames done (0.0 - 100.0)
+    pub percent: f64,
+    pub tasks_total: usize,
+    pub tasks_finished: usize,
+    pub tasks_running: usize,
+    pub tasks_waiting: usize,
+    pub tasks_failed: usize,
+    /// Seconds since the Job started running
+    pub elapsed: Option<f64>,
+    /// Estimated seconds until the running and waiting Tasks are done, None \
+    /// if nothing runs
+    pub eta: Option<f64>,
+    /// The estimated time all frames are done
+    pub eta_at: Option<DateTime<Utc>>,
+    /// Frames per minute finished in the last window
+    pub throughput: Option<f64>,
+    pub trend: Trend
+}
+
+impl Progress{
+    /// Create a progress report for the Job, estimating render times only \
+    /// from the Job itself
+    pub fn from_job(job: &Job) -> Self{
+        Self::with_estimator(job, &Estimator::new())
+    }
+
+    /// Create a progress report for the Job, estimating render times with \
+    /// the given Estimator
+    pub fn with_estimator(job: &Job, estimator: &Estimator) -> Self{
+        Self::at(job, estimator, Utc::now())
+    }
+
+    /// Create a progress report for the Job as it is at the given time
+    pub fn at(job: &Job, estimator: &Estimator, now: DateTime<Utc>) -> Self{
+        let mut progress = Progress{
+            id: job.id.clone(),
+            status: job.status.to_string(),
+            created: now,
+            frames_total: 0,
+            frames_done: 0,
+            frames_running: 0,
+            percent: 0.0,
+            tasks_total: 0,
+            tasks_finished: 0,
+            tasks_running: 0,
+            tasks_waiting: 0,
+            tasks_failed: 0,
+            elapsed: job.time.start.map(|start| to_seconds(job.time.finish.unwrap_or(now) - start)),
+            eta: None,
+            eta_at: None,
+            throughput: None,
+            trend: Trend::Unknown
+        };
+
+        let per_frame = estimator.estimate(job).per_frame;
+        let mut running_work = 0.0;
+        let mut waiting_frames = 0;
+        let mut finished = Vec::new();
+        for task in job.tasks.iter(){
+            let frames = match task.command{
+                Command::Blender(ref command) => command.frame.len(),
+                _ => continue
+            };
+            progress.tasks_total += 1;
+            progress.frames_total += frames;
+            if task.is_finished(){
+                progress.tasks_finished += 1;
+                progress.frames_done += frames;
+                if let Some(finish) = task.time.finish{
+                    finished.push((finish, frames));
+                }
+            }else if task.is_running(){
+                progress.tasks_running += 1;
+                progress.frames_running += frames;
+                // The part of the Task that is still to do
+                let elapsed = task.time.start.map(|start| to_seconds(now - start)).unwrap_or(0.0);
+                running_work += (per_frame * frames as f64 - elapsed).max(0.0);
+            }else if task.is_errored(){
+                progress.tasks_failed += 1;
+            }else if !task.is_ended(){
+                progress.tasks_waiting += 1;
+                waiting_frames += frames;
+            }
+        }
+        if progress.frames_total > 0{
+            progress.percent = 100.0 * progress.frames_done as f64 / progress.frames_total as f64;
+        }
+
+        // Everything that doesn't run yet is spread over the current parallelism.
+        // Errored and aborted Tasks are not rendered again by themselves, so
+        // their frames don't count
+        if progress.tasks_running > 0{
+            let waiting_work = per_frame * waiting_frames as f64;
+            let eta = (running_work + waiting_work) / progress.tasks_running as f64;
+            progress.eta = Some(eta);
+            progress.eta_at = Some(now + chrono::Duration::milliseconds((eta * 1000.0) as i64));
+        }else if progress.frames_total > 0 && progress.frames_done == progress.frames_total{
+            progress.eta = Some(0.0);
+        }
+
+        let (throughput, trend) = throughput(&finished, now);
+        progress.throughput = throughput;
+        progress.trend = trend;
+        progress
+    }
+
+    /// Return true if all frames are done
+    pub fn is_done(&self) -> bool{
+        self.frames_total > 0 && self.frames_done == self.frames_total
+    }
+
+    /// Serialize the report to JSON
+    pub fn to_json(&self) -> GenResult<String>{
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.0}% ({} of {} frames)", self.percent, self.frames_done, self.frames_total)?;
+        if self.tasks_running > 0{
+            write!(f, ", {} running", self.tasks_running)?;
+        }
+        if self.tasks_failed > 0{
+            write!(f, ", {} failed", self.tasks_failed)?;
+        }
+        if let Some(eta) = self.eta.filter(|_| !self.is_done()){
+            write!(f, ", about {} left", format_seconds(eta))?;
+        }
+        if let Some(throughput) = self.throughput{
+            write!(f, ", {:.1} frames/min ({})", throughput, self.trend)?;
+        }
+        Ok(())
+    }
+}
+
+
+impl Job{
+    /// Return a progress report of the Job (see [Progress](progress/struct.Progress.html))
+    pub fn progress(&self) -> Progress{
+        Progress::from_job(self)
+    }
+}
+
+
+/// Return the frames per minute finished in the last window and how it \
+/// compares to the window before. The trend is only known if Tasks have \
+/// been finishing for longer than a window
+fn throughput(finished: &[(DateTime<Utc>, usize)], now: DateTime<Utc>) -> (Option<f64>, Trend){
+    let window = chrono::Duration::minutes(THROUGHPUT_WINDOW_MINUTES);
+    let frames_between = |from: DateTime<Utc>, to: DateTime<Utc>| -> usize{
+        finished.iter().filter(|(t, _)| *t > from && *t <= to).map(|(_, n)| n).sum()
+    };
+    if finished.is_empty(){
+        return (None, Trend::Unknown);
+    }
+    let recent = frames_between(now - window, now) as f64;
+    let before = frames_between(now - window - window, now - window) as f64;
+    let throughput = Some(recent / THROUGHPUT_WINDOW_MINUTES as f64);
+    if finished.iter().all(|(t, _)| *t > now - window){
+        return (throughput, Trend::Unknown);
+    }
+    let trend = if recent > before * (1.0 + TREND_THRESHOLD){
+        Trend::Rising
+    }else if recent < before * (1.0 - TREND_THRESHOLD){
+        Trend::Falling
+    }else{
+        Trend::Steady
+    };
+    (throughput, trend)
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{JobBuilder, TEST_JOB_ID};
+
+    fn task(frame: usize, start: Option<i64>, finish: Option<i64>, now: DateTime<Utc>) -> Task{
+        let mut task = Task::new_blender_single(frame, "PNG", TEST_JOB_ID);
+        if start.is_some(){
+            task.queue();
+            task.start();
+            task.time.start = start.map(|m| now - chrono::Duration::minutes(m));
+        }
+        if finish.is_some(){
+            task.finish();
+            task.time.finish = finish.map(|m| now - chrono::Duration::minutes(m));
+        }
+        task
+    }
+
+    fn job(now: DateTime<Utc>) -> Job{
+        let mut job = JobBuilder::new().with_frames(1, 10).build();
+        job.time.start = Some(now - chrono::Duration::minutes(30));
+        // Every finished task took 2 minutes
+        for (i, finish) in [18, 16, 14, 12, 8, 6].iter().enumerate(){
+            job.tasks.push_back(task(i + 1, Some(finish + 2), Some(*finish), now));
+        }
+        // Two running tasks, started a minute ago
+        job.tasks.push_back(task(7, Some(1), None, now));
+        job.tasks.push_back(task(8, Some(1), None, now));
+        job.tasks.push_back(task(9, None, None, now));
+        let mut failed = task(10, None, None, now);
+        failed.queue();
+        failed.error();
+        job.tasks.push_back(failed);
+        job
+    }
+
+    #[test]
+    fn report() {
+        let now = Utc::now();
+        let progress = Progress::at(&job(now), &Estimator::new(), now);
+        assert_eq!(progress.frames_total, 10);
+        assert_eq!(progress.frames_done, 6);
+        assert_eq!(progress.frames_running, 2);
+        assert_eq!(progress.percent, 60.0);
+        assert_eq!((progress.tasks_total, progress.tasks_finished, progress.tasks_running, progress.tasks_waiting, progress.tasks_failed),
+                   (10, 6, 2, 1, 1));
+        assert_eq!(progress.elapsed, Some(1800.0));
+        // 2 x 60s left on the running tasks and 120s for the waiting one on 2
+        // workers, the failed one is not rendered again
+        assert_eq!(progress.eta, Some(120.0));
+        assert_eq!(progress.eta_at, Some(now + chrono::Duration::seconds(120)));
+        // 2 frames in the last 10 minutes, 4 in the 10 minutes before
+        assert_eq!(progress.throughput, Some(0.2));
+        assert_eq!(progress.trend, Trend::Falling);
+        assert_eq!(progress.to_string(), "60% (6 of 10 frames), 2 running, 1 failed, about 2m 00s left, 0.2 frames/min (falling)");
+    }
+
+    #[test]
+    fn idle_and_done() {
+        let now = Utc::now();
+        let job = JobBuilder::new().build();
+        let progress = Progress::at(&job, &Estimator::new(), now);
+        assert_eq!((progress.percent, progress.eta, progress.elapsed, progress.trend), (0.0, None, None, Trend::Unknown));
+        assert!(!progress.is_done());
+
+        let mut job = job;
+        job.tasks.push_back(task(1, Some(3), Some(1), now));
+        let progress = Progress::at(&job, &Estimator::new(), now);
+        assert!(progress.is_done());
+        assert_eq!(progress.eta, Some(0.0));
+        assert_eq!(progress.to_string(), "100% (1 of 1 frames), 0.1 frames/min (unknown)");
+    }
+
+    #[test]
+    fn json() {
+        let now = Utc::now();
+        let progress = Progress::at(&job(now), &Estimator::new(), now);
+        let json = progress.to_json().unwrap();
+        assert!(json.contains("\"percent\":60.0"));
+        assert!(json.contains("\"trend\":\"Falling\""));
+        let deserialized: Progress = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, progress);
+    }
+}
@@ -0,0 +1,319 @@
+//! The renderlog module parses the stdout of blender into typed \
+//! [RenderEvents](enum.RenderEvent.html). Blender reports its progress with \
+//! lines like these:
+//! ```text
+//! Fra:12 Mem:230M (Peak 310M) | Time:00:04.12 | Rendering 12/64 Tiles
+//! Saved: '/data/frames/000012.png'
+//!  Time: 00:09.31 (Saving: 00:00.12)
+//! ```
+//! The parser is stateful, because some information (e.g. the render time of \
+//! a saved frame) is spread across multiple lines. Feed it line by line (e.g. \
+//! from the output handler of the [Executor](../executor/struct.Executor.html)) \
+//! and call `finish()` once the process exited.
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::renderlog::{RenderLog, RenderEvent};
+//! let mut log = RenderLog::new();
+//! let mut events = Vec::new();
+//! events.extend(log.parse_line("Fra:12 Mem:230M (Peak 310M) | Time:00:04.12 | Rendering 16/64 Tiles"));
+//! events.extend(log.parse_line("Saved: '/data/frames/000012.png'"));
+//! events.extend(log.finish());
+//!
+//! assert_eq!(events[0], RenderEvent::FrameStarted{ frame: 12 });
+//! assert_eq!(events[2], RenderEvent::Progress{ frame: 12, percent: 25.0 });
+//! assert_eq!(log.current_frame(), Some(12));
+//! ```
+
+use ::*;
+use regex::Regex;
+use std::time::Duration;
+
+
+
+
+// ===========================================================================
+//                               RenderEvent
+// ===========================================================================
+
+/// A event extracted from the output of blender
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderEvent{
+    /// The version blender reports at startup, e.g. "2.79 (sub 0)"
+    Version(String),
+    /// Blender started working on a frame
+    FrameStarted{ frame: usize },
+    /// The render progress of the current frame in percent (0.0 to 100.0)
+    Progress{ frame: usize, percent: f64 },
+    /// The peak memory (in bytes) of the current frame grew
+    Memory{ frame: usize, peak: usize },
+    /// A frame was written to path. The render time is the time blender \
+    /// reported for the frame (including saving)
+    FrameSaved{ frame: Option<usize>, path: String, render_time: Option<Duration> },
+    /// Blender reported a error
+    Error(String)
+}
+
+
+
+
+// ===========================================================================
+//                                RenderLog
+// ===========================================================================
+
+/// A stateful parser for blender's stdout
+#[derive(Debug, Clone)]
+pub struct RenderLog{
+    frame: Option<usize>,
+    peak: usize,
+    last_time: Option<Duration>,
+    saved: Option<String>,
+    frame_re: Regex,
+    peak_re: Regex,
+    time_re: Regex,
+    progress_re: Regex,
+    saved_re: Regex,
+    saved_time_re: Regex,
+    version_re: Regex,
+    error_re: Regex
+}
+
+impl Default for RenderLog{
+    fn default() -> Self{
+        RenderLog::new()
+    }
+}
+
+impl RenderLog{
+    /// Create a new RenderLog parser
+    pub fn new() -> Self{
+        RenderLog{
+            frame: None,
+            peak: 0,
+            last_time: None,
+            saved: None,
+            frame_re: Regex::new(r"^Fra:\s*(\d+)\b").unwrap(),
+            peak_re: Regex::new(r"Peak:?\s*([\d.]+)\s*([KMGT]?)").unwrap(),
+            time_re: Regex::new(r"\|\s*Time:\s*([\d:.]+)").unwrap(),
+            progress_re: Regex::new(r"(?:Rendering|Rendered|Path Tracing Tile|Path Tracing Sample|Sample)\s+(\d+)\s*/\s*(\d+)").unwrap(),
+            saved_re: Regex::new(r#"^Saved:\s*['"]?(.*?)['"]?\s*$"#).unwrap(),
+            saved_time_re: Regex::new(r"^\s*Time:\s*([\d:.]+)").unwrap(),
+            version_re: Regex::new(r"^Blender\s+(\d+\.\d+.*?)\s*(?:\(hash.*)?$").unwrap(),
+            error_re: Regex::new(r"^(?:.*\|\s*)?(?:Error|ERROR)\s*:\s*(.+?)\s*$").unwrap()
+        }
+    }
+
+    /// Parse all lines of a complete log and return the events
+    pub fn parse<S>(log: S) -> Vec<RenderEvent> where S: AsRef<str>{
+        let mut parser = RenderLog::new();
+        let mut events: Vec<RenderEvent> = log.as_ref()
+                                              .lines()
+                                              .flat_map(|line| parser.parse_line(line))
+                                              .collect();
+        events.extend(parser.finish());
+        events
+    }
+
+    /// Return the frame blender is currently working on
+    pub fn current_frame(&self) -> Option<usize>{
+        self.frame
+    }
+
+    /// Parse a single line of output and return the events it produced. \
+    /// A saved frame is only reported once the next line arrived (or \
+    /// `finish()` was called), because the render time follows on the next line
+    pub fn parse_line<S>(&mut self, line: S) -> Vec<RenderEvent> where S: AsRef<str>{
+        let line = line.as_ref().trim_end();
+        let mut events = Vec::new();
+
+        // A pending saved frame is completed by a Time line
+        if let Some(path) = self.saved.take(){
+            let render_time = match self.saved_time_re.captures(line){
+                Some(captures) => parse_duration(&captures[1]),
+                None => None
+            };
+            let saved_time_line = render_time.is_some();
+            events.push(RenderEvent::FrameSaved{
+                frame: self.frame,
+                path,
+                render_time: render_time.or(self.last_time)
+            });
+            if saved_time_line { return events; }
+        }
+
+        if let Some(captures) = self.frame_re.captures(line){
+            if let Ok(frame) = captures[1].parse::<usize>(){
+                if Some(frame) != self.frame{
+                    self.frame = Some(frame);
+                    self.peak = 0;
+                    self.last_time = None;
+                    events.push(RenderEvent::FrameStarted{ frame });
+                }
+            }
+            let frame = self.frame.unwrap_or_default();
+
+            if let Some(captures) = self.peak_re.captures(line){
+                if let Some(peak) = parse_memory(&captures[1], &captures[2]){
+                    if peak > self.peak{
+                        self.peak = peak;
+                        events.push(RenderEvent::Memory{ frame, peak });
+                    }
+                }
+            }
+
+            if let Some(captures) = self.time_re.captures(line){
+                if let Some(time) = parse_duration(&captures[1]){
+                    self.last_time = Some(time);
+                }
+            }
+
+            if let Some(captures) = self.progress_re.captures(line){
+                let done = captures[1].parse::<f64>().unwrap_or(0.0);
+                let total = captures[2].parse::<f64>().unwrap_or(0.0);
+                if total > 0.0{
+                    let percent = (done / total * 100.0).min(100.0);
+                    events.push(RenderEvent::Progress{ frame, percent });
+                }
+            }
+        }
+
+        if let Some(captures) = self.saved_re.captures(line){
+            self.saved = Some(captures[1].to_string());
+        }else if let Some(captures) = self.version_re.captures(line){
+            events.push(RenderEvent::Version(captures[1].to_string()));
+        }else if let Some(captures) = self.error_re.captures(line){
+            // Blender prints this on exit in debug builds, it is no render error
+            if !captures[1].starts_with("Not freed memory blocks"){
+                events.push(RenderEvent::Error(captures[1].to_string()));
+            }
+        }
+
+        events
+    }
+
+    /// Flush the events that are still pending (call this when the output ended)
+    pub fn finish(&mut self) -> Vec<RenderEvent>{
+        match self.saved.take(){
+            Some(path) => vec![RenderEvent::FrameSaved{ frame: self.frame, path, render_time: self.last_time }],
+            None => Vec::new()
+        }
+    }
+}
+
+
+
+/// Parse a blender duration like `00:04.12` (MM:SS.ss) or `01:02:04.12` \
+/// (HH:MM:SS.ss)
+pub fn parse_duration<S>(s: S) -> Option<Duration> where S: AsRef<str>{
+    let mut seconds = 0.0;
+    let parts: Vec<&str> = s.as_ref().trim().split(':').collect();
+    if parts.is_empty() || parts.len() > 3 { return None; }
+    for part in parts{
+        let value = part.parse::<f64>().ok()?;
+        if value < 0.0 { return None; }
+        seconds = seconds * 60.0 + value;
+    }
+    let millis = (seconds * 1000.0).round() as u64;
+    Some(Duration::from_millis(millis))
+}
+
+/// Parse a memory value like `310` with the unit `M` into bytes
+fn parse_memory(value: &str, unit: &str) -> Option<usize>{
+    let value = value.parse::<f64>().ok()?;
+    let factor = match unit{
+        "K" => 1024.0,
+        "M" => 1024.0 * 1024.0,
+        "G" => 1024.0 * 1024.0 * 1024.0,
+        "T" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => 1.0
+    };
+    Some((value * factor).round() as usize)
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CYCLES: &str = "Blender 2.79 (sub 0) (hash 5bd8ac9abfa built 2017-09-11 10:43:32)
+Read blend: /data/blendfiles/shot.blend
+Fra:12 Mem:12.00M (0.00M, Peak 12.00M) | Time:00:00.12 | Preparing Scene data
+Fra:12 Mem:230.00M (0.00M, Peak 310.00M) | Time:00:04.12 | Mem:0.00M, Peak:0.00M | Scene, RenderLayer | Rendering 12/64 Tiles
+Fra:12 Mem:230.00M (0.00M, Peak 310.00M) | Time:00:08.90 | Mem:0.00M, Peak:0.00M | Scene, RenderLayer | Rendered 64/64 Tiles
+Saved: '/data/frames/000012.png'
+ Time: 00:09.31 (Saving: 00:00.12)
+
+Fra:13 Mem:12.00M (0.00M, Peak 1.5G) | Time:00:00.10 | Path Tracing Tile 1/4
+Saved: '/data/frames/000013.png'
+ Time: 01:02:03.50 (Saving: 00:00.12)
+
+Blender quit";
+
+    #[test]
+    fn full_log() {
+        let events = RenderLog::parse(CYCLES);
+        assert_eq!(events[0], RenderEvent::Version("2.79 (sub 0)".to_string()));
+        assert_eq!(events[1], RenderEvent::FrameStarted{ frame: 12 });
+        assert_eq!(events[2], RenderEvent::Memory{ frame: 12, peak: 12 * 1024 * 1024 });
+        assert_eq!(events[3], RenderEvent::Memory{ frame: 12, peak: 310 * 1024 * 1024 });
+        assert_eq!(events[4], RenderEvent::Progress{ frame: 12, percent: 18.75 });
+        assert_eq!(events[5], RenderEvent::Progress{ frame: 12, percent: 100.0 });
+        assert_eq!(events[6], RenderEvent::FrameSaved{
+            frame: Some(12),
+            path: "/data/frames/000012.png".to_string(),
+            render_time: Some(Duration::from_millis(9310))
+        });
+        assert_eq!(events[7], RenderEvent::FrameStarted{ frame: 13 });
+        assert_eq!(events[8], RenderEvent::Memory{ frame: 13, peak: 1536 * 1024 * 1024 });
+        assert_eq!(events[9], RenderEvent::Progress{ frame: 13, percent: 25.0 });
+        assert_eq!(events[10], RenderEvent::FrameSaved{
+            frame: Some(13),
+            path: "/data/frames/000013.png".to_string(),
+            render_time: Some(Duration::from_millis(3723500))
+        });
+        assert_eq!(events.len(), 11);
+    }
+
+    #[test]
+    fn saved_without_time() {
+        let mut log = RenderLog::new();
+        assert!(log.parse_line("Fra:3 Mem:10M (Peak 10M) | Time:00:02.50 | Sample 5/10").len() == 3);
+        assert!(log.parse_line("Saved: \"/tmp/000003.exr\"").is_empty());
+        assert_eq!(log.finish(), vec![RenderEvent::FrameSaved{
+            frame: Some(3),
+            path: "/tmp/000003.exr".to_string(),
+            render_time: Some(Duration::from_millis(2500))
+        }]);
+        assert!(log.finish().is_empty());
+    }
+
+    #[test]
+    fn errors() {
+        let events = RenderLog::parse("Error: Cannot read file '/foo.blend': No such file or directory\nError: Not freed memory blocks: 3, total unfreed memory 0.002 MB");
+        assert_eq!(events, vec![RenderEvent::Error("Cannot read file '/foo.blend': No such file or directory".to_string())]);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("00:04.12"), Some(Duration::from_millis(4120)));
+        assert_eq!(parse_duration("01:00:00.00"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("12.5"), Some(Duration::from_millis(12500)));
+        assert_eq!(parse_duration("aa:bb"), None);
+        assert_eq!(parse_duration("1:2:3:4"), None);
+    }
+
+    #[test]
+    fn memory() {
+        assert_eq!(parse_memory("310", "M"), Some(310 * 1024 * 1024));
+        assert_eq!(parse_memory("1.5", "K"), Some(1536));
+        assert_eq!(parse_memory("x", "K"), None);
+    }
+}
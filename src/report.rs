@@ -0,0 +1,418 @@
+//! The report module collects the numbers of a Job that are spread across \
+//! [JobTime](../jobtime/struct.JobTime.html), the Tasks, the History and \
+//! the [Frames](../frames/struct.Frame.html) into a single [JobReport](struct.JobReport.html). \
+//! It can be written as JSON, as CSV with one row per frame and as human \
+//! readable text, e.g. to attach it to notifications or to store it next to \
+//! the frames.
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::{Job, JobReport};
+//! let job = Job::new("/data/blendfiles/5873c0033e78b222bec2cb2a221487cf/shot.blend", "dh@atoav.com", true);
+//! let report = JobReport::from_job(&job);
+//! assert_eq!(report.to_csv().lines().count(), 1);
+//! println!("{}", report.to_text());
+//! ```
+
+use ::*;
+use std::path::Path;
+use std::io::Write;
+use atomicwrites::{AtomicFile, AllowOverwrite};
+use csv::csv_escape;
+use estimate::{format_seconds, to_seconds};
+use history::Level;
+
+
+/// The number of slowest frames listed in a report
+pub const SLOWEST_FRAMES: usize = 5;
+
+/// The filename stem of written reports (`report.json`, `report.csv`, …)
+pub const REPORT_STEM: &str = "report";
+
+/// The columns of the CSV report
+pub const CSV_HEADER: &str = "frame,task,status,render_time,peak_memory,worker,attempt,blender_version,filesize,hash,uploaded,corrupt";
+
+
+
+
+// ===========================================================================
+//                                 FrameRow
+// ===========================================================================
+
+/// Everything known about a single frame. Times are in seconds
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FrameRow{
+    pub frame: usize,
+    pub task: String,
+    pub status: String,
+    pub render_time: Option<f64>,
+    pub peak_memory: Option<usize>,
+    pub worker: Option<String>,
+    pub attempt: Option<usize>,
+    pub blender_version: Option<String>,
+    pub filesize: Option<usize>,
+    pub hash: Option<String>,
+    pub uploaded: bool,
+    pub corrupt: Option<String>
+}
+
+impl FrameRow{
+    /// Return the row as a line of CSV (see `CSV_HEADER`)
+    pub fn to_csv(&self) -> String{
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        let fields = vec![
+            self.frame.to_string(),
+            self.task.clone(),
+            self.status.clone(),
+            optional(self.render_time.map(|t| format!("{:.3}", t))),
+            optional(self.peak_memory.map(|m| m.to_string())),
+            optional(self.worker.clone()),
+            optional(self.attempt.map(|a| a.to_string())),
+            optional(self.blender_version.clone()),
+            optional(self.filesize.map(|s| s.to_string())),
+            optional(self.hash.clone()),
+            self.uploaded.to_string(),
+            optional(self.corrupt.clone())
+        ];
+        fields.iter().map(|field| csv_escape(field)).collect::<Vec<String>>().join(",")
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                 JobReport
+// ===========================================================================
+
+/// A summary of a Job. Times are in seconds, memory and sizes in bytes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JobReport{
+    pub id: String,
+    pub blendfile: String,
+    pub status: String,
+    pub renderer: String,
+    pub device: String,
+    pub resolution: String,
+    pub image_format: String,
+    pub created: Option<DateTime<Utc>>,
+    pub queued: Option<DateTime<Utc>>,
+    pub started: Option<DateTime<Utc>>,
+    pub finished: Option<DateTime<Utc>>,
+    /// Time from start to finish (or now)
+    pub wall_time: Option<f64>,
+    /// Time between queueing and start (or now)
+    pub wait_time: Option<f64>,
+    /// Sum of the render times of all frames (or of the durations of the \
+    /// Tasks where frames have no render time)
+    pub cpu_time: f64,
+    pub mean_frame_time: Option<f64>,
+    /// The slowest frames with their render time, slowest first
+    pub slowest: Vec<(usize, f64)>,
+    /// The frame with the highest peak memory and its peak memory
+    pub peak_memory: Option<(usize, usize)>,
+    pub frames_total: usize,
+    pub frames_rendered: usize,
+    pub tasks_total: usize,
+    pub tasks_finished: usize,
+    pub tasks_errored: usize,
+    pub tasks_aborted: usize,
+    /// Additional render attempts over all frames
+    pub retries: usize,
+    pub corrupt: Vec<usize>,
+    /// The total size of all frames with a known filesize
+    pub total_size: usize,
+    /// Error messages from the history of the Job
+    pub errors: Vec<String>,
+    pub frames: Vec<FrameRow>
+}
+
+impl JobReport{
+    /// Collect the report of a Job
+    pub fn from_job(job: &Job) -> Self{
+        let mut report = JobReport{
+            id: job.id.clone(),
+            blendfile: job.paths.filename.clone(),
+            status: job.status.to_string(),
+            renderer: job.render.renderer.clone(),
+            device: job.render.device.clone(),
+            resolution: format!("{}x{}", job.resolution.scaled_x(), job.resolution.scaled_y()),
+            image_format: job.render.image_format.clone(),
+            created: job.time.creation,
+            queued: job.time.queued,
+            started: job.time.start,
+            finished: job.time.finish,
+            wall_time: job.time.duration().map(to_seconds),
+            wait_time: job.time.waiting_for().map(to_seconds),
+            cpu_time: 0.0,
+            mean_frame_time: None,
+            slowest: Vec::new(),
+            peak_memory: None,
+            frames_total: 0,
+            frames_rendered: 0,
+            tasks_total: 0,
+            tasks_finished: 0,
+            tasks_errored: 0,
+            tasks_aborted: 0,
+            retries: 0,
+            corrupt: Vec::new(),
+            total_size: 0,
+            errors: job.history.iter().filter(|event| event.level == Level::Error).map(|event| event.text()).collect(),
+            frames: Vec::new()
+        };
+
+        let mut render_times = Vec::new();
+        for task in job.tasks.iter(){
+            let command = match task.command{
+                Command::Blender(ref command) => command,
+                _ => continue
+            };
+            report.tasks_total += 1;
+            if task.is_finished() { report.tasks_finished += 1; }
+            if task.is_errored() { report.tasks_errored += 1; }
+            if task.is_aborted() { report.tasks_aborted += 1; }
+
+            let known: Vec<f64> = command.frame.values()
+                                         .filter_map(|frame| frame.get_render_time())
+                                         .map(|t| t.as_secs_f64())
+                                         .collect();
+            report.cpu_time += match known.is_empty(){
+                true => task.time.duration().filter(|_| task.is_ended()).map(to_seconds).unwrap_or(0.0),
+                false => known.iter().sum()
+            };
+
+            for (framenumber, frame) in command.frame.iter(){
+                let render_time = frame.get_render_time().map(|t| t.as_secs_f64());
+                if let Some(t) = render_time{
+                    render_times.push((*framenumber, t));
+                }
+                if let Some(memory) = frame.get_peak_memory(){
+                    let highest = match report.peak_memory{
+                        Some((_, peak)) => memory > peak,
+                        None => true
+                    };
+                    if highest{
+                        report.peak_memory = Some((*framenumber, memory));
+                    }
+                }
+                report.retries += frame.get_attempt().unwrap_or(1).saturating_sub(1);
+                if frame.is_corrupt(){
+                    report.corrupt.push(*framenumber);
+                }
+                report.total_size += frame.get_filesize().unwrap_or(0);
+                report.frames_total += 1;
+                if task.is_finished() || render_time.is_some(){
+                    report.frames_rendered += 1;
+                }
+                report.frames.push(FrameRow{
+                    frame: *framenumber,
+                    task: task.id.clone(),
+                    status: format!("{:?}", task.status),
+                    render_time,
+                    peak_memory: frame.get_peak_memory(),
+                    worker: frame.get_worker(),
+                    attempt: frame.get_attempt(),
+                    blender_version: frame.get_blender_version(),
+                    filesize: frame.get_filesize(),
+                    hash: frame.get_hash(),
+                    uploaded: frame.is_uploaded(),
+                    corrupt: frame.get_corrupt()
+                });
+            }
+        }
+        report.frames.sort_by_key(|row| row.frame);
+        report.corrupt.sort();
+        if !render_times.is_empty(){
+            report.mean_frame_time = Some(render_times.iter().map(|(_, t)| t).sum::<f64>() / render_times.len() as f64);
+        }
+        render_times.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then(a.0.cmp(&b.0)));
+        render_times.truncate(SLOWEST_FRAMES);
+        report.slowest = render_times;
+        report
+    }
+
+    /// Serialize the report to (pretty) JSON
+    pub fn to_json(&self) -> GenResult<String>{
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Return the frames as CSV with a header line and one row per frame
+    pub fn to_csv(&self) -> String{
+        let mut csv = String::from(CSV_HEADER);
+        csv.push('\n');
+        for row in self.frames.iter(){
+            csv.push_str(&row.to_csv());
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Return a short human readable summary
+    pub fn to_text(&self) -> String{
+        let optional = |value: Option<f64>| value.map(format_seconds).unwrap_or_else(|| "-".to_string());
+        let mut text = String::new();
+        text.push_str(&format!("Job:          {}\n", self.id));
+        text.push_str(&format!("Blendfile:    {}\n", self.blendfile));
+        text.push_str(&format!("Status:       {}\n", self.status));
+        text.push_str(&format!("Renderer:     {} ({})\n", self.renderer, self.device));
+        text.push_str(&format!("Resolution:   {}\n", self.resolution));
+        text.push_str(&format!("Format:       {}\n", self.image_format));
+        text.push_str(&format!("Frames:       {} of {} rendered\n", self.frames_rendered, self.frames_total));
+        text.push_str(&format!("Tasks:        {} finished, {} errored, {} aborted (of {})\n",
+            self.tasks_finished, self.tasks_errored, self.tasks_aborted, self.tasks_total));
+        text.push_str(&format!("Waited:       {}\n", optional(self.wait_time)));
+        text.push_str(&format!("Wall time:    {}\n", optional(self.wall_time)));
+        text.push_str(&format!("CPU time:     {}\n", format_seconds(self.cpu_time)));
+        text.push_str(&format!("Per frame:    {}\n", optional(self.mean_frame_time)));
+        if !self.slowest.is_empty(){
+            let slowest: Vec<String> = self.slowest.iter().map(|(f, t)| format!("{} ({})", f, format_seconds(*t))).collect();
+            text.push_str(&format!("Slowest:      {}\n", slowest.join(", ")));
+        }
+        if let Some((frame, memory)) = self.peak_memory{
+            text.push_str(&format!("Peak memory:  {:.1} MB (frame {})\n", memory as f64 / 1_000_000.0, frame));
+        }
+        text.push_str(&format!("Size:         {:.1} MB\n", self.total_size as f64 / 1_000_000.0));
+        if self.retries > 0{
+            text.push_str(&format!("Retries:      {}\n", self.retries));
+        }
+        if !self.corrupt.is_empty(){
+            text.push_str(&format!("Corrupt:      {:?}\n", self.corrupt));
+        }
+        if !self.errors.is_empty(){
+            text.push_str(&format!("Errors:       {}\n", self.errors.len()));
+            for error in self.errors.iter(){
+                text.push_str(&format!("  - {}\n", error));
+            }
+        }
+        text
+    }
+
+    /// Write `report.json`, `report.csv` and `report.txt` atomically into \
+    /// the directory
+    pub fn write_to<P>(&self, directory: P) -> GenResult<()> where P: AsRef<Path>{
+        let directory = directory.as_ref();
+        fs::create_dir_all(directory)?;
+        let outputs = vec![("json", self.to_json()?), ("csv", self.to_csv()), ("txt", self.to_text())];
+        for (extension, content) in outputs{
+            let path = directory.join(format!("{}.{}", REPORT_STEM, extension));
+            AtomicFile::new(path, AllowOverwrite).write(|f| f.write_all(content.as_bytes()))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for JobReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_text())
+    }
+}
+
+
+impl Job{
+    /// Collect the [JobReport](report/struct.JobReport.html) of the Job
+    pub fn report(&self) -> JobReport{
+        JobReport::from_job(self)
+    }
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use common::tempfile::TempDir;
+    use common::{JobBuilder, TEST_JOB_ID};
+
+    fn job() -> Job{
+        let mut job = JobBuilder::new().with_renderer("CYCLES")
+                                       .with_device("GPU")
+                                       .with_image_format("PNG")
+                                       .with_resolution(1920, 1080, 50)
+                                       .build();
+        let now = Utc::now();
+        job.time.queued = Some(now - chrono::Duration::seconds(600));
+        job.time.start = Some(now - chrono::Duration::seconds(480));
+        job.time.finish = Some(now);
+        for (frame, seconds, attempt) in [(1, 40, 1), (2, 90, 3), (3, 60, 1)].iter().cloned(){
+            let mut task = Task::new_blender_single(frame, "PNG", TEST_JOB_ID);
+            if let Command::Blender(ref mut command) = task.command{
+                let f = command.frame.get_mut(&frame).unwrap();
+                f.set_render_time(Duration::from_secs(seconds));
+                f.set_attempt(attempt);
+                f.set_peak_memory(frame * 1_000_000);
+                f.set_worker("worker,1");
+                f.set_filesize(1_000_000);
+            }
+            task.queue();
+            task.start();
+            task.finish();
+            job.tasks.push_back(task);
+        }
+        let mut failed = Task::new_blender_single(4, "PNG", TEST_JOB_ID);
+        failed.queue();
+        failed.error();
+        job.tasks.push_back(failed);
+        job.add_history("Error: blender crashed on frame 4");
+        job
+    }
+
+    #[test]
+    fn collect() {
+        let report = JobReport::from_job(&job());
+        assert_eq!(report.resolution, "960x540");
+        assert_eq!(report.wait_time, Some(120.0));
+        assert_eq!(report.wall_time, Some(480.0));
+        assert_eq!(report.cpu_time, 190.0);
+        assert_eq!(report.slowest, vec![(2, 90.0), (3, 60.0), (1, 40.0)]);
+        assert_eq!(report.peak_memory, Some((3, 3_000_000)));
+        assert!((report.mean_frame_time.unwrap() - 63.333).abs() < 0.001);
+        assert_eq!((report.frames_total, report.frames_rendered), (4, 3));
+        assert_eq!((report.tasks_total, report.tasks_finished, report.tasks_errored), (4, 3, 1));
+        assert_eq!(report.retries, 2);
+        assert_eq!(report.total_size, 3_000_000);
+        assert_eq!(report.errors, vec!["Error: blender crashed on frame 4"]);
+    }
+
+    #[test]
+    fn outputs() {
+        let report = JobReport::from_job(&job());
+        let csv = report.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[2].starts_with("2,"));
+        assert!(lines[2].contains(",90.000,2000000,\"worker,1\",3,,1000000,,false,"));
+        assert!(lines[4].starts_with("4,") && lines[4].contains(",Errored,"));
+
+        let text = report.to_text();
+        assert!(text.contains("Waited:       2m 00s\n"));
+        assert!(text.contains("Slowest:      2 (1m 30s), 3 (1m 00s), 1 (40s)\n"));
+        assert!(text.contains("  - Error: blender crashed on frame 4\n"));
+
+        let deserialized: JobReport = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(deserialized, report);
+
+        let dir = TempDir::new().unwrap();
+        report.write_to(dir.path()).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("report.csv")).unwrap(), csv);
+        assert!(dir.path().join("report.json").exists());
+        assert!(dir.path().join("report.txt").exists());
+    }
+
+    #[test]
+    fn waiting_for() {
+        let mut job = job();
+        job.time.start = None;
+        assert!(job.time.waiting_for_seconds().unwrap() >= 600);
+        job.time.queued = None;
+        assert_eq!(job.time.waiting_for(), None);
+    }
+}
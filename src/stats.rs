@@ -0,0 +1,519 @@
+//! The stats module aggregates farm wide statistics over many Jobs: the number \
+//! of Jobs per Status, the median time Jobs waited in the queue, the frames \
+//! and pixels rendered per day, the error rate of each renderer and the users \
+//! with the most Jobs. All statistics are restricted to a [StatsWindow](struct.StatsWindow.html).
+//!
+//! [FarmStats](struct.FarmStats.html) only keeps a small [JobSummary](struct.JobSummary.html) \
+//! per Job, so Jobs can be added, replaced and removed one by one and the \
+//! window can be changed without reading any Job again. `refresh()` rereads \
+//! only the Jobs whose `data.json` changed since the last call:
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::{FarmStats, StatsWindow};
+//! let mut stats = FarmStats::new(StatsWindow::last_days(7));
+//! stats.refresh("/data/blendfiles").ok();
+//! println!("{}", stats);
+//! ```
+
+use ::*;
+use std::path::Path;
+use std::time::SystemTime;
+
+
+
+
+// ===========================================================================
+//                               StatsWindow
+// ===========================================================================
+
+/// A span of time the statistics are restricted to. Both ends are optional
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct StatsWindow{
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>
+}
+
+impl StatsWindow{
+    /// A window without bounds
+    pub fn all() -> Self{
+        Self::default()
+    }
+
+    /// A window from the given time until now (and beyond)
+    pub fn since(start: DateTime<Utc>) -> Self{
+        StatsWindow{ start: Some(start), end: None }
+    }
+
+    /// A window covering the given number of days until now
+    pub fn last_days(days: i64) -> Self{
+        Self::since(Utc::now() - chrono::Duration::days(days))
+    }
+
+    /// A window between two times (including start, excluding end)
+    pub fn between(start: DateTime<Utc>, end: DateTime<Utc>) -> Self{
+        StatsWindow{ start: Some(start), end: Some(end) }
+    }
+
+    /// Return true if the window has neither start nor end
+    pub fn is_unbounded(&self) -> bool{
+        self.start.is_none() && self.end.is_none()
+    }
+
+    /// Return true if the time lies within the window
+    pub fn contains(&self, time: &DateTime<Utc>) -> bool{
+        let after_start = match self.start{
+            Some(start) => *time >= start,
+            None => true
+        };
+        let before_end = match self.end{
+            Some(end) => *time < end,
+            None => true
+        };
+        after_start && before_end
+    }
+
+    /// Like `contains()`, but unknown times only lie within unbounded windows
+    pub fn contains_option(&self, time: &Option<DateTime<Utc>>) -> bool{
+        match time{
+            Some(time) => self.contains(time),
+            None => self.is_unbounded()
+        }
+    }
+}
+
+impl fmt::Display for StatsWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format = |time: Option<DateTime<Utc>>, default: &str| match time{
+            Some(time) => time.format("%Y-%m-%d %H:%M").to_string(),
+            None => default.to_string()
+        };
+        write!(f, "{} – {}", format(self.start, "beginning"), format(self.end, "now"))
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                JobSummary
+// ===========================================================================
+
+/// The parts of a Job the statistics are computed from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JobSummary{
+    pub id: String,
+    pub user: String,
+    pub status: String,
+    pub renderer: String,
+    pub created: Option<DateTime<Utc>>,
+    pub queued: Option<DateTime<Utc>>,
+    pub started: Option<DateTime<Utc>>,
+    /// Scaled width × height of a single frame
+    pub pixels_per_frame: usize,
+    /// Time of finishing and number of frames of every finished Task
+    pub finished: Vec<(DateTime<Utc>, usize)>,
+    /// Times of every errored Task
+    pub errored: Vec<DateTime<Utc>>
+}
+
+impl JobSummary{
+    /// Summarize a Job
+    pub fn from_job(job: &Job) -> Self{
+        let mut summary = JobSummary{
+            id: job.id.clone(),
+            user: job.email.clone(),
+            status: job.status.to_string(),
+            renderer: job.render.renderer.clone(),
+            created: job.time.creation,
+            queued: job.time.queued,
+            started: job.time.start,
+            pixels_per_frame: job.resolution.scaled_x() * job.resolution.scaled_y(),
+            finished: Vec::new(),
+            errored: Vec::new()
+        };
+        for task in job.tasks.iter(){
+            let frames = match task.command{
+                Command::Blender(ref command) => command.frame.len(),
+                _ => continue
+            };
+            if task.is_finished(){
+                if let Some(finish) = task.time.finish{
+                    summary.finished.push((finish, frames));
+                }
+            }
+            if task.is_errored(){
+                if let Some(error) = task.time.error{
+                    summary.errored.push(error);
+                }
+            }
+        }
+        summary
+    }
+
+    /// The time the Job waited between queueing and starting (only for \
+    /// Jobs that started already)
+    pub fn wait(&self) -> Option<chrono::Duration>{
+        match (self.queued, self.started){
+            (Some(queued), Some(started)) => Some(started - queued),
+            _ => None
+        }
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                Aggregates
+// ===========================================================================
+
+/// Frames and pixels rendered within a period
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Throughput{
+    pub frames: usize,
+    pub pixels: usize
+}
+
+impl Throughput{
+    /// The rendered pixels in megapixels
+    pub fn megapixels(&self) -> f64{
+        self.pixels as f64 / 1_000_000.0
+    }
+}
+
+
+/// The number of ended and errored Tasks of a renderer
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct ErrorRate{
+    pub tasks: usize,
+    pub errored: usize
+}
+
+impl ErrorRate{
+    /// The share of errored Tasks (0.0 to 1.0), None if no Task ended
+    pub fn rate(&self) -> Option<f64>{
+        match self.tasks{
+            0 => None,
+            tasks => Some(self.errored as f64 / tasks as f64)
+        }
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                FarmStats
+// ===========================================================================
+
+/// Statistics over many Jobs. Jobs are counted for status, wait and users if \
+/// they were created within the window, frames and errors if the Task \
+/// finished or errored within the window
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FarmStats{
+    pub window: StatsWindow,
+    pub jobs: BTreeMap<String, JobSummary>,
+    /// Modification time and id of the `data.json` of every Job directory \
+    /// read by `refresh()`
+    #[serde(skip)]
+    modified: HashMap<PathBuf, (SystemTime, String)>
+}
+
+impl FarmStats{
+    /// Create empty statistics for the window
+    pub fn new(window: StatsWindow) -> Self{
+        FarmStats{ window, ..Default::default() }
+    }
+
+    /// Create statistics for the window from Jobs (e.g. from `read_all()`)
+    pub fn from_jobs<'a, I>(window: StatsWindow, jobs: I) -> Self where I: IntoIterator<Item=&'a Job>{
+        let mut stats = Self::new(window);
+        jobs.into_iter().for_each(|job| stats.add_job(job));
+        stats
+    }
+
+    /// Create statistics for the window from all Jobs in the directory
+    pub fn from_directory<P>(window: StatsWindow, directory: P) -> GenResult<Self> where P: AsRef<Path>{
+        let mut stats = Self::new(window);
+        stats.refresh(directory)?;
+        Ok(stats)
+    }
+
+    /// Add a Job or replace the previous summary of a Job with the same id
+    pub fn add_job(&mut self, job: &Job){
+        self.jobs.insert(job.id.clone(), JobSummary::from_job(job));
+    }
+
+    /// Remove a Job, return true if it was known
+    pub fn remove_job(&mut self, id: &str) -> bool{
+        self.modified.retain(|_, (_, known)| known != id);
+        self.jobs.remove(id).is_some()
+    }
+
+    /// Change the window. This is cheap as no Job has to be read again
+    pub fn set_window(&mut self, window: StatsWindow){
+        self.window = window;
+    }
+
+    /// Read all Jobs in the directory whose `data.json` changed since the \
+    /// last refresh and forget about Jobs whose directory is gone. Return \
+    /// the number of Jobs that were read
+    pub fn refresh<P>(&mut self, directory: P) -> GenResult<usize> where P: AsRef<Path>{
+        let directory = directory.as_ref();
+        let mut seen = Vec::new();
+        let mut read = 0;
+        for entry in fs::read_dir(directory)?{
+            let path = entry?.path();
+            let modified = match fs::metadata(path.join("data.json")).and_then(|m| m.modified()){
+                Ok(modified) => modified,
+                Err(_) => continue
+            };
+            seen.push(path.clone());
+            if self.modified.get(&path).map(|(known, _)| *known) == Some(modified){
+                continue;
+            }
+            match Job::from_directory(&path){
+                Ok(job) => {
+                    if let Some((_, previous)) = self.modified.get(&path){
+                        if *previous != job.id { self.jobs.remove(previous); }
+                    }
+                    self.modified.insert(path, (modified, job.id.clone()));
+                    self.add_job(&job);
+                    read += 1;
+                },
+                Err(err) => eprintln!("Error: FarmStats::refresh({}) couldn't deserialize Job from {}: {}",
+                    directory.to_string_lossy(), path.to_string_lossy(), err)
+            }
+        }
+        let gone: Vec<String> = self.modified.iter()
+                                    .filter(|(path, _)| !seen.contains(path))
+                                    .map(|(_, (_, id))| id.clone())
+                                    .collect();
+        for id in gone{
+            self.remove_job(&id);
+        }
+        Ok(read)
+    }
+
+    /// The summaries of all Jobs created within the window
+    pub fn jobs_in_window(&self) -> impl Iterator<Item=&JobSummary>{
+        let window = self.window;
+        self.jobs.values().filter(move |job| window.contains_option(&job.created))
+    }
+
+    /// The number of Jobs per Status (e.g. `job.finished`)
+    pub fn status_counts(&self) -> BTreeMap<String, usize>{
+        let mut counts = BTreeMap::new();
+        for job in self.jobs_in_window(){
+            *counts.entry(job.status.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// The median time the Jobs waited in the queue before they started
+    pub fn median_wait(&self) -> Option<chrono::Duration>{
+        let mut waits: Vec<chrono::Duration> = self.jobs_in_window().filter_map(|job| job.wait()).collect();
+        if waits.is_empty(){
+            return None;
+        }
+        waits.sort();
+        let middle = waits.len() / 2;
+        match waits.len() % 2{
+            0 => Some((waits[middle - 1] + waits[middle]) / 2),
+            _ => Some(waits[middle])
+        }
+    }
+
+    /// The frames and pixels of the Tasks that finished within the window, \
+    /// per day (UTC)
+    pub fn daily(&self) -> BTreeMap<NaiveDate, Throughput>{
+        let mut days: BTreeMap<NaiveDate, Throughput> = BTreeMap::new();
+        for job in self.jobs.values(){
+            for (finish, frames) in job.finished.iter().filter(|(finish, _)| self.window.contains(finish)){
+                let day = days.entry(finish.naive_utc().date()).or_default();
+                day.frames += frames;
+                day.pixels += frames * job.pixels_per_frame;
+            }
+        }
+        days
+    }
+
+    /// The frames and pixels of all Tasks that finished within the window
+    pub fn throughput(&self) -> Throughput{
+        self.daily().values().fold(Throughput::default(), |sum, day| {
+            Throughput{ frames: sum.frames + day.frames, pixels: sum.pixels + day.pixels }
+        })
+    }
+
+    /// The number of finished and errored Tasks within the window per \
+    /// renderer
+    pub fn error_rates(&self) -> BTreeMap<String, ErrorRate>{
+        let mut rates: BTreeMap<String, ErrorRate> = BTreeMap::new();
+        for job in self.jobs.values(){
+            let finished = job.finished.iter().filter(|(finish, _)| self.window.contains(finish)).count();
+            let errored = job.errored.iter().filter(|error| self.window.contains(error)).count();
+            if finished + errored == 0{
+                continue;
+            }
+            let renderer = match job.renderer.as_str(){
+                "" => "unknown".to_string(),
+                renderer => renderer.to_string()
+            };
+            let rate = rates.entry(renderer).or_default();
+            rate.tasks += finished + errored;
+            rate.errored += errored;
+        }
+        rates
+    }
+
+    /// The users with the most Jobs created within the window, most first
+    pub fn top_users(&self, n: usize) -> Vec<(String, usize)>{
+        let mut users: HashMap<&str, usize> = HashMap::new();
+        for job in self.jobs_in_window(){
+            *users.entry(job.user.as_str()).or_insert(0) += 1;
+        }
+        let mut users: Vec<(String, usize)> = users.into_iter().map(|(user, jobs)| (user.to_string(), jobs)).collect();
+        users.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        users.truncate(n);
+        users
+    }
+
+    /// Serialize the statistics (including the Job summaries) to JSON
+    pub fn to_json(&self) -> GenResult<String>{
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl fmt::Display for FarmStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Window:       {}", self.window)?;
+        writeln!(f, "Jobs:         {}", self.jobs_in_window().count())?;
+        for (status, count) in self.status_counts(){
+            writeln!(f, "  {:<12}{}", status, count)?;
+        }
+        match self.median_wait(){
+            Some(wait) => writeln!(f, "Median wait:  {}s", wait.num_seconds())?,
+            None => writeln!(f, "Median wait:  -")?
+        }
+        let throughput = self.throughput();
+        writeln!(f, "Rendered:     {} frames, {:.1} megapixels", throughput.frames, throughput.megapixels())?;
+        for (renderer, rate) in self.error_rates(){
+            writeln!(f, "Errors:       {} {}/{} tasks ({:.1}%)", renderer, rate.errored, rate.tasks, rate.rate().unwrap_or(0.0) * 100.0)?;
+        }
+        for (user, jobs) in self.top_users(5){
+            writeln!(f, "User:         {} ({} jobs)", user, jobs)?;
+        }
+        Ok(())
+    }
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::tempfile::TempDir;
+    use common::{JobBuilder, TEST_JOB_ID};
+
+    fn task(frame: usize, finish: DateTime<Utc>, errored: bool) -> Task{
+        let mut task = Task::new_blender_single(frame, "PNG", TEST_JOB_ID);
+        task.queue();
+        task.start();
+        match errored{
+            true => { task.error(); task.time.error = Some(finish); },
+            false => { task.finish(); task.time.finish = Some(finish); }
+        }
+        task
+    }
+
+    fn jobs(directory: &Path) -> Vec<Job>{
+        let job = |id: &str, email: &str, renderer: &str|{
+            JobBuilder::new().in_directory(directory)
+                             .with_id(id)
+                             .with_email(email)
+                             .with_renderer(renderer)
+                             .with_resolution(1000, 1000, 100)
+                             .build()
+        };
+        let day: DateTime<Utc> = "2026-10-01T12:00:00Z".parse().unwrap();
+        let mut a = job("a", "anna@example.com", "CYCLES");
+        a.time.queued = Some(day);
+        a.time.start = Some(day + chrono::Duration::seconds(60));
+        a.tasks.push_back(task(1, day, false));
+        a.tasks.push_back(task(2, day + chrono::Duration::days(1), false));
+        a.tasks.push_back(task(3, day + chrono::Duration::days(1), true));
+
+        let mut b = job("b", "bert@example.com", "BLENDER_EEVEE");
+        b.time.queued = Some(day);
+        b.time.start = Some(day + chrono::Duration::seconds(300));
+        b.tasks.push_back(task(1, day + chrono::Duration::days(1), false));
+
+        let mut c = job("c", "anna@example.com", "CYCLES");
+        c.time.queued = Some(day);
+        c.time.start = Some(day + chrono::Duration::seconds(120));
+        c.tasks.push_back(task(1, day + chrono::Duration::days(5), true));
+        c.status = Status::Job(status::JobStatus::Finished);
+        vec![a, b, c]
+    }
+
+    #[test]
+    fn aggregate() {
+        let dir = TempDir::new().unwrap();
+        let stats = FarmStats::from_jobs(StatsWindow::all(), jobs(dir.path()).iter());
+        let counts = stats.status_counts();
+        assert_eq!(counts.values().sum::<usize>(), 3);
+        assert_eq!(counts.get("job.finished"), Some(&1));
+        assert_eq!(stats.median_wait(), Some(chrono::Duration::seconds(120)));
+
+        let daily = stats.daily();
+        assert_eq!(daily.len(), 2);
+        assert_eq!(daily[&"2026-10-02".parse::<NaiveDate>().unwrap()], Throughput{ frames: 2, pixels: 2_000_000 });
+        assert_eq!(stats.throughput().frames, 3);
+
+        let rates = stats.error_rates();
+        assert_eq!(rates["CYCLES"], ErrorRate{ tasks: 4, errored: 2 });
+        assert_eq!(rates["CYCLES"].rate(), Some(0.5));
+        assert_eq!(rates["BLENDER_EEVEE"].rate(), Some(0.0));
+
+        assert_eq!(stats.top_users(1), vec![("anna@example.com".to_string(), 2)]);
+        assert!(stats.to_string().contains("Rendered:     3 frames, 3.0 megapixels"));
+    }
+
+    #[test]
+    fn window() {
+        let dir = TempDir::new().unwrap();
+        let mut stats = FarmStats::from_jobs(StatsWindow::all(), jobs(dir.path()).iter());
+        let start: DateTime<Utc> = "2026-10-02T00:00:00Z".parse().unwrap();
+        stats.set_window(StatsWindow::between(start, start + chrono::Duration::days(1)));
+        assert_eq!(stats.throughput(), Throughput{ frames: 2, pixels: 2_000_000 });
+        assert_eq!(stats.error_rates()["CYCLES"], ErrorRate{ tasks: 2, errored: 1 });
+        // Jobs were created now, which is outside of the window
+        assert!(stats.status_counts().is_empty());
+        assert_eq!(stats.median_wait(), None);
+    }
+
+    #[test]
+    fn refresh() {
+        let dir = TempDir::new().unwrap();
+        for job in jobs(dir.path()){
+            fs::create_dir_all(dir.path().join(&job.id)).unwrap();
+            job.write_to_file().unwrap();
+        }
+        let mut stats = FarmStats::from_directory(StatsWindow::all(), dir.path()).unwrap();
+        assert_eq!(stats.jobs.len(), 3);
+        assert_eq!(stats.refresh(dir.path()).unwrap(), 0);
+
+        fs::remove_dir_all(dir.path().join("b")).unwrap();
+        assert_eq!(stats.refresh(dir.path()).unwrap(), 0);
+        assert_eq!(stats.jobs.len(), 2);
+        assert_eq!(stats.top_users(5), vec![("anna@example.com".to_string(), 2)]);
+    }
+}
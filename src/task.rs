@@ -5,6 +5,7 @@ use std::hash::{Hash, Hasher};
 use std::collections::HashMap;
 use chrono::Duration;
 use common::random_id;
+use history::Event;
 
 
 // ===========================================================================
@@ -61,7 +62,11 @@ pub struct Task{
     pub time: JobTime,
     pub command: Command,
     pub data: HashMap<String, String>,
-    pub parent_id: String
+    pub parent_id: String,
+    /// Messages logged while the Task was processed (e.g. by the worker). \
+    /// These are merged into the timeline of the parent Job
+    #[serde(default)]
+    pub history: History
 }
 
 impl Hash for Task {
@@ -93,7 +98,8 @@ impl Task{
             time: JobTime::new(),
             command: Command::new(command.into()),
             parent_id: parent_id.into(),
-            data: HashMap::new()
+            data: HashMap::new(),
+            history: History::new()
         }
     }
 
@@ -111,7 +117,8 @@ impl Task{
             time: JobTime::new(),
             command: Command::new_blender_single(frame, image_format.into()),
             parent_id: id.into(),
-            data: HashMap::new()
+            data: HashMap::new(),
+            history: History::new()
         }
     }
 
@@ -129,7 +136,8 @@ impl Task{
             time: JobTime::new(),
             command: Command::new_blender_range(start, end, step, image_format.into()),
             parent_id: id.into(),
-            data: HashMap::new()
+            data: HashMap::new(),
+            history: History::new()
         }
     }
 
@@ -167,6 +175,23 @@ impl Task{
         self.data.insert(key.into(), value.into());
     }
 
+    /// Verify the rendered frames of a blender Task (see \
+    /// `BlenderCommand::verify_frames()`). If any frame is corrupt, its \
+    /// framenumbers are stored in `data["corrupt_frames"]` and a Task that \
+    /// has run is errored (see `reject()`), so it can be rendered again. \
+    /// Return the framenumbers of the corrupt frames.
+    pub fn verify_frames(&mut self, resolution: Option<&Resolution>) -> GenResult<Vec<usize>>{
+        let corrupt = self.command.verify_frames(resolution)?;
+        if corrupt.is_empty(){
+            self.data.remove("corrupt_frames");
+        }else{
+            let numbers: Vec<String> = corrupt.iter().map(|f| f.to_string()).collect();
+            self.add_data("corrupt_frames".to_string(), numbers.join(","));
+            self.reject();
+        }
+        Ok(corrupt)
+    }
+
     /// Convert the command to string. This returns an Error when the command is a variant \
     /// that needs construction first (see explaination for construct method).
     ///
@@ -239,6 +264,7 @@ impl Task{
             self.time.merge(&other.time);
             self.command.merge(&other.command);
             self.merge_data(&other);
+            self.history.append(&mut other.history.clone());
         }else{
             eprintln!("Error: you tried to merge two Tasks with differing ids or parent_ids");      
         }
@@ -248,6 +274,16 @@ impl Task{
     pub fn merge_data(&mut self, other: &Self){
         self.data.extend(other.data.clone());
     }
+
+    /// Add a message to the Tasks history (using the current time)
+    pub fn add_history<S>(&mut self, value: S) where S: Into<String>{
+        self.history.insert(Utc::now(), value);
+    }
+
+    /// Add an Event to the Tasks history
+    pub fn add_event(&mut self, event: Event){
+        self.history.push(event);
+    }
 }
 
 
@@ -321,6 +357,18 @@ impl Task{
         } 
     }
 
+    /// Error the task because its output turned out to be unusable (only if \
+    /// it is running or has finished) and log the time of this call
+    pub fn reject(&mut self){
+        match self.status{
+            Status::Running|Status::Finished => {
+                self.time.error();
+                self.status = Status::Errored;
+            },
+            _ => ()
+        }
+    }
+
     /// Abort the task (only if it is either running, waiting, queued or paused)
     /// and log the time of this call
     pub fn abort(&mut self){
@@ -1077,6 +1125,30 @@ impl TaskQueue for Tasks{
 mod test {
     use super::*;
 
+    #[test]
+    fn verify_frames_errors_finished_task() {
+        use common::tempfile::TempDir;
+        let dir = TempDir::new().unwrap();
+        let mut t = Task::new_blender_single(1, "PNG", "a");
+        t.construct("shot.blend".to_string(), dir.path().to_string_lossy().to_string());
+        t.queue();
+        t.start();
+        t.finish();
+        assert_eq!(t.verify_frames(None).unwrap(), vec![1]);
+        assert!(t.is_errored());
+        assert_eq!(t.data.get("corrupt_frames"), Some(&"1".to_string()));
+        assert!(Task::new_basic("ls", "a").verify_frames(None).is_err());
+
+        // Tasks that haven't run are not errored
+        let mut waiting = Task::new_blender_single(1, "PNG", "a");
+        waiting.construct("shot.blend".to_string(), dir.path().to_string_lossy().to_string());
+        assert_eq!(waiting.verify_frames(None).unwrap(), vec![1]);
+        assert!(waiting.is_waiting());
+        waiting.abort();
+        waiting.verify_frames(None).unwrap();
+        assert!(waiting.is_aborted());
+    }
+
     #[test]
     fn tasks_length() {
         let mut a = Tasks::new();
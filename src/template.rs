@@ -0,0 +1,355 @@
+//! The template module defines the [OutputTemplate](struct.OutputTemplate.html), \
+//! which describes how rendered frames are named. A template is a filename \
+//! without extension, that may contain these placeholders:
+//! - `{blend}` the name of the blendfile without `.blend`
+//! - `{scene}` the name of the rendered scene
+//! - `{camera}` the name of the active camera
+//! - `{job}` the id of the job
+//! - `{frame}` or `{frame:N}` the framenumber, padded with zeros to N digits \
+//!   (6 if no padding is given). Every template needs exactly one of these.
+//!
+//! The default template `{frame:6}` names frames like `000042.png`, the \
+//! template `{blend}_{camera}_{frame:4}` names them like `shot010_cam2_0042.png`.
+//! The same template is used for blender's `-o` argument (where the frame \
+//! becomes `####`) and for the paths where the rendered frames are expected.
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::template::{OutputTemplate, TemplateContext};
+//! let template = OutputTemplate::new("{blend}_{camera}_{frame:4}").unwrap();
+//! let context = TemplateContext{
+//!     blend: Some("shot010".to_string()),
+//!     camera: Some("cam2".to_string()),
+//!     ..Default::default()
+//! };
+//! let resolved = template.resolve(&context);
+//! assert_eq!(resolved.blender_pattern(), "shot010_cam2_####");
+//! assert_eq!(resolved.file_stem(42), "shot010_cam2_0042");
+//! ```
+
+use ::*;
+use std::convert::TryFrom;
+
+
+/// The padding of `{frame}` if no padding is given
+pub const DEFAULT_PADDING: usize = 6;
+
+/// The largest allowed padding of `{frame:N}`
+pub const MAX_PADDING: usize = 12;
+
+
+// ===========================================================================
+//                              TemplateContext
+// ===========================================================================
+
+/// The values the placeholders of a OutputTemplate are replaced with. Values \
+/// that are None are left in the template when resolving.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TemplateContext{
+    pub blend: Option<String>,
+    pub scene: Option<String>,
+    pub camera: Option<String>,
+    pub job: Option<String>
+}
+
+
+
+
+// ===========================================================================
+//                              OutputTemplate
+// ===========================================================================
+
+/// A validated filename template for rendered frames. It is serialized as \
+/// the template string (and validated when deserialized)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub struct OutputTemplate(String);
+
+/// A parsed part of a template
+#[derive(Debug, Clone, PartialEq)]
+enum Segment{
+    Literal(String),
+    Blend,
+    Scene,
+    Camera,
+    Job,
+    Frame(usize)
+}
+
+impl Default for OutputTemplate{
+    fn default() -> Self{
+        OutputTemplate(format!("{{frame:{}}}", DEFAULT_PADDING))
+    }
+}
+
+impl OutputTemplate{
+    /// Create a new OutputTemplate. Return Error if the template contains \
+    /// unknown placeholders, unbalanced braces, path separators or `#`, or \
+    /// not exactly one `{frame}` placeholder
+    pub fn new<S>(template: S) -> GenResult<Self> where S: Into<String>{
+        let template = template.into();
+        let segments = parse(&template)?;
+        let frames = segments.iter().filter(|s| matches!(s, Segment::Frame(_))).count();
+        if frames != 1{
+            let message = format!("Error: The output template \"{}\" needs exactly one {{frame}} placeholder, found {}", template, frames);
+            return Err(From::from(message));
+        }
+        Ok(OutputTemplate(template))
+    }
+
+    /// Return the template string
+    pub fn as_str(&self) -> &str{
+        &self.0
+    }
+
+    /// Return true if this is the default template
+    pub fn is_default(&self) -> bool{
+        self == &Self::default()
+    }
+
+    /// Return the padding of the frame placeholder
+    pub fn frame_padding(&self) -> usize{
+        self.segments()
+            .iter()
+            .filter_map(|s| match s { Segment::Frame(padding) => Some(*padding), _ => None })
+            .next()
+            .unwrap_or(DEFAULT_PADDING)
+    }
+
+    /// Return true if no placeholders except `{frame}` are left
+    pub fn is_resolved(&self) -> bool{
+        self.segments()
+            .iter()
+            .all(|s| matches!(s, Segment::Literal(_) | Segment::Frame(_)))
+    }
+
+    /// Replace all placeholders the context has values for and return the \
+    /// new template. The values are sanitized, so they can't introduce path \
+    /// separators, `#` or braces.
+    pub fn resolve(&self, context: &TemplateContext) -> Self{
+        let resolved = self.segments()
+            .into_iter()
+            .map(|segment|{
+                let value = match segment{
+                    Segment::Blend => context.blend.as_ref(),
+                    Segment::Scene => context.scene.as_ref(),
+                    Segment::Camera => context.camera.as_ref(),
+                    Segment::Job => context.job.as_ref(),
+                    _ => None
+                };
+                match value{
+                    Some(value) => sanitize(value),
+                    None => to_template_string(&segment)
+                }
+            })
+            .collect();
+        OutputTemplate(resolved)
+    }
+
+    /// Return the pattern for blender's `-o` argument, where the frame is \
+    /// replaced by one `#` per digit. Unresolved placeholders are left empty
+    pub fn blender_pattern(&self) -> String{
+        self.render(|padding| "#".repeat(padding))
+    }
+
+    /// Return the filename (without extension) of the given frame. \
+    /// Unresolved placeholders are left empty
+    pub fn file_stem(&self, framenumber: usize) -> String{
+        self.render(|padding| format!("{:0width$}", framenumber, width = padding))
+    }
+
+    fn render<F>(&self, frame: F) -> String where F: Fn(usize) -> String{
+        self.segments()
+            .iter()
+            .map(|segment| match segment{
+                Segment::Literal(literal) => literal.clone(),
+                Segment::Frame(padding) => frame(*padding),
+                _ => String::new()
+            })
+            .collect()
+    }
+
+    fn segments(&self) -> Vec<Segment>{
+        // The template has been validated on creation
+        parse(&self.0).unwrap_or_default()
+    }
+}
+
+impl TryFrom<String> for OutputTemplate {
+    type Error = GenError;
+
+    fn try_from(template: String) -> GenResult<Self>{
+        OutputTemplate::new(template)
+    }
+}
+
+impl From<OutputTemplate> for String {
+    fn from(template: OutputTemplate) -> Self{
+        template.0
+    }
+}
+
+impl fmt::Display for OutputTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+
+
+/// Split a template into its segments
+fn parse(template: &str) -> GenResult<Vec<Segment>>{
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next(){
+        match c{
+            '{' => {
+                let mut placeholder = String::new();
+                let mut closed = false;
+                for c in chars.by_ref(){
+                    match c{
+                        '}' => { closed = true; break; },
+                        '{' => break,
+                        c => placeholder.push(c)
+                    }
+                }
+                if !closed{
+                    return Err(From::from(format!("Error: Unbalanced braces in output template \"{}\"", template)));
+                }
+                if !literal.is_empty(){
+                    segments.push(Segment::Literal(literal.clone()));
+                    literal.clear();
+                }
+                segments.push(parse_placeholder(&placeholder, template)?);
+            },
+            '}' => return Err(From::from(format!("Error: Unbalanced braces in output template \"{}\"", template))),
+            '/' | '\\' | '#' => {
+                return Err(From::from(format!("Error: The output template \"{}\" must not contain '{}'", template, c)));
+            },
+            c if c.is_control() => {
+                return Err(From::from(format!("Error: The output template \"{}\" must not contain control characters", template)));
+            },
+            c => literal.push(c)
+        }
+    }
+    if !literal.is_empty(){
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+/// Parse the inside of a `{...}` placeholder
+fn parse_placeholder(placeholder: &str, template: &str) -> GenResult<Segment>{
+    let mut parts = placeholder.splitn(2, ':');
+    let name = parts.next().unwrap_or("");
+    let argument = parts.next();
+    match (name, argument){
+        ("blend", None) => Ok(Segment::Blend),
+        ("scene", None) => Ok(Segment::Scene),
+        ("camera", None) => Ok(Segment::Camera),
+        ("job", None) => Ok(Segment::Job),
+        ("frame", None) => Ok(Segment::Frame(DEFAULT_PADDING)),
+        ("frame", Some(padding)) => {
+            match padding.parse::<usize>(){
+                Ok(padding) if (1..=MAX_PADDING).contains(&padding) => Ok(Segment::Frame(padding)),
+                _ => Err(From::from(format!("Error: Invalid frame padding \"{}\" in output template \"{}\" (allowed: 1 to {})", padding, template, MAX_PADDING)))
+            }
+        },
+        _ => Err(From::from(format!("Error: Unknown placeholder {{{}}} in output template \"{}\"", placeholder, template)))
+    }
+}
+
+/// Convert a segment back to its template representation
+fn to_template_string(segment: &Segment) -> String{
+    match segment{
+        Segment::Literal(literal) => literal.clone(),
+        Segment::Blend => "{blend}".to_string(),
+        Segment::Scene => "{scene}".to_string(),
+        Segment::Camera => "{camera}".to_string(),
+        Segment::Job => "{job}".to_string(),
+        Segment::Frame(padding) => format!("{{frame:{}}}", padding)
+    }
+}
+
+/// Replace characters that would change the meaning of a template (or of \
+/// a path)
+pub fn sanitize(value: &str) -> String{
+    value.chars()
+         .map(|c| match c{
+             '/' | '\\' | '#' | '{' | '}' => '_',
+             c if c.is_control() => '_',
+             c => c
+         })
+         .collect()
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_template() {
+        let t = OutputTemplate::default();
+        assert_eq!(t.as_str(), "{frame:6}");
+        assert_eq!(t.blender_pattern(), "######");
+        assert_eq!(t.file_stem(42), "000042");
+        assert!(t.is_resolved());
+    }
+
+    #[test]
+    fn placeholders() {
+        let t = OutputTemplate::new("{blend}_{scene}_{camera}_{job}_{frame}").unwrap();
+        assert!(!t.is_resolved());
+        let context = TemplateContext{
+            blend: Some("shot010".to_string()),
+            scene: Some("Scene".to_string()),
+            camera: Some("cam2".to_string()),
+            job: Some("5873c0033e78b222bec2cb2a221487cf".to_string())
+        };
+        let r = t.resolve(&context);
+        assert!(r.is_resolved());
+        assert_eq!(r.file_stem(7), "shot010_Scene_cam2_5873c0033e78b222bec2cb2a221487cf_000007");
+    }
+
+    #[test]
+    fn partial_resolve() {
+        let t = OutputTemplate::new("{blend}-{camera}.{frame:3}").unwrap();
+        let r = t.resolve(&TemplateContext{ blend: Some("a/b#c".to_string()), ..Default::default() });
+        assert_eq!(r.as_str(), "a_b_c-{camera}.{frame:3}");
+        assert_eq!(r.blender_pattern(), "a_b_c-.###");
+        assert_eq!(r.file_stem(1234), "a_b_c-.1234");
+        assert_eq!(OutputTemplate::new(r.as_str()).unwrap(), r);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(OutputTemplate::new("{blend}").is_err());
+        assert!(OutputTemplate::new("{frame}{frame}").is_err());
+        assert!(OutputTemplate::new("{frame:0}").is_err());
+        assert!(OutputTemplate::new("{frame:x}").is_err());
+        assert!(OutputTemplate::new("{frames}").is_err());
+        assert!(OutputTemplate::new("{frame").is_err());
+        assert!(OutputTemplate::new("frame}{frame}").is_err());
+        assert!(OutputTemplate::new("out/{frame}").is_err());
+        assert!(OutputTemplate::new("###{frame}").is_err());
+    }
+
+    #[test]
+    fn serialize() {
+        let t = OutputTemplate::new("{blend}_{frame:4}").unwrap();
+        assert_eq!(serde_json::to_string(&t).unwrap(), "\"{blend}_{frame:4}\"");
+        let d: OutputTemplate = serde_json::from_str("\"{blend}_{frame:4}\"").unwrap();
+        assert_eq!(d, t);
+        assert!(serde_json::from_str::<OutputTemplate>("\"{blend}\"").is_err());
+    }
+}
@@ -0,0 +1,439 @@
+//! The upload module posts the rendered frames of a [BlenderCommand](../command/struct.BlenderCommand.html) \
+//! to flaskbender. The [Uploader](struct.Uploader.html) runs a bounded number \
+//! of uploads in parallel and retries uploads that failed because of a \
+//! connection error or a server error (5xx) with an exponential backoff.
+//!
+//! A frame (or frame output) is only marked as uploaded once the server \
+//! answered with a success status. Frames that are already uploaded are \
+//! skipped, so a interrupted upload can simply be run again.
+//!
+//! ```no_run
+//! # extern crate bender_job;
+//! # use bender_job::Task;
+//! # use bender_job::upload::Uploader;
+//! # use std::time::Duration;
+//! let mut task = Task::new_blender_single(121, "PNG", "55067970443c49eaafdb60541fbde157");
+//! task.construct("my/blend/file.blend", "some/out/folder");
+//! // ... render, then filesize and hash the frames
+//!
+//! let uploader = Uploader::new("http://localhost:5000/frames")
+//!                         .with_concurrency(4)
+//!                         .with_retries(5, Duration::from_millis(500));
+//! let report = task.command.upload_frames(&uploader).expect("Couldn't upload");
+//! println!("Uploaded {}, failed {}", report.uploaded(), report.failed());
+//! ```
+
+use ::*;
+use reqwest::{header::USER_AGENT, multipart};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use command::BlenderCommand;
+
+
+/// The User-Agent used for uploads
+pub const UPLOAD_USER_AGENT: &str = "bender-worker";
+
+
+
+
+// ===========================================================================
+//                                UploadItem
+// ===========================================================================
+
+/// A single file that has to be uploaded. The output is the name of the \
+/// frame output (None if the frame consists of a single file)
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadItem{
+    pub framenumber: usize,
+    pub output: Option<String>,
+    pub path: PathBuf,
+    pub filesize: usize,
+    pub hash: String
+}
+
+impl fmt::Display for UploadItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.output{
+            Some(ref output) => write!(f, "Frame {} ({})", self.framenumber, output),
+            None => write!(f, "Frame {}", self.framenumber)
+        }
+    }
+}
+
+
+
+
+// ===========================================================================
+//                               UploadResult
+// ===========================================================================
+
+/// Describes how the upload of a UploadItem ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum UploadOutcome{
+    /// The server confirmed the upload with the status code
+    Uploaded(u16),
+    /// The server rejected the upload with the status code. This is either \
+    /// a status code that is not retried, or a retryable one (5xx, 408 or \
+    /// 429) that was still returned after all retries
+    Rejected(u16),
+    /// The upload failed (after all retries) with the error message
+    Failed(String)
+}
+
+/// The result of the upload of a single UploadItem
+#[derive(Debug, Clone, PartialEq)]
+pub struct UploadResult{
+    pub item: UploadItem,
+    pub outcome: UploadOutcome,
+    pub attempts: usize
+}
+
+impl UploadResult{
+    /// Return true if the server confirmed the upload
+    pub fn is_success(&self) -> bool{
+        matches!(self.outcome, UploadOutcome::Uploaded(_))
+    }
+}
+
+
+/// The results of a upload run. Skipped counts the files that had already \
+/// been uploaded before
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct UploadReport{
+    pub results: Vec<UploadResult>,
+    pub skipped: usize
+}
+
+impl UploadReport{
+    /// Return the number of confirmed uploads
+    pub fn uploaded(&self) -> usize{
+        self.results.iter().filter(|result| result.is_success()).count()
+    }
+
+    /// Return the number of uploads that were rejected or failed
+    pub fn failed(&self) -> usize{
+        self.results.len() - self.uploaded()
+    }
+
+    /// Return true if all uploads have been confirmed
+    pub fn is_success(&self) -> bool{
+        self.failed() == 0
+    }
+
+    /// Return the results of all uploads that were rejected or failed
+    pub fn failures(&self) -> Vec<&UploadResult>{
+        self.results.iter().filter(|result| !result.is_success()).collect()
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                 Uploader
+// ===========================================================================
+
+/// The Uploader posts frames to the given url as multipart forms with the \
+/// fields `frame`, `filesize`, `filehash`, `file` (and `output` for frames \
+/// with several outputs). See the [ingest](../ingest/index.html) module for \
+/// the receiving side.
+///
+/// At most `concurrency` uploads run at the same time. Connection errors, \
+/// timeouts and server errors (5xx, 408 and 429) are retried up to `retries` \
+/// times. The n-th retry waits `backoff * 2^(n-1)`, but never longer than \
+/// `max_backoff`.
+#[derive(Debug, Clone)]
+pub struct Uploader{
+    pub url: String,
+    pub concurrency: usize,
+    pub retries: usize,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    pub timeout: Option<Duration>
+}
+
+impl Uploader{
+    /// Create a new Uploader for the url with 2 parallel uploads, 3 retries \
+    /// and a initial backoff of 1 second
+    pub fn new<S>(url: S) -> Self where S: Into<String>{
+        Uploader{
+            url: url.into(),
+            concurrency: 2,
+            retries: 3,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            timeout: Some(Duration::from_secs(300))
+        }
+    }
+
+    /// Run at most `concurrency` uploads at the same time (at least one)
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self{
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Retry failed uploads `retries` times, starting with the given backoff
+    pub fn with_retries(mut self, retries: usize, backoff: Duration) -> Self{
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Never wait longer than `max_backoff` between two attempts
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self{
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Abort a single attempt after the timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self{
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Return the time to wait before the given retry (starting at 1)
+    pub fn backoff_for(&self, retry: usize) -> Duration{
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1) as u32);
+        self.backoff.checked_mul(factor)
+                    .unwrap_or(self.max_backoff)
+                    .min(self.max_backoff)
+    }
+
+    /// Upload all frames of the BlenderCommand that haven't been uploaded \
+    /// yet and mark the confirmed ones as uploaded. Return Error if the url \
+    /// is invalid or a frame that has to be uploaded has no filesize or hash
+    pub fn upload(&self, command: &mut BlenderCommand) -> GenResult<UploadReport>{
+        let (items, skipped) = command.pending_uploads()?;
+        let results = self.upload_items(items)?;
+        for result in results.iter().filter(|result| result.is_success()){
+            command.confirm_upload(&result.item)?;
+        }
+        Ok(UploadReport{ results, skipped })
+    }
+
+    /// Upload the items in parallel and return the results in the order of \
+    /// the items. Return Error if the url is invalid
+    pub fn upload_items(&self, items: Vec<UploadItem>) -> GenResult<Vec<UploadResult>>{
+        let url = reqwest::Url::parse(self.url.as_str())?;
+        let mut builder = reqwest::Client::builder();
+        if let Some(timeout) = self.timeout{
+            builder = builder.timeout(timeout);
+        }
+        let client = builder.build()?;
+
+        let count = items.len();
+        let queue = Arc::new(Mutex::new(items.into_iter().enumerate().collect::<VecDeque<_>>()));
+        let (sender, receiver) = mpsc::channel();
+        let workers: Vec<thread::JoinHandle<()>> = (0..self.concurrency.max(1).min(count))
+            .map(|_|{
+                let queue = queue.clone();
+                let sender = sender.clone();
+                let client = client.clone();
+                let url = url.clone();
+                let uploader = self.clone();
+                thread::spawn(move ||{
+                    loop{
+                        let next = queue.lock().unwrap().pop_front();
+                        match next{
+                            Some((index, item)) => {
+                                let result = uploader.upload_with_retries(&client, &url, item);
+                                if sender.send((index, result)).is_err(){ break; }
+                            },
+                            None => break
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        let mut results: Vec<(usize, UploadResult)> = receiver.iter().collect();
+        for worker in workers{
+            if worker.join().is_err(){
+                return Err(From::from("Error: A upload thread panicked"));
+            }
+        }
+        results.sort_by_key(|(index, _)| *index);
+        Ok(results.into_iter().map(|(_, result)| result).collect())
+    }
+
+    /// Upload a single item, retrying as configured
+    fn upload_with_retries(&self, client: &reqwest::Client, url: &reqwest::Url, item: UploadItem) -> UploadResult{
+        let mut attempts = 0;
+        loop{
+            attempts += 1;
+            let (outcome, retry) = match post(client, url, &item){
+                Ok(status) if status.is_success() => (UploadOutcome::Uploaded(status.as_u16()), false),
+                Ok(status) => (UploadOutcome::Rejected(status.as_u16()), is_retryable(status)),
+                Err(Attempt::Request(err)) => (UploadOutcome::Failed(err), true),
+                Err(Attempt::File(err)) => (UploadOutcome::Failed(err), false)
+            };
+            if !retry || attempts > self.retries{
+                return UploadResult{ item, outcome, attempts };
+            }
+            thread::sleep(self.backoff_for(attempts));
+        }
+    }
+}
+
+
+/// Why a single attempt failed: reading the file errors are not retried, \
+/// request errors are
+enum Attempt{
+    File(String),
+    Request(String)
+}
+
+
+/// Post a single item and return the status code of the response
+fn post(client: &reqwest::Client, url: &reqwest::Url, item: &UploadItem) -> Result<reqwest::StatusCode, Attempt>{
+    let mut form = multipart::Form::new();
+    if let Some(ref output) = item.output{
+        form = form.text("output", output.clone());
+    }
+    let form = form.text("frame", item.framenumber.to_string())
+                   .text("filesize", item.filesize.to_string())
+                   .text("filehash", item.hash.clone())
+                   .file("file", &item.path)
+                   .map_err(|err| Attempt::File(format!("Error: Couldn't read {}: {}", item.path.to_string_lossy(), err)))?;
+
+    let response = client.post(url.clone())
+                         .header(USER_AGENT, UPLOAD_USER_AGENT)
+                         .multipart(form)
+                         .send()
+                         .map_err(|err| Attempt::Request(format!("Error: Couldn't upload {}: {}", item, err)))?;
+    Ok(response.status())
+}
+
+
+/// Return true if a response with this status is worth retrying
+pub(crate) fn is_retryable(status: reqwest::StatusCode) -> bool{
+    status.is_server_error() || status.as_u16() == 408 || status.as_u16() == 429
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::HttpStub;
+    use common::tempfile::TempDir;
+
+    fn rendered(dir: &TempDir, start: usize, end: usize) -> BlenderCommand{
+        let mut c = BlenderCommand::new_range(start, end, 1, "PNG");
+        c.construct("shot.blend".to_string(), dir.path().to_string_lossy().to_string());
+        for path in c.renderpaths(){
+            fs::write(path, verify::tests::png(4, 4)).unwrap();
+        }
+        c.get_frame_filesizes().unwrap();
+        c.get_frame_hashes().unwrap();
+        c
+    }
+
+    fn fast(url: String) -> Uploader{
+        Uploader::new(url).with_retries(3, Duration::from_millis(1))
+    }
+
+    #[test]
+    fn backoff() {
+        let u = Uploader::new("http://localhost").with_retries(10, Duration::from_millis(100))
+                                                 .with_max_backoff(Duration::from_millis(500));
+        assert_eq!(u.backoff_for(1), Duration::from_millis(100));
+        assert_eq!(u.backoff_for(3), Duration::from_millis(400));
+        assert_eq!(u.backoff_for(4), Duration::from_millis(500));
+        assert_eq!(u.backoff_for(80), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn upload_all() {
+        let dir = TempDir::new().unwrap();
+        let mut c = rendered(&dir, 1, 5);
+        let stub = HttpStub::start(vec![]);
+        let report = fast(stub.url("/frames")).with_concurrency(3).upload(&mut c).unwrap();
+        assert_eq!(report.uploaded(), 5);
+        assert!(report.is_success());
+        assert!(c.frame.all_uploaded());
+
+        let requests = stub.requests();
+        assert_eq!(requests.len(), 5);
+        assert_eq!(requests[0].path, "/frames");
+        assert_eq!(requests[0].header("user-agent"), Some("bender-worker".to_string()));
+        assert!(requests[0].body_string().contains("name=\"filehash\""));
+        assert!(requests.iter().any(|r| r.body_string().contains("name=\"frame\"\r\n\r\n3\r\n")));
+        assert!(!requests[0].body_string().contains("name=\"output\""));
+
+        // Everything is uploaded already
+        let report = fast(stub.url("/frames")).upload(&mut c).unwrap();
+        assert_eq!(report.skipped, 5);
+        assert!(report.results.is_empty());
+        assert_eq!(stub.requests().len(), 5);
+    }
+
+    #[test]
+    fn retries() {
+        let dir = TempDir::new().unwrap();
+        let mut c = rendered(&dir, 1, 1);
+        let stub = HttpStub::start(vec![503, 0, 201]);
+        let report = fast(stub.url("/")).upload(&mut c).unwrap();
+        assert_eq!(report.results[0].attempts, 3);
+        assert_eq!(report.results[0].outcome, UploadOutcome::Uploaded(201));
+        assert!(c.frame.is_uploaded(1));
+    }
+
+    #[test]
+    fn failures_are_not_marked() {
+        let dir = TempDir::new().unwrap();
+        let mut c = rendered(&dir, 1, 2);
+        // Frame 1 is rejected, frame 2 fails after all retries
+        let stub = HttpStub::start(vec![400, 500, 500, 500, 500]);
+        let report = fast(stub.url("/")).with_concurrency(1).upload(&mut c).unwrap();
+        assert_eq!(report.results[0].outcome, UploadOutcome::Rejected(400));
+        assert_eq!(report.results[0].attempts, 1);
+        assert_eq!(report.results[1].outcome, UploadOutcome::Rejected(500));
+        assert_eq!(report.results[1].attempts, 4);
+        assert_eq!(report.failed(), 2);
+        assert!(!c.frame.any_uploaded());
+
+        // Resume: only the missing frames are uploaded
+        c.set_uploaded(1).unwrap();
+        let report = fast(stub.url("/")).upload(&mut c).unwrap();
+        assert_eq!((report.skipped, report.uploaded()), (1, 1));
+        assert!(c.frame.all_uploaded());
+    }
+
+    #[test]
+    fn missing_hash() {
+        let dir = TempDir::new().unwrap();
+        let mut c = BlenderCommand::new_single(1, "PNG");
+        c.construct("shot.blend".to_string(), dir.path().to_string_lossy().to_string());
+        assert!(fast("http://127.0.0.1:1/".to_string()).upload(&mut c).is_err());
+    }
+
+    #[test]
+    fn outputs() {
+        let dir = TempDir::new().unwrap();
+        let mut c = BlenderCommand::new_single(1, "PNG").with_outputs(vec![OutputSpec::view("_L"), OutputSpec::view("_R")]);
+        c.construct("shot.blend".to_string(), dir.path().to_string_lossy().to_string());
+        for path in c.renderpaths(){
+            fs::write(path, verify::tests::png(4, 4)).unwrap();
+        }
+        c.get_frame_filesizes().unwrap();
+        c.get_frame_hashes().unwrap();
+
+        let stub = HttpStub::start(vec![200, 400]);
+        let report = fast(stub.url("/")).with_concurrency(1).upload(&mut c).unwrap();
+        assert_eq!(report.uploaded(), 1);
+        assert!(c.frame[&1].get_output("render_L").unwrap().is_uploaded());
+        assert!(!c.frame.is_uploaded(1));
+        assert!(stub.requests()[0].body_string().contains("render_L"));
+    }
+}
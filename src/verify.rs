@@ -0,0 +1,714 @@
+//! The verify module checks rendered frames before they are hashed and \
+//! uploaded. A crashed or killed blender may leave truncated or broken files \
+//! behind, which would otherwise be accepted just because they exist.
+//!
+//! The format of a file is detected by its magic bytes (PNG, JPEG, OpenEXR, \
+//! TIFF, BMP, HDR) or by a plausible header (TARGA, which has no magic bytes). \
+//! The header is decoded to get the dimensions of the image and the structure \
+//! of the file is walked as far as possible without decoding pixels, so that \
+//! truncated files are detected.
+//!
+//! ```
+//! # extern crate bender_job;
+//! # use bender_job::verify::{self, VerifyError};
+//! let truncated = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR";
+//! match verify::inspect(&truncated[..]){
+//!     Err(VerifyError::Truncated(_)) => println!("Frame is truncated"),
+//!     _ => panic!("should have been truncated")
+//! }
+//! ```
+
+use ::*;
+use std::io::Read;
+use std::path::Path;
+use data::Resolution;
+
+
+
+
+// ===========================================================================
+//                                 ImageKind
+// ===========================================================================
+
+/// The kinds of image files the verification understands
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageKind{
+    Png,
+    Jpeg,
+    OpenExr,
+    Tiff,
+    Bmp,
+    Targa,
+    Hdr
+}
+
+impl fmt::Display for ImageKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self{
+            ImageKind::Png => "PNG",
+            ImageKind::Jpeg => "JPEG",
+            ImageKind::OpenExr => "OpenEXR",
+            ImageKind::Tiff => "TIFF",
+            ImageKind::Bmp => "BMP",
+            ImageKind::Targa => "TARGA",
+            ImageKind::Hdr => "HDR"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+
+
+
+// ===========================================================================
+//                            ImageInfo & VerifyError
+// ===========================================================================
+
+/// The information gathered from a verified image file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageInfo{
+    pub kind: ImageKind,
+    pub width: usize,
+    pub height: usize
+}
+
+/// The reasons why a image file didn't pass verification
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyError{
+    /// The file couldn't be read
+    Io(String),
+    /// The file is none of the supported formats
+    UnknownFormat,
+    /// The file ends before its structure is complete
+    Truncated(ImageKind),
+    /// The file has the magic bytes of a format, but a broken structure
+    Malformed(ImageKind, String),
+    /// The image has different dimensions than the scene (width, height)
+    WrongDimensions{ expected: (usize, usize), found: (usize, usize) }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self{
+            VerifyError::Io(message) => write!(f, "Couldn't read the file: {}", message),
+            VerifyError::UnknownFormat => write!(f, "The file is no supported image format"),
+            VerifyError::Truncated(kind) => write!(f, "The {} file is truncated", kind),
+            VerifyError::Malformed(kind, message) => write!(f, "The {} file is malformed: {}", kind, message),
+            VerifyError::WrongDimensions{ expected, found } =>
+                write!(f, "The image is {}x{} pixels, but {}x{} were expected", found.0, found.1, expected.0, expected.1)
+        }
+    }
+}
+
+impl Error for VerifyError {}
+
+
+
+
+// ===========================================================================
+//                                Verification
+// ===========================================================================
+
+/// Read the file at path and verify it. If a Resolution is given (and not \
+/// the default Resolution), the dimensions of the image are compared against \
+/// its `scaled_x()` and `scaled_y()`
+pub fn verify_file<P>(path: P, expected: Option<&Resolution>) -> Result<ImageInfo, VerifyError> where P: AsRef<Path>{
+    let path = path.as_ref();
+    let mut bytes = Vec::new();
+    fs::File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|err| VerifyError::Io(format!("{}: {}", path.to_string_lossy(), err)))?;
+    verify_bytes(&bytes, expected)
+}
+
+/// Verify the bytes of a image file. See `verify_file()`
+pub fn verify_bytes(bytes: &[u8], expected: Option<&Resolution>) -> Result<ImageInfo, VerifyError>{
+    let info = inspect(bytes)?;
+    if let Some(resolution) = expected{
+        if !resolution.is_default(){
+            let expected = (resolution.scaled_x(), resolution.scaled_y());
+            let found = (info.width, info.height);
+            if expected != found{
+                return Err(VerifyError::WrongDimensions{ expected, found });
+            }
+        }
+    }
+    Ok(info)
+}
+
+/// Detect the format of the bytes, decode the dimensions and check the \
+/// structure of the file
+pub fn inspect(bytes: &[u8]) -> Result<ImageInfo, VerifyError>{
+    let kind = detect(bytes).ok_or(VerifyError::UnknownFormat)?;
+    let (width, height) = match kind{
+        ImageKind::Png => png(bytes)?,
+        ImageKind::Jpeg => jpeg(bytes)?,
+        ImageKind::OpenExr => exr(bytes)?,
+        ImageKind::Tiff => tiff(bytes)?,
+        ImageKind::Bmp => bmp(bytes)?,
+        ImageKind::Targa => targa(bytes)?,
+        ImageKind::Hdr => hdr(bytes)?
+    };
+    if width == 0 || height == 0{
+        return Err(VerifyError::Malformed(kind, format!("invalid dimensions {}x{}", width, height)));
+    }
+    Ok(ImageInfo{ kind, width, height })
+}
+
+/// Detect the kind of image by its magic bytes
+pub fn detect(bytes: &[u8]) -> Option<ImageKind>{
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n"){
+        Some(ImageKind::Png)
+    }else if bytes.starts_with(&[0xFF, 0xD8]){
+        Some(ImageKind::Jpeg)
+    }else if bytes.starts_with(&[0x76, 0x2F, 0x31, 0x01]){
+        Some(ImageKind::OpenExr)
+    }else if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*"){
+        Some(ImageKind::Tiff)
+    }else if bytes.starts_with(b"BM"){
+        Some(ImageKind::Bmp)
+    }else if bytes.starts_with(b"#?"){
+        Some(ImageKind::Hdr)
+    }else if is_targa_header(bytes){
+        Some(ImageKind::Targa)
+    }else{
+        None
+    }
+}
+
+
+
+// ---------------------------- byte helpers ---------------------------------
+
+fn be_u16(b: &[u8], pos: usize) -> Option<usize>{
+    b.get(pos..pos+2).map(|v| (v[0] as usize) << 8 | v[1] as usize)
+}
+
+fn be_u32(b: &[u8], pos: usize) -> Option<usize>{
+    b.get(pos..pos+4).map(|v| (v[0] as usize) << 24 | (v[1] as usize) << 16 | (v[2] as usize) << 8 | v[3] as usize)
+}
+
+fn le_u16(b: &[u8], pos: usize) -> Option<usize>{
+    b.get(pos..pos+2).map(|v| (v[1] as usize) << 8 | v[0] as usize)
+}
+
+fn le_u32(b: &[u8], pos: usize) -> Option<usize>{
+    b.get(pos..pos+4).map(|v| (v[3] as usize) << 24 | (v[2] as usize) << 16 | (v[1] as usize) << 8 | v[0] as usize)
+}
+
+fn le_i32(b: &[u8], pos: usize) -> Option<i64>{
+    le_u32(b, pos).map(|v| i64::from(v as u32 as i32))
+}
+
+fn le_u64(b: &[u8], pos: usize) -> Option<u64>{
+    b.get(pos..pos+8).map(|v| v.iter().rev().fold(0u64, |acc, byte| acc << 8 | u64::from(*byte)))
+}
+
+
+
+// ------------------------------- formats -----------------------------------
+
+/// Walk all PNG chunks up to the IEND chunk
+fn png(b: &[u8]) -> Result<(usize, usize), VerifyError>{
+    let kind = ImageKind::Png;
+    if b.len() < 24 { return Err(VerifyError::Truncated(kind)); }
+    if &b[12..16] != b"IHDR"{
+        return Err(VerifyError::Malformed(kind, "the first chunk is not IHDR".to_string()));
+    }
+    let width = be_u32(b, 16).unwrap();
+    let height = be_u32(b, 20).unwrap();
+
+    let mut pos = 8;
+    loop{
+        let length = be_u32(b, pos).ok_or(VerifyError::Truncated(kind))?;
+        let chunk_type = b.get(pos+4..pos+8).ok_or(VerifyError::Truncated(kind))?;
+        let end = pos + 12 + length;
+        if end > b.len(){ return Err(VerifyError::Truncated(kind)); }
+        if chunk_type == b"IEND"{ return Ok((width, height)); }
+        pos = end;
+    }
+}
+
+/// Walk the JPEG segments (skipping entropy coded data) up to the EOI marker
+fn jpeg(b: &[u8]) -> Result<(usize, usize), VerifyError>{
+    let kind = ImageKind::Jpeg;
+    let mut dimensions = None;
+    let mut pos = 2;
+    loop{
+        match b.get(pos){
+            None => return Err(VerifyError::Truncated(kind)),
+            Some(0xFF) => (),
+            Some(_) => return Err(VerifyError::Malformed(kind, format!("expected a marker at byte {}", pos)))
+        }
+        // Skip fill bytes
+        while b.get(pos) == Some(&0xFF){ pos += 1; }
+        let marker = *b.get(pos).ok_or(VerifyError::Truncated(kind))?;
+        pos += 1;
+
+        match marker{
+            0xD9 => {
+                return dimensions.ok_or_else(|| VerifyError::Malformed(kind, "no frame header (SOF) found".to_string()));
+            },
+            0x01 | 0xD0..=0xD7 => (),
+            _ => {
+                let length = be_u16(b, pos).ok_or(VerifyError::Truncated(kind))?;
+                if length < 2 {
+                    return Err(VerifyError::Malformed(kind, format!("invalid segment length at byte {}", pos)));
+                }
+                let is_sof = match marker { 0xC0..=0xCF => marker != 0xC4 && marker != 0xC8 && marker != 0xCC, _ => false };
+                if is_sof{
+                    let height = be_u16(b, pos+3).ok_or(VerifyError::Truncated(kind))?;
+                    let width = be_u16(b, pos+5).ok_or(VerifyError::Truncated(kind))?;
+                    dimensions = Some((width, height));
+                }
+                pos += length;
+                if pos > b.len(){ return Err(VerifyError::Truncated(kind)); }
+
+                // After the start of scan the entropy coded data follows. It
+                // ends at the first marker that is no stuffed byte or restart
+                if marker == 0xDA{
+                    loop{
+                        match (b.get(pos), b.get(pos+1)){
+                            (Some(0xFF), Some(next)) if *next != 0x00 && !(0xD0..=0xD7).contains(next) => break,
+                            (Some(_), Some(_)) => pos += 1,
+                            _ => return Err(VerifyError::Truncated(kind))
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Read the OpenEXR header and check that every chunk of the offset table \
+/// lies within the file (for single part scanline images)
+fn exr(b: &[u8]) -> Result<(usize, usize), VerifyError>{
+    let kind = ImageKind::OpenExr;
+    let version = le_u32(b, 4).ok_or(VerifyError::Truncated(kind))?;
+    let tiled = version & 0x200 != 0;
+    let multipart = version & 0x1000 != 0;
+
+    let mut pos = 8;
+    let mut data_window = None;
+    let mut compression = 0;
+    loop{
+        let name = read_cstring(b, &mut pos).ok_or(VerifyError::Truncated(kind))?;
+        if name.is_empty(){ break; }
+        let _attribute_type = read_cstring(b, &mut pos).ok_or(VerifyError::Truncated(kind))?;
+        let size = le_u32(b, pos).ok_or(VerifyError::Truncated(kind))?;
+        pos += 4;
+        let value = b.get(pos..pos+size).ok_or(VerifyError::Truncated(kind))?;
+        match name{
+            b"dataWindow" if size == 16 => {
+                data_window = Some((le_i32(value, 0).unwrap(), le_i32(value, 4).unwrap(),
+                                    le_i32(value, 8).unwrap(), le_i32(value, 12).unwrap()));
+            },
+            b"compression" if size == 1 => compression = value[0],
+            _ => ()
+        }
+        pos += size;
+    }
+
+    let (xmin, ymin, xmax, ymax) = data_window.ok_or_else(|| VerifyError::Malformed(kind, "no dataWindow attribute".to_string()))?;
+    if xmax < xmin || ymax < ymin{
+        return Err(VerifyError::Malformed(kind, "invalid dataWindow".to_string()));
+    }
+    let width = (xmax - xmin + 1) as usize;
+    let height = (ymax - ymin + 1) as usize;
+
+    // The chunk layout of tiled and multipart files is not checked
+    if tiled || multipart { return Ok((width, height)); }
+
+    let lines_per_chunk = match compression{
+        0..=2 => 1,
+        3 | 5 => 16,
+        4 | 6 | 7 | 8 => 32,
+        9 => 256,
+        _ => return Err(VerifyError::Malformed(kind, format!("unknown compression {}", compression)))
+    };
+    let chunks = height.div_ceil(lines_per_chunk);
+    for i in 0..chunks{
+        let offset = le_u64(b, pos + i * 8).ok_or(VerifyError::Truncated(kind))? as usize;
+        if offset == 0 || offset >= b.len(){ return Err(VerifyError::Truncated(kind)); }
+        let size = le_u32(b, offset + 4).ok_or(VerifyError::Truncated(kind))?;
+        if offset + 8 + size > b.len(){ return Err(VerifyError::Truncated(kind)); }
+    }
+    Ok((width, height))
+}
+
+/// Read a null terminated string and advance pos behind the terminator
+fn read_cstring<'a>(b: &'a [u8], pos: &mut usize) -> Option<&'a [u8]>{
+    let rest = b.get(*pos..)?;
+    let end = rest.iter().position(|byte| *byte == 0)?;
+    *pos += end + 1;
+    Some(&rest[..end])
+}
+
+/// Read the first TIFF IFD and check that all strips (or tiles) lie within \
+/// the file
+fn tiff(b: &[u8]) -> Result<(usize, usize), VerifyError>{
+    let kind = ImageKind::Tiff;
+    let little = b.starts_with(b"II");
+    let u16_at = |pos: usize| if little { le_u16(b, pos) } else { be_u16(b, pos) };
+    let u32_at = |pos: usize| if little { le_u32(b, pos) } else { be_u32(b, pos) };
+
+    let ifd = u32_at(4).ok_or(VerifyError::Truncated(kind))?;
+    let count = u16_at(ifd).ok_or(VerifyError::Truncated(kind))?;
+
+    let mut width = None;
+    let mut height = None;
+    let mut offsets = Vec::new();
+    let mut counts = Vec::new();
+    for i in 0..count{
+        let entry = ifd + 2 + i * 12;
+        let tag = u16_at(entry).ok_or(VerifyError::Truncated(kind))?;
+        let field_type = u16_at(entry + 2).ok_or(VerifyError::Truncated(kind))?;
+        let n = u32_at(entry + 4).ok_or(VerifyError::Truncated(kind))?;
+        let size = match field_type { 3 => 2, 4 => 4, _ => continue };
+        let data = if n * size <= 4 { entry + 8 } else { u32_at(entry + 8).ok_or(VerifyError::Truncated(kind))? };
+        let values: Option<Vec<usize>> = (0..n).map(|j| if size == 2 { u16_at(data + j * 2) } else { u32_at(data + j * 4) })
+                                               .collect();
+        let values = values.ok_or(VerifyError::Truncated(kind))?;
+        match tag{
+            256 => width = values.first().cloned(),
+            257 => height = values.first().cloned(),
+            273 | 324 => offsets = values,
+            279 | 325 => counts = values,
+            _ => ()
+        }
+    }
+
+    if offsets.len() != counts.len(){
+        return Err(VerifyError::Malformed(kind, "the number of strip offsets and byte counts differ".to_string()));
+    }
+    if offsets.iter().zip(counts.iter()).any(|(offset, count)| offset + count > b.len()){
+        return Err(VerifyError::Truncated(kind));
+    }
+    match (width, height){
+        (Some(width), Some(height)) => Ok((width, height)),
+        _ => Err(VerifyError::Malformed(kind, "no ImageWidth or ImageLength tag".to_string()))
+    }
+}
+
+/// Read the BMP header and check the file size and uncompressed pixel data
+fn bmp(b: &[u8]) -> Result<(usize, usize), VerifyError>{
+    let kind = ImageKind::Bmp;
+    let filesize = le_u32(b, 2).ok_or(VerifyError::Truncated(kind))?;
+    let data_offset = le_u32(b, 10).ok_or(VerifyError::Truncated(kind))?;
+    let header_size = le_u32(b, 14).ok_or(VerifyError::Truncated(kind))?;
+    if filesize > b.len() || data_offset > b.len(){ return Err(VerifyError::Truncated(kind)); }
+
+    let (width, height, bpp, compression) = if header_size == 12{
+        (le_u16(b, 18).map(|w| w as i64), le_u16(b, 20).map(|h| h as i64), le_u16(b, 24), Some(0))
+    }else{
+        (le_i32(b, 18), le_i32(b, 22), le_u16(b, 28), le_u32(b, 30))
+    };
+    let width = width.ok_or(VerifyError::Truncated(kind))?.unsigned_abs() as usize;
+    let height = height.ok_or(VerifyError::Truncated(kind))?.unsigned_abs() as usize;
+    let bpp = bpp.ok_or(VerifyError::Truncated(kind))?;
+
+    // Uncompressed rows are padded to 4 bytes
+    if compression == Some(0){
+        let end = width.checked_mul(bpp)
+                       .map(|bits| bits.div_ceil(32) * 4)
+                       .and_then(|row| row.checked_mul(height))
+                       .and_then(|size| size.checked_add(data_offset))
+                       .ok_or_else(|| VerifyError::Malformed(kind, format!("{}x{} pixels overflow the pixel data size", width, height)))?;
+        if end > b.len(){ return Err(VerifyError::Truncated(kind)); }
+    }
+    Ok((width, height))
+}
+
+/// Return true if the bytes start with a plausible TARGA header
+fn is_targa_header(b: &[u8]) -> bool{
+    b.len() >= 18
+    && b[1] <= 1
+    && [1, 2, 3, 9, 10, 11].contains(&b[2])
+    && [8, 15, 16, 24, 32].contains(&b[16])
+}
+
+/// Read the TARGA header and check the (run length encoded) pixel data
+fn targa(b: &[u8]) -> Result<(usize, usize), VerifyError>{
+    let kind = ImageKind::Targa;
+    let colormap_length = le_u16(b, 5).unwrap();
+    let colormap_bits = b[7] as usize;
+    let width = le_u16(b, 12).unwrap();
+    let height = le_u16(b, 14).unwrap();
+    let pixel_bytes = (b[16] as usize).div_ceil(8);
+
+    let mut pos = 18 + b[0] as usize + colormap_length * colormap_bits.div_ceil(8);
+    let pixels = width * height;
+    if b[2] < 9{
+        pos += pixels * pixel_bytes;
+    }else{
+        let mut decoded = 0;
+        while decoded < pixels{
+            let header = *b.get(pos).ok_or(VerifyError::Truncated(kind))? as usize;
+            let count = (header & 0x7F) + 1;
+            pos += 1 + if header & 0x80 != 0 { pixel_bytes } else { count * pixel_bytes };
+            decoded += count;
+        }
+    }
+    if pos > b.len(){ return Err(VerifyError::Truncated(kind)); }
+    Ok((width, height))
+}
+
+/// Read the Radiance HDR header and walk the scanlines
+fn hdr(b: &[u8]) -> Result<(usize, usize), VerifyError>{
+    let kind = ImageKind::Hdr;
+    let header_end = b.windows(2)
+                      .position(|w| w == b"\n\n")
+                      .ok_or(VerifyError::Truncated(kind))? + 2;
+    let line_end = b[header_end..].iter()
+                                  .position(|byte| *byte == b'\n')
+                                  .ok_or(VerifyError::Truncated(kind))? + header_end;
+    let resolution = String::from_utf8_lossy(&b[header_end..line_end]).to_string();
+    let tokens: Vec<&str> = resolution.split_whitespace().collect();
+    let parsed = match tokens.as_slice(){
+        [a, first, c, second] => {
+            match (first.parse::<usize>(), second.parse::<usize>()){
+                (Ok(first), Ok(second)) if a.ends_with('Y') && c.ends_with('X') => Some((second, first)),
+                (Ok(first), Ok(second)) if a.ends_with('X') && c.ends_with('Y') => Some((first, second)),
+                _ => None
+            }
+        },
+        _ => None
+    };
+    let (width, height) = parsed.ok_or_else(|| VerifyError::Malformed(kind, format!("invalid resolution line \"{}\"", resolution)))?;
+    if width == 0 || height == 0{
+        return Err(VerifyError::Malformed(kind, format!("empty resolution \"{}\"", resolution)));
+    }
+    let scanline = width.checked_mul(4)
+                        .ok_or_else(|| VerifyError::Malformed(kind, format!("scanline width {} overflows", width)))?;
+
+    let mut pos = line_end + 1;
+    for _ in 0..height{
+        let adaptive_rle = (8..=0x7FFF).contains(&width)
+                           && b.get(pos) == Some(&2) && b.get(pos+1) == Some(&2)
+                           && b.get(pos+2).filter(|byte| *byte & 0x80 == 0).is_some();
+        if adaptive_rle{
+            pos += 4;
+            for _ in 0..4{
+                let mut x = 0;
+                while x < width{
+                    let count = *b.get(pos).ok_or(VerifyError::Truncated(kind))? as usize;
+                    match count{
+                        0 => return Err(VerifyError::Malformed(kind, "zero length run".to_string())),
+                        1..=128 => { x += count; pos += 1 + count; },
+                        _ => { x += count - 128; pos += 2; }
+                    }
+                }
+            }
+        }else{
+            pos = pos.checked_add(scanline).ok_or(VerifyError::Truncated(kind))?;
+        }
+        if pos > b.len(){ return Err(VerifyError::Truncated(kind)); }
+    }
+    Ok((width, height))
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn resolution(x: usize, y: usize) -> Resolution{
+        Resolution{ x, y, scale: 100 }
+    }
+
+    pub fn png(width: u32, height: u32) -> Vec<u8>{
+        let mut b = b"\x89PNG\r\n\x1a\n".to_vec();
+        b.extend(&[0, 0, 0, 13]);
+        b.extend(b"IHDR");
+        b.extend(&width.to_be_bytes());
+        b.extend(&height.to_be_bytes());
+        b.extend(&[8, 2, 0, 0, 0, 0, 0, 0, 0]);
+        b.extend(&[0, 0, 0, 2]);
+        b.extend(b"IDAT\x78\x9c\0\0\0\0");
+        b.extend(&[0, 0, 0, 0]);
+        b.extend(b"IEND\xae\x42\x60\x82");
+        b
+    }
+
+    fn jpeg(width: u16, height: u16) -> Vec<u8>{
+        let mut b = vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 16];
+        b.extend(b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+        b.extend(&[0xFF, 0xC0, 0, 11, 8]);
+        b.extend(&height.to_be_bytes());
+        b.extend(&width.to_be_bytes());
+        b.extend(&[1, 1, 0x11, 0]);
+        b.extend(&[0xFF, 0xDA, 0, 8, 1, 1, 0, 0, 63, 0]);
+        b.extend(&[0x12, 0xFF, 0x00, 0x34, 0xFF, 0xD0, 0x56]);
+        b.extend(&[0xFF, 0xD9]);
+        b
+    }
+
+    fn exr(width: i32, height: i32) -> Vec<u8>{
+        let mut b = vec![0x76, 0x2F, 0x31, 0x01, 2, 0, 0, 0];
+        b.extend(b"compression\0compression\0");
+        b.extend(&1u32.to_le_bytes());
+        b.push(0);
+        b.extend(b"dataWindow\0box2i\0");
+        b.extend(&16u32.to_le_bytes());
+        for v in &[0, 0, width - 1, height - 1]{ b.extend(&v.to_le_bytes()); }
+        b.push(0);
+        let table = b.len();
+        let first_chunk = (table + height as usize * 8) as u64;
+        for y in 0..height as u64{ b.extend(&(first_chunk + y * 12).to_le_bytes()); }
+        for y in 0..height{
+            b.extend(&y.to_le_bytes());
+            b.extend(&4u32.to_le_bytes());
+            b.extend(&[0, 0, 0, 0]);
+        }
+        b
+    }
+
+    fn tiff(width: u16, height: u16) -> Vec<u8>{
+        let data_size = width as u32 * height as u32 * 3;
+        let mut b = b"II*\0".to_vec();
+        b.extend(&8u32.to_le_bytes());
+        b.extend(&4u16.to_le_bytes());
+        let entries: [(u16, u16, u32); 4] = [(256, 3, width as u32), (257, 3, height as u32), (273, 4, 62), (279, 4, data_size)];
+        for (tag, field_type, value) in entries.iter(){
+            b.extend(&tag.to_le_bytes());
+            b.extend(&field_type.to_le_bytes());
+            b.extend(&1u32.to_le_bytes());
+            b.extend(&value.to_le_bytes());
+        }
+        b.extend(&0u32.to_le_bytes());
+        b.extend(vec![0; data_size as usize]);
+        b
+    }
+
+    fn bmp(width: i32, height: i32) -> Vec<u8>{
+        let row = ((width * 24 + 31) / 32 * 4) as usize;
+        let size = 54 + row * height as usize;
+        let mut b = b"BM".to_vec();
+        b.extend(&(size as u32).to_le_bytes());
+        b.extend(&[0, 0, 0, 0]);
+        b.extend(&54u32.to_le_bytes());
+        b.extend(&40u32.to_le_bytes());
+        b.extend(&width.to_le_bytes());
+        b.extend(&(-height).to_le_bytes());
+        b.extend(&1u16.to_le_bytes());
+        b.extend(&24u16.to_le_bytes());
+        b.extend(vec![0; 24]);
+        b.extend(vec![0; row * height as usize]);
+        b
+    }
+
+    fn targa(width: u16, height: u16, rle: bool) -> Vec<u8>{
+        let mut b = vec![0, 0, if rle { 10 } else { 2 }, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        b.extend(&width.to_le_bytes());
+        b.extend(&height.to_le_bytes());
+        b.extend(&[24, 0]);
+        if rle{
+            // One run per row
+            for _ in 0..height{
+                b.push(0x80 | (width as u8 - 1));
+                b.extend(&[1, 2, 3]);
+            }
+        }else{
+            b.extend(vec![0; width as usize * height as usize * 3]);
+        }
+        b
+    }
+
+    fn hdr(width: usize, height: usize) -> Vec<u8>{
+        let mut b = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width).into_bytes();
+        for _ in 0..height{
+            if width >= 8{
+                b.extend(&[2, 2, (width >> 8) as u8, width as u8]);
+                for _ in 0..4{ b.extend(&[128 + width as u8, 7]); }
+            }else{
+                b.extend(vec![1; width * 4]);
+            }
+        }
+        b
+    }
+
+    fn check(bytes: Vec<u8>, kind: ImageKind, width: usize, height: usize){
+        assert_eq!(inspect(&bytes), Ok(ImageInfo{ kind, width, height }));
+        assert!(verify_bytes(&bytes, Some(&resolution(width, height))).is_ok());
+        for cut in &[1, bytes.len() / 2]{
+            match inspect(&bytes[..bytes.len() - cut]){
+                Err(VerifyError::Truncated(k)) => assert_eq!(k, kind),
+                other => panic!("{} cut by {} bytes was {:?}", kind, cut, other)
+            }
+        }
+    }
+
+    #[test]
+    fn valid_and_truncated() {
+        check(png(64, 32), ImageKind::Png, 64, 32);
+        check(jpeg(64, 32), ImageKind::Jpeg, 64, 32);
+        check(exr(64, 32), ImageKind::OpenExr, 64, 32);
+        check(tiff(64, 32), ImageKind::Tiff, 64, 32);
+        check(bmp(64, 32), ImageKind::Bmp, 64, 32);
+        check(targa(64, 32, false), ImageKind::Targa, 64, 32);
+        check(targa(64, 32, true), ImageKind::Targa, 64, 32);
+        check(hdr(64, 32), ImageKind::Hdr, 64, 32);
+        check(hdr(4, 32), ImageKind::Hdr, 4, 32);
+    }
+
+    #[test]
+    fn wrong_dimensions() {
+        let r = Resolution{ x: 128, y: 64, scale: 50 };
+        assert!(verify_bytes(&png(64, 32), Some(&r)).is_ok());
+        assert_eq!(verify_bytes(&png(128, 64), Some(&r)),
+                   Err(VerifyError::WrongDimensions{ expected: (64, 32), found: (128, 64) }));
+        // A default (unscanned) Resolution is not compared
+        assert!(verify_bytes(&png(128, 64), Some(&Resolution::default())).is_ok());
+    }
+
+    #[test]
+    fn unknown_and_malformed() {
+        assert_eq!(inspect(b"hello world"), Err(VerifyError::UnknownFormat));
+        assert_eq!(inspect(b""), Err(VerifyError::UnknownFormat));
+        let mut broken = png(8, 8);
+        broken[12..16].copy_from_slice(b"IDAT");
+        match inspect(&broken){
+            Err(VerifyError::Malformed(ImageKind::Png, _)) => (),
+            other => panic!("{:?}", other)
+        }
+        assert!(inspect(&png(0, 8)).is_err());
+    }
+
+    #[test]
+    fn hostile_headers() {
+        let malformed = |bytes: &[u8], expected: ImageKind| match inspect(bytes){
+            Err(VerifyError::Malformed(kind, _)) => assert_eq!(kind, expected),
+            other => panic!("{:?}", other)
+        };
+        // These must neither hang nor overflow
+        malformed(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 18446744073709551615 +X 0\n", ImageKind::Hdr);
+        malformed(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 0 +X 64\n", ImageKind::Hdr);
+        malformed(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 18446744073709551615\n", ImageKind::Hdr);
+        match inspect(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 18446744073709551615 +X 4611686018427387903\n"){
+            Err(VerifyError::Truncated(ImageKind::Hdr)) => (),
+            other => panic!("{:?}", other)
+        }
+
+        let mut huge = bmp(64, 32);
+        huge[18..22].copy_from_slice(&i32::MAX.to_le_bytes());
+        huge[22..26].copy_from_slice(&i32::MIN.to_le_bytes());
+        huge[28..30].copy_from_slice(&u16::MAX.to_le_bytes());
+        malformed(&huge, ImageKind::Bmp);
+    }
+
+    #[test]
+    fn missing_file() {
+        match verify_file("/this/file/does/not/exist.png", None){
+            Err(VerifyError::Io(_)) => (),
+            other => panic!("{:?}", other)
+        }
+    }
+}
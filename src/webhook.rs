@@ -0,0 +1,671 @@
+//! The webhook module posts the events of a Job as signed JSON to the \
+//! webhook urls the owner of the Job registered in `Job::webhooks`. Events \
+//! are Status transitions (see [observer](../observer/index.html)) and \
+//! finished frames.
+//!
+//! Each request carries these headers:
+//! - `X-Bender-Event`: `status` or `frame`
+//! - `X-Bender-Delivery`: the unique id of the delivery
+//! - `X-Bender-Timestamp`: the unix time the payload was signed at
+//! - `X-Bender-Signature`: `blake2b=<hex>`, the HMAC-BLAKE2b of \
+//!   `<timestamp>.<body>` with the secret of the Job (only if a secret is set)
+//!
+//! Webhooks only reach public hosts: urls pointing at loopback, private, \
+//! link-local (e.g. cloud metadata endpoints) or otherwise internal addresses \
+//! are rejected when they are added and, after resolving the host, again \
+//! before each delivery (see `WebhookSender::with_internal_targets()`). \
+//! Redirects are not followed.
+//!
+//! Connection errors, timeouts and server errors are retried like uploads \
+//! (see [Uploader](../upload/struct.Uploader.html)). Every delivery is \
+//! recorded in the delivery log at `Job::data["webhook.log"]` (the latest \
+//! `LOG_LIMIT` deliveries as JSON):
+//!
+//! ```no_run
+//! # extern crate bender_job;
+//! # use bender_job::Job;
+//! # use bender_job::webhook::{WebhookSender, WebhookEvent};
+//! let mut job = Job::new("/data/blendfiles/5873c0033e78b222bec2cb2a221487cf/shot.blend", "dh@atoav.com", true);
+//! job.webhooks.add("https://pipeline.example.com/hooks/bender").unwrap();
+//! job.webhooks.secret = Some("s3cr3t".to_string());
+//! let changes = job.subscribe();
+//!
+//! job.set_validate();
+//! let sender = WebhookSender::new();
+//! for change in changes.try_iter(){
+//!     sender.deliver(&mut job, &WebhookEvent::from(change));
+//! }
+//! println!("{:?}", job.webhook_log());
+//! ```
+
+use ::*;
+use blake2::{Blake2b, Digest};
+use reqwest::header::{CONTENT_TYPE, USER_AGENT};
+use std::thread;
+use std::time::Duration;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use observer::StatusChange;
+use upload::is_retryable;
+
+
+/// The User-Agent used for webhook requests
+pub const WEBHOOK_USER_AGENT: &str = "bender-webhook";
+
+/// The key of the delivery log in `Job::data`
+pub const LOG_KEY: &str = "webhook.log";
+
+/// The number of deliveries kept in the delivery log
+pub const LOG_LIMIT: usize = 50;
+
+/// The block size of BLAKE2b in bytes (used for the HMAC)
+const BLAKE2B_BLOCK_SIZE: usize = 128;
+
+/// Counter that makes delivery ids unique within the process
+static DELIVERY_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+
+
+
+// ===========================================================================
+//                                 Webhooks
+// ===========================================================================
+
+/// The webhook urls of a Job and the secret its payloads are signed with. \
+/// The secret is redacted in the Debug output
+#[derive(Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct Webhooks{
+    #[serde(default)]
+    pub urls: Vec<String>,
+    #[serde(default)]
+    pub secret: Option<String>
+}
+
+impl Webhooks{
+    /// Add a url (ignored if it is known already). Return Error if it isn't \
+    /// a valid http(s) url or points at a internal host (see `is_internal()`)
+    pub fn add<S>(&mut self, url: S) -> GenResult<()> where S: Into<String>{
+        let url = url.into();
+        let parsed = reqwest::Url::parse(url.as_str())?;
+        if parsed.scheme() != "http" && parsed.scheme() != "https"{
+            return Err(From::from(format!("Error: Webhook url {} is not a http(s) url", url)));
+        }
+        let host = parsed.host_str().unwrap_or("").trim_start_matches('[').trim_end_matches(']').to_lowercase();
+        let internal = match host.parse::<IpAddr>(){
+            Ok(ip) => is_internal(&ip),
+            Err(_) => {
+                let domain = host.trim_end_matches('.');
+                domain.is_empty() || domain == "localhost" || domain.ends_with(".localhost")
+            }
+        };
+        if internal{
+            return Err(From::from(format!("Error: Webhook url {} points at a internal host", url)));
+        }
+        if !self.urls.contains(&url){
+            self.urls.push(url);
+        }
+        Ok(())
+    }
+
+    /// Remove a url, return true if it was known
+    pub fn remove(&mut self, url: &str) -> bool{
+        let count = self.urls.len();
+        self.urls.retain(|known| known != url);
+        count != self.urls.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.urls.is_empty()
+    }
+
+    /// Add the urls of other and take its secret if self has none
+    pub fn merge(&mut self, other: &Self){
+        for url in other.urls.iter(){
+            if !self.urls.contains(url){
+                self.urls.push(url.clone());
+            }
+        }
+        if self.secret.is_none(){
+            self.secret = other.secret.clone();
+        }
+    }
+}
+
+impl fmt::Debug for Webhooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Webhooks")
+         .field("urls", &self.urls)
+         .field("secret", &self.secret.as_ref().map(|_| "<redacted>"))
+         .finish()
+    }
+}
+
+
+
+
+// ===========================================================================
+//                               WebhookEvent
+// ===========================================================================
+
+/// Something that happened to a Job
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum WebhookEvent{
+    Status(StatusChange),
+    Frame{ frame: usize, time: DateTime<Utc> }
+}
+
+impl WebhookEvent{
+    /// A frame finished just now
+    pub fn frame(frame: usize) -> Self{
+        WebhookEvent::Frame{ frame, time: Utc::now() }
+    }
+
+    /// The name used in the `X-Bender-Event` header and the payload
+    pub fn name(&self) -> &'static str{
+        match self{
+            WebhookEvent::Status(_) => "status",
+            WebhookEvent::Frame{..} => "frame"
+        }
+    }
+}
+
+impl From<StatusChange> for WebhookEvent{
+    fn from(change: StatusChange) -> Self{
+        WebhookEvent::Status(change)
+    }
+}
+
+
+/// The JSON body of a webhook request
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WebhookPayload{
+    pub delivery: String,
+    pub event: String,
+    pub job: String,
+    pub time: DateTime<Utc>,
+    pub status: String,
+    pub previous: Option<String>,
+    pub reason: Option<String>,
+    pub frame: Option<usize>,
+    pub frames_done: usize,
+    pub frames_total: usize
+}
+
+impl WebhookPayload{
+    /// Create the payload of an event of the Job
+    pub fn new(job: &Job, event: &WebhookEvent, delivery: String) -> Self{
+        let progress = job.progress();
+        let mut payload = WebhookPayload{
+            delivery,
+            event: event.name().to_string(),
+            job: job.id.clone(),
+            time: Utc::now(),
+            status: job.status.to_string(),
+            previous: None,
+            reason: None,
+            frame: None,
+            frames_done: progress.frames_done,
+            frames_total: progress.frames_total
+        };
+        match event{
+            WebhookEvent::Status(change) => {
+                payload.time = change.time;
+                payload.status = change.to.to_string();
+                payload.previous = Some(change.from.to_string());
+                payload.reason = Some(change.reason.clone());
+            },
+            WebhookEvent::Frame{ frame, time } => {
+                payload.time = *time;
+                payload.frame = Some(*frame);
+            }
+        }
+        payload
+    }
+}
+
+
+
+
+// ===========================================================================
+//                                Signatures
+// ===========================================================================
+
+/// Return the HMAC-BLAKE2b of the message as hex string
+pub fn hmac_blake2b(secret: &[u8], message: &[u8]) -> String{
+    let mut key = [0u8; BLAKE2B_BLOCK_SIZE];
+    if secret.len() > BLAKE2B_BLOCK_SIZE{
+        let hashed = Blake2b::digest(secret);
+        key[..hashed.len()].copy_from_slice(&hashed);
+    }else{
+        key[..secret.len()].copy_from_slice(secret);
+    }
+    let inner_pad: Vec<u8> = key.iter().map(|byte| byte ^ 0x36).collect();
+    let outer_pad: Vec<u8> = key.iter().map(|byte| byte ^ 0x5c).collect();
+
+    let mut inner = Blake2b::new();
+    inner.input(&inner_pad);
+    inner.input(message);
+    let mut outer = Blake2b::new();
+    outer.input(&outer_pad);
+    outer.input(inner.result());
+    format!("{:x}", outer.result())
+}
+
+
+/// Return the value of the `X-Bender-Signature` header for a body
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String{
+    format!("blake2b={}", hmac_blake2b(secret.as_bytes(), format!("{}.{}", timestamp, body).as_bytes()))
+}
+
+
+/// Return true if the signature header matches the body (for receivers)
+pub fn verify(secret: &str, timestamp: i64, body: &str, signature: &str) -> bool{
+    let expected = sign(secret, timestamp, body);
+    // Compare in constant time
+    expected.len() == signature.len() &&
+    expected.bytes().zip(signature.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+
+
+
+// ===========================================================================
+//                                Deliveries
+// ===========================================================================
+
+/// The result of delivering an event to a single url
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeliveryRecord{
+    pub delivery: String,
+    pub url: String,
+    pub event: String,
+    pub time: DateTime<Utc>,
+    pub attempts: usize,
+    /// The status code of the last response
+    pub status: Option<u16>,
+    pub error: Option<String>
+}
+
+impl DeliveryRecord{
+    /// Return true if the receiver answered with a success status
+    pub fn is_success(&self) -> bool{
+        match self.status{
+            Some(status) => (200..300).contains(&status),
+            None => false
+        }
+    }
+}
+
+impl fmt::Display for DeliveryRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.status, &self.error){
+            (_, Some(error)) => write!(f, "{} {} to {} failed after {} attempts: {}", self.delivery, self.event, self.url, self.attempts, error),
+            (Some(status), None) => write!(f, "{} {} to {}: {} after {} attempts", self.delivery, self.event, self.url, status, self.attempts),
+            (None, None) => write!(f, "{} {} to {}", self.delivery, self.event, self.url)
+        }
+    }
+}
+
+
+/// Posts webhook events of Jobs. Connection errors, timeouts and server \
+/// errors (5xx, 408 and 429) are retried up to `retries` times with an \
+/// exponential backoff. Internal hosts are refused unless `allow_internal` \
+/// is set
+#[derive(Debug, Clone)]
+pub struct WebhookSender{
+    pub retries: usize,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    pub timeout: Option<Duration>,
+    pub allow_internal: bool
+}
+
+impl Default for WebhookSender{
+    fn default() -> Self{
+        Self::new()
+    }
+}
+
+impl WebhookSender{
+    /// Create a WebhookSender with 3 retries, a initial backoff of 1 second \
+    /// and a timeout of 10 seconds
+    pub fn new() -> Self{
+        WebhookSender{
+            retries: 3,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            timeout: Some(Duration::from_secs(10)),
+            allow_internal: false
+        }
+    }
+
+    /// Retry failed deliveries `retries` times, starting with the given backoff
+    pub fn with_retries(mut self, retries: usize, backoff: Duration) -> Self{
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Never wait longer than `max_backoff` between two attempts
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self{
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// Abort a single attempt after the timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self{
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Deliver to urls that resolve to internal hosts as well (e.g. for a \
+    /// pipeline running on the same machine)
+    pub fn with_internal_targets(mut self, allow_internal: bool) -> Self{
+        self.allow_internal = allow_internal;
+        self
+    }
+
+    /// Return the time to wait before the given retry (starting at 1)
+    pub fn backoff_for(&self, retry: usize) -> Duration{
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1) as u32);
+        self.backoff.checked_mul(factor)
+                    .unwrap_or(self.max_backoff)
+                    .min(self.max_backoff)
+    }
+
+    /// Post the event to every webhook url of the Job, record the deliveries \
+    /// in the delivery log of the Job and return them
+    pub fn deliver(&self, job: &mut Job, event: &WebhookEvent) -> Vec<DeliveryRecord>{
+        if job.webhooks.is_empty(){
+            return Vec::new();
+        }
+        let delivery = format!("{}-{}-{}", job.id, Utc::now().timestamp_millis(), DELIVERY_COUNTER.fetch_add(1, Ordering::SeqCst));
+        let payload = WebhookPayload::new(job, event, delivery.clone());
+        let records: Vec<DeliveryRecord> = match serde_json::to_string(&payload){
+            Ok(body) => job.webhooks.urls.iter().map(|url| self.post_with_retries(url, &job.webhooks.secret, &payload, &body)).collect(),
+            Err(err) => job.webhooks.urls.iter().map(|url| DeliveryRecord{
+                delivery: delivery.clone(),
+                url: url.clone(),
+                event: payload.event.clone(),
+                time: Utc::now(),
+                attempts: 0,
+                status: None,
+                error: Some(format!("Error: Couldn't serialize payload: {}", err))
+            }).collect()
+        };
+        job.record_deliveries(&records);
+        records
+    }
+
+    /// Post a payload to a single url, retrying as configured
+    fn post_with_retries(&self, url: &str, secret: &Option<String>, payload: &WebhookPayload, body: &str) -> DeliveryRecord{
+        let mut record = DeliveryRecord{
+            delivery: payload.delivery.clone(),
+            url: url.to_string(),
+            event: payload.event.clone(),
+            time: Utc::now(),
+            attempts: 0,
+            status: None,
+            error: None
+        };
+        loop{
+            record.attempts += 1;
+            let retry = match self.post(url, secret, payload, body){
+                Ok(status) => {
+                    record.status = Some(status.as_u16());
+                    record.error = None;
+                    !status.is_success() && is_retryable(status)
+                },
+                Err(err) => {
+                    record.error = Some(err);
+                    true
+                }
+            };
+            if !retry || record.attempts > self.retries{
+                record.time = Utc::now();
+                return record;
+            }
+            thread::sleep(self.backoff_for(record.attempts));
+        }
+    }
+
+    /// Post a payload once and return the status code of the response
+    fn post(&self, url: &str, secret: &Option<String>, payload: &WebhookPayload, body: &str) -> Result<reqwest::StatusCode, String>{
+        if !self.allow_internal{
+            check_target(url)?;
+        }
+        let mut builder = reqwest::Client::builder().redirect(reqwest::RedirectPolicy::none());
+        if let Some(timeout) = self.timeout{
+            builder = builder.timeout(timeout);
+        }
+        let client = builder.build().map_err(|err| format!("Error: Couldn't create client: {}", err))?;
+        let timestamp = Utc::now().timestamp();
+        let mut request = client.post(url)
+                                .header(USER_AGENT, WEBHOOK_USER_AGENT)
+                                .header(CONTENT_TYPE, "application/json")
+                                .header("X-Bender-Event", payload.event.as_str())
+                                .header("X-Bender-Delivery", payload.delivery.as_str())
+                                .header("X-Bender-Timestamp", timestamp.to_string());
+        if let Some(secret) = secret{
+            request = request.header("X-Bender-Signature", sign(secret, timestamp, body));
+        }
+        let response = request.body(body.to_string())
+                              .send()
+                              .map_err(|err| format!("Error: Couldn't deliver to {}: {}", url, err))?;
+        Ok(response.status())
+    }
+}
+
+
+/// Return true if the address can't be reached from the internet or belongs \
+/// to the host itself: loopback, private, link-local, shared (100.64.0.0/10), \
+/// unspecified and broadcast addresses, IPv6 unique local addresses and \
+/// IPv4 addresses mapped into IPv6
+pub fn is_internal(ip: &IpAddr) -> bool{
+    match ip{
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_unspecified()
+                || ip.is_broadcast() || octets[0] == 0 || (octets[0] == 100 && octets[1] & 0xc0 == 64)
+        },
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            ip.is_loopback() || ip.is_unspecified()
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80
+                || ip.to_ipv4().filter(|ip| is_internal(&IpAddr::V4(*ip))).is_some()
+        }
+    }
+}
+
+
+/// Resolve the host of the url and return Error if any of its addresses is \
+/// internal
+fn check_target(url: &str) -> Result<(), String>{
+    let parsed = reqwest::Url::parse(url).map_err(|err| format!("Error: Invalid webhook url {}: {}", url, err))?;
+    let host = parsed.host_str().ok_or_else(|| format!("Error: Webhook url {} has no host", url))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = parsed.port_or_known_default().unwrap_or(80);
+    let addresses = (host, port).to_socket_addrs()
+                                .map_err(|err| format!("Error: Couldn't resolve {}: {}", host, err))?;
+    match addresses.map(|address| address.ip()).find(is_internal){
+        Some(ip) => Err(format!("Error: Refused to deliver to {}, it resolves to the internal address {}", url, ip)),
+        None => Ok(())
+    }
+}
+
+
+impl Job{
+    /// Return the delivery log of the Job (oldest first)
+    pub fn webhook_log(&self) -> Vec<DeliveryRecord>{
+        self.data.get(LOG_KEY)
+                 .and_then(|log| serde_json::from_str(log).ok())
+                 .unwrap_or_default()
+    }
+
+    /// Append deliveries to the delivery log, keeping the latest `LOG_LIMIT`
+    fn record_deliveries(&mut self, records: &[DeliveryRecord]){
+        let mut log = self.webhook_log();
+        log.extend_from_slice(records);
+        let excess = log.len().saturating_sub(LOG_LIMIT);
+        log.drain(..excess);
+        if let Ok(log) = serde_json::to_string(&log){
+            self.add_data(LOG_KEY.to_string(), log);
+        }
+        for record in records.iter().filter(|record| !record.is_success()){
+            self.add_history(format!("Error: Webhook delivery {}", record).as_str());
+        }
+    }
+}
+
+
+
+
+
+
+// ===========================================================================
+//                                UNIT TESTS
+// ===========================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{HttpStub, JobBuilder};
+
+    fn job(urls: &[String]) -> Job{
+        let mut job = JobBuilder::new().build();
+        // The stub listens on localhost, which Webhooks::add refuses
+        job.webhooks.urls = urls.to_vec();
+        job.webhooks.secret = Some("s3cr3t".to_string());
+        job
+    }
+
+    fn fast() -> WebhookSender{
+        WebhookSender::new().with_retries(2, Duration::from_millis(1))
+                            .with_internal_targets(true)
+    }
+
+    #[test]
+    fn urls() {
+        let mut webhooks = Webhooks::default();
+        assert!(webhooks.add("ftp://example.com").is_err());
+        assert!(webhooks.add("not a url").is_err());
+        webhooks.add("https://example.com/hook").unwrap();
+        webhooks.add("https://example.com/hook").unwrap();
+        assert_eq!(webhooks.urls.len(), 1);
+        assert!(webhooks.remove("https://example.com/hook"));
+        assert!(webhooks.is_empty());
+    }
+
+    #[test]
+    fn internal_targets() {
+        let mut webhooks = Webhooks::default();
+        for url in ["http://localhost:8080/hook", "http://api.LOCALHOST./", "http://127.0.0.1/", "http://10.1.2.3/",
+                    "http://172.16.0.1/", "http://192.168.1.1/", "http://169.254.169.254/latest/meta-data/",
+                    "http://100.64.0.1/", "http://0.0.0.0/", "http://[::1]/", "http://[fe80::1]/", "http://[fd00::1]/",
+                    "http://[::ffff:169.254.169.254]/"].iter(){
+            assert!(webhooks.add(*url).is_err(), "{}", url);
+        }
+        assert!(webhooks.is_empty());
+        webhooks.add("http://93.184.216.34/hook").unwrap();
+        webhooks.add("http://[2606:2800:220:1::]/hook").unwrap();
+        assert!(!is_internal(&"8.8.8.8".parse().unwrap()));
+
+        // Hosts are checked again after resolving them
+        let stub = HttpStub::start(vec![]);
+        let mut job = job(&[stub.url("/hook").replace("127.0.0.1", "localhost")]);
+        let records = WebhookSender::new().with_retries(0, Duration::from_millis(1)).deliver(&mut job, &WebhookEvent::frame(1));
+        assert!(records[0].error.as_ref().unwrap().contains("internal address"));
+        assert!(stub.requests().is_empty());
+    }
+
+    #[test]
+    fn no_redirects() {
+        let stub = HttpStub::start(vec![302]);
+        let mut job = job(&[stub.url("/hook")]);
+        let records = fast().deliver(&mut job, &WebhookEvent::frame(1));
+        assert_eq!((records[0].attempts, records[0].status), (1, Some(302)));
+        assert!(!records[0].is_success());
+        assert_eq!(stub.requests().len(), 1);
+    }
+
+    #[test]
+    fn secret_redacted() {
+        let mut webhooks = Webhooks::default();
+        webhooks.add("https://example.com/hook").unwrap();
+        webhooks.secret = Some("s3cr3t".to_string());
+        let debug = format!("{:?}", webhooks);
+        assert!(debug.contains("https://example.com/hook"));
+        assert!(debug.contains("<redacted>"));
+        assert!(!debug.contains("s3cr3t"));
+        assert!(!format!("{:?}", Webhooks::default()).contains("<redacted>"));
+    }
+
+    #[test]
+    fn signatures() {
+        // Keys longer than the block size are hashed first
+        let long = hmac_blake2b(&[0xaa; 200], b"message");
+        assert_eq!(long.len(), 128);
+        assert_ne!(long, hmac_blake2b(&[0xaa; 199], b"message"));
+        let signature = sign("s3cr3t", 1700000000, "{}");
+        assert!(signature.starts_with("blake2b="));
+        assert!(verify("s3cr3t", 1700000000, "{}", &signature));
+        assert!(!verify("s3cr3t", 1700000001, "{}", &signature));
+        assert!(!verify("other", 1700000000, "{}", &signature));
+    }
+
+    #[test]
+    fn deliver_status() {
+        let stub = HttpStub::start(vec![]);
+        let mut job = job(&[stub.url("/hook")]);
+        let changes = job.subscribe();
+        job.set_validate();
+        let records = fast().deliver(&mut job, &WebhookEvent::from(changes.try_recv().unwrap()));
+        assert_eq!(records.len(), 1);
+        assert!(records[0].is_success());
+
+        let requests = stub.requests();
+        assert_eq!(requests.len(), 1);
+        let request = &requests[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.header("x-bender-event"), Some("status".to_string()));
+        let timestamp: i64 = request.header("x-bender-timestamp").unwrap().parse().unwrap();
+        assert!(verify("s3cr3t", timestamp, &request.body_string(), &request.header("x-bender-signature").unwrap()));
+        let payload: WebhookPayload = serde_json::from_str(&request.body_string()).unwrap();
+        assert_eq!(payload.job, "5873c0033e78b222bec2cb2a221487cf");
+        assert_eq!(payload.previous, Some("request.untouched".to_string()));
+        assert_eq!(payload.status, "request.checked");
+        assert_eq!(payload.delivery, records[0].delivery);
+        assert_eq!(job.webhook_log(), records);
+    }
+
+    #[test]
+    fn retries_and_log() {
+        let stub = HttpStub::start(vec![503, 0, 201, 400]);
+        let mut job = job(&[stub.url("/hook")]);
+        let records = fast().deliver(&mut job, &WebhookEvent::frame(12));
+        assert_eq!(records[0].attempts, 3);
+        assert_eq!(records[0].status, Some(201));
+        assert!(stub.requests()[2].body_string().contains("\"frame\":12"));
+
+        // Client errors are not retried and are logged in the history
+        let records = fast().deliver(&mut job, &WebhookEvent::frame(13));
+        assert_eq!((records[0].attempts, records[0].status), (1, Some(400)));
+        assert!(job.last_event_message().starts_with("Error: Webhook delivery"));
+        assert_eq!(job.webhook_log().len(), 2);
+
+        // The log survives serialization and is capped
+        let mut job = Job::deserialize_from_u8(&job.serialize_to_u8().unwrap()).unwrap();
+        let record = job.webhook_log()[0].clone();
+        job.record_deliveries(&vec![record; LOG_LIMIT]);
+        assert_eq!(job.webhook_log().len(), LOG_LIMIT);
+        assert_eq!(job.webhook_log()[0].status, Some(201));
+    }
+
+    #[test]
+    fn without_urls() {
+        let mut job = job(&[]);
+        assert!(fast().deliver(&mut job, &WebhookEvent::frame(1)).is_empty());
+        assert!(!job.data.contains_key(LOG_KEY));
+    }
+}
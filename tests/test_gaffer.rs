@@ -55,7 +55,10 @@ mod gaffer{
             cuda: false,
             device: "GPU".to_string(),
             image_format: "PNG".to_string(),
-            uses_compositing: true
+            uses_compositing: true,
+            scene: "Scene".to_string(),
+            camera: "Camera".to_string(),
+            outputs: Vec::new()
         };
 
         let resolution = Resolution{
@@ -82,7 +85,7 @@ mod gaffer{
         j.validate();
         assert_eq!(j.status.is_validated(), true);
         j.scan_and_optimize(true);
-        assert_eq!(j.history.iter().any(|(_, value)| value.starts_with("optimize_blend.py")), true);
+        assert_eq!(j.history.iter().any(|event| event.source == "optimize_blend.py"), true);
         tempdir.close().expect("Couldn't close tempdir");
     }
 
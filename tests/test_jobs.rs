@@ -23,7 +23,7 @@ mod job_functions{
         let mut j = common::get_job();
         j.add_history("Something very complex");
         // Get last element from history
-        let (_key, value) = j.history.iter().next_back().unwrap();
+        let value = j.history.texts().next_back().unwrap();
         assert_eq!(value, "Something very complex");
     }
 
@@ -37,10 +37,10 @@ mod job_functions{
         // Test if last element was actually added
         let len = j.history.iter().count();
         let msg = format!("There was no next_back value. history len is {}:\n{:#?}\n", len, j.history);
-        let (_key, value) = j.history.iter().next_back().expect(&msg);
+        let value = j.history.texts().next_back().expect(&msg);
         assert_eq!(value, "Something completely different");
         // Test if the element before the element is what we expect
-        let (_key, value) = j.history.iter().nth(j.history.len()-2).unwrap();
+        let value = j.history.texts().nth(j.history.len()-2).unwrap();
         assert_eq!(value, "Something very complex");
 
         // j.add_history_debounced("Something very complex");